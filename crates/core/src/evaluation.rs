@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Explains the value of a flag with the given settings for the context, the flag is on
+/// when every rule of an enabled flag matches. Only depends on serde so the same rules
+/// can be evaluated outside the server.
+pub fn evaluate(enabled: bool, rules: &[Rule], context: &Map<String, Value>) -> Reason {
+    if !enabled {
+        Reason::Disabled
+    } else if rules.is_empty() {
+        Reason::Default
+    } else if rules.iter().all(|rule| rule.check(context)) {
+        Reason::RuleMatch
+    } else {
+        Reason::RuleMismatch
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Rule {
+    pub parameter: String,
+    pub operator: Operator,
+}
+
+impl Rule {
+    pub fn check(&self, input: &Map<String, Value>) -> bool {
+        match input.get(&self.parameter) {
+            None => self.validate(&Value::Null),
+            Some(value) => self.validate(value),
+        }
+    }
+
+    fn validate(&self, value: &Value) -> bool {
+        match value {
+            Value::Null => self.validate_string(None),
+            Value::String(v) => self.validate_string(Some(v)),
+            Value::Array(values) => {
+                for v in values {
+                    if !self.validate(v) {
+                        return false;
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn validate_string(&self, value: Option<&str>) -> bool {
+        match &self.operator {
+            Operator::Is(v) => value == Some(v),
+            Operator::IsNot(v) => value.is_none() || value != Some(v),
+            Operator::Contains(v) => value.unwrap_or("").contains(v),
+            Operator::IsOneOf(v) => value.is_some() && v.contains(&value.unwrap().to_string()),
+            Operator::IsNotOneOf(v) => value.is_none() || !v.contains(&value.unwrap().to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum Operator {
+    Is(String),
+    IsNot(String),
+    Contains(String),
+    IsOneOf(Vec<String>),
+    IsNotOneOf(Vec<String>),
+}
+
+/// Value of one flag for a context
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Evaluation {
+    pub flag: String,
+    pub value: bool,
+    pub variant: Option<String>,
+    pub reason: Reason,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// The flag is disabled, globally or in the environment
+    Disabled,
+    /// The flag is enabled without rules
+    Default,
+    /// Every rule matched the context
+    RuleMatch,
+    /// At least one rule did not match the context
+    RuleMismatch,
+}
+
+impl Reason {
+    /// Value of the flag the reason was given for
+    pub fn is_on(&self) -> bool {
+        matches!(self, Reason::Default | Reason::RuleMatch)
+    }
+}
+
+#[cfg(test)]
+mod test_rules {
+    use super::*;
+
+    #[test]
+    fn test_rule_instance() {
+        let rule = Rule {
+            parameter: "tenant".to_string(),
+            operator: Operator::Is("tenant_1".to_string()),
+        };
+        assert_eq!(rule.parameter, "tenant");
+        assert_eq!(rule.operator, Operator::Is("tenant_1".to_string()))
+    }
+
+    #[test]
+    fn test_rule_is() {
+        let rule = Rule {
+            parameter: "tenant".to_string(),
+            operator: Operator::Is("tenant_1".to_string()),
+        };
+        let mut payload = Map::new();
+        payload.insert("tenant".to_string(), Value::String("tenant_1".to_string()));
+        assert!(rule.check(&payload));
+        payload.clear();
+        assert!(!rule.check(&payload));
+        payload.insert("tenant".to_string(), Value::String("tenant_2".to_string()));
+        assert!(!rule.check(&payload));
+    }
+
+    #[test]
+    fn test_rule_is_not() {
+        let rule = Rule {
+            parameter: "tenant".to_string(),
+            operator: Operator::IsNot("tenant_1".to_string()),
+        };
+        let mut payload = Map::new();
+        payload.insert("tenant".to_string(), Value::String("tenant_1".to_string()));
+        assert!(!rule.check(&payload));
+        payload.clear();
+        assert!(rule.check(&payload));
+        payload.insert("tenant".to_string(), Value::String("tenant_2".to_string()));
+        assert!(rule.check(&payload));
+    }
+
+    #[test]
+    fn test_rule_contains() {
+        let rule = Rule {
+            parameter: "tenant".to_string(),
+            operator: Operator::Contains("tenant".to_string()),
+        };
+        let mut payload = Map::new();
+        payload.insert("tenant".to_string(), Value::String("tenant_1".to_string()));
+        assert!(rule.check(&payload));
+        payload.clear();
+        assert!(!rule.check(&payload));
+        payload.insert("tenant".to_string(), Value::String("test".to_string()));
+        assert!(!rule.check(&payload));
+    }
+
+    #[test]
+    fn test_rule_is_one_of() {
+        let rule = Rule {
+            parameter: "tenant".to_string(),
+            operator: Operator::IsOneOf(Vec::from(["tenant_1".to_string()])),
+        };
+        let mut payload = Map::new();
+        payload.insert("tenant".to_string(), Value::String("tenant_1".to_string()));
+        assert!(rule.check(&payload));
+        payload.clear();
+        assert!(!rule.check(&payload));
+        payload.insert("tenant".to_string(), Value::String("test".to_string()));
+        assert!(!rule.check(&payload));
+    }
+
+    #[test]
+    fn test_rule_is_not_one_of() {
+        let rule = Rule {
+            parameter: "tenant".to_string(),
+            operator: Operator::IsNotOneOf(Vec::from(["tenant_1".to_string()])),
+        };
+        let mut payload = Map::new();
+        payload.insert("tenant".to_string(), Value::String("tenant_1".to_string()));
+        assert!(!rule.check(&payload));
+        payload.clear();
+        assert!(rule.check(&payload));
+        payload.insert("tenant".to_string(), Value::String("test".to_string()));
+        assert!(rule.check(&payload));
+    }
+
+    #[test]
+    fn test_rule_with_input_array() {
+        let rule = Rule {
+            parameter: "tenant".to_string(),
+            operator: Operator::Is("tenant_1".to_string()),
+        };
+        let mut payload = Map::new();
+        payload.insert(
+            "tenant".to_string(),
+            Value::Array(Vec::from([Value::String("tenant_1".to_string())])),
+        );
+        assert!(rule.check(&payload));
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let rules = vec![Rule { parameter: "tenant".to_string(), operator: Operator::Is("tenant_1".to_string()) }];
+        let context = Map::from_iter([("tenant".to_string(), Value::from("tenant_1"))]);
+        assert_eq!(evaluate(false, &rules, &context), Reason::Disabled);
+        assert_eq!(evaluate(true, &[], &context), Reason::Default);
+        assert_eq!(evaluate(true, &rules, &context), Reason::RuleMatch);
+        assert_eq!(evaluate(true, &rules, &Map::new()), Reason::RuleMismatch);
+        assert!(Reason::Default.is_on() && Reason::RuleMatch.is_on());
+        assert!(!Reason::Disabled.is_on() && !Reason::RuleMismatch.is_on());
+    }
+}
@@ -0,0 +1,8 @@
+pub mod evaluation;
+pub mod models;
+pub mod ruleset;
+pub mod utils;
+
+pub use evaluation::{Evaluation, Operator, Reason, Rule};
+pub use models::{Environment, FeatureFlag, FlagOverride};
+pub use ruleset::Ruleset;
@@ -0,0 +1,565 @@
+use bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::hash::Hash;
+use chrono::{DateTime, Utc};
+use crate::evaluation;
+pub use crate::evaluation::{Evaluation, Operator, Reason, Rule};
+use crate::utils;
+
+/// Project owning the data created before projects existed, and the one used by unscoped routes
+pub const DEFAULT_PROJECT: &str = "default";
+
+fn default_project() -> String {
+    DEFAULT_PROJECT.to_string()
+}
+
+/// Owner of flags and environments, names only need to be unique within a project
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    #[serde(
+        rename = "_id",
+        with = "utils::optional_object_id_format",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub id: Option<ObjectId>,
+    pub name: String,
+    #[serde(default)]
+    pub label: String,
+
+    #[serde(with = "utils::date_format")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "utils::date_format")]
+    pub updated_at: DateTime<Utc>
+}
+
+impl Project {
+    pub fn new(name: &str, label: &str) -> Self {
+        Project {
+            id: None,
+            name: name.to_string(),
+            label: label.to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct FeatureFlag {
+    #[serde(
+        rename = "_id",
+        with = "utils::optional_object_id_format",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub id: Option<ObjectId>,
+    /// Name of the owning project
+    #[serde(default = "default_project")]
+    pub project: String,
+    pub name: String,
+    pub label: String,
+    pub enabled: bool,
+    pub rules: Vec<Rule>,
+    /// Served instead of `true` to the contexts the flag is on for, only set by environment overrides
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Team or person to ask before changing or removing the flag
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Free-form references, such as tickets or dashboards
+    #[serde(default)]
+    pub links: Vec<String>,
+    #[serde(default)]
+    pub lifecycle: Lifecycle,
+    /// Date after which a temporary flag should be removed
+    #[serde(with = "utils::optional_date_format", default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Archived flags are kept for restoring but are not evaluated
+    #[serde(default)]
+    pub archived: bool,
+    /// Incremented on every update, used for optimistic concurrency control
+    #[serde(default)]
+    pub version: i64,
+
+    #[serde(with = "utils::date_format")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "utils::date_format")]
+    pub updated_at: DateTime<Utc>
+}
+
+impl FeatureFlag {
+    pub fn new(name: &str, label: &str, enabled: bool, rules: Vec<Rule>) -> Self {
+        FeatureFlag {
+            id: None,
+            project: default_project(),
+            name: name.to_string(),
+            label: label.to_string(),
+            enabled,
+            rules,
+            variant: None,
+            description: String::new(),
+            tags: vec![],
+            owner: None,
+            links: vec![],
+            lifecycle: Lifecycle::default(),
+            expires_at: None,
+            archived: false,
+            version: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn set_metadata(&mut self, metadata: FlagMetadata) {
+        self.description = metadata.description;
+        self.tags = metadata.tags;
+        self.owner = metadata.owner;
+        self.links = metadata.links;
+        self.expires_at = metadata.expires_at;
+        // The archived state is only changed by `set_archived`
+        if !self.archived {
+            self.lifecycle = metadata.lifecycle;
+        }
+    }
+
+    /// Archived flags are in the archived lifecycle state, restored ones become active again
+    pub fn set_archived(&mut self, archived: bool) {
+        if archived {
+            self.lifecycle = Lifecycle::Archived;
+        } else if self.lifecycle == Lifecycle::Archived {
+            self.lifecycle = Lifecycle::Active;
+        }
+        self.archived = archived;
+    }
+
+    pub fn metadata(&self) -> FlagMetadata {
+        FlagMetadata {
+            description: self.description.clone(),
+            tags: self.tags.clone(),
+            owner: self.owner.clone(),
+            links: self.links.clone(),
+            lifecycle: self.lifecycle,
+            expires_at: self.expires_at,
+        }
+    }
+
+    pub fn is_context_valid(&self, context: &Map<String, Value>) -> bool {
+        self.reason(context).is_on()
+    }
+
+    /// Explains the value returned by `is_context_valid`
+    pub fn reason(&self, context: &Map<String, Value>) -> Reason {
+        evaluation::evaluate(self.enabled, &self.rules, context)
+    }
+
+    /// The variant when the flag is on for the context and has one, otherwise whether it is on
+    pub fn value(&self, context: &Map<String, Value>) -> Value {
+        match (self.is_context_valid(context), &self.variant) {
+            (true, Some(variant)) => Value::String(variant.clone()),
+            (valid, _) => Value::Bool(valid),
+        }
+    }
+}
+
+/// Descriptive fields of a flag, they are not used for evaluation
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FlagMetadata {
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub links: Vec<String>,
+    #[serde(default)]
+    pub lifecycle: Lifecycle,
+    #[serde(with = "utils::optional_date_format", default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Metadata of an update, missing fields keep their stored value and `null` clears the optional ones
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FlagMetadataUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "utils::nullable", skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub links: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lifecycle: Option<Lifecycle>,
+    #[serde(with = "utils::nullable_date_format", default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+}
+
+impl FlagMetadataUpdate {
+    /// The given metadata with the fields of the update replaced
+    pub fn apply(self, metadata: FlagMetadata) -> FlagMetadata {
+        FlagMetadata {
+            description: self.description.unwrap_or(metadata.description),
+            tags: self.tags.unwrap_or(metadata.tags),
+            owner: self.owner.unwrap_or(metadata.owner),
+            links: self.links.unwrap_or(metadata.links),
+            lifecycle: self.lifecycle.unwrap_or(metadata.lifecycle),
+            expires_at: self.expires_at.unwrap_or(metadata.expires_at),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lifecycle {
+    /// Being set up, not used yet
+    Draft,
+    #[default]
+    Active,
+    /// Meant to stay, such as kill switches, never reported as stale
+    Permanent,
+    /// Being removed from the code using it
+    Deprecated,
+    /// Set when the flag is archived
+    Archived,
+}
+
+impl Lifecycle {
+    /// Temporary flags are expected to be removed once rolled out
+    pub fn is_temporary(&self) -> bool {
+        *self != Lifecycle::Permanent
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Environment {
+    #[serde(
+        rename = "_id",
+        with = "utils::optional_object_id_format",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub id: Option<ObjectId>,
+    /// Name of the owning project
+    #[serde(default = "default_project")]
+    pub project: String,
+    pub name: String,
+    /// Overrides of the global flags, one per flag name
+    #[serde(default)]
+    pub flags: Vec<FlagOverride>,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub version: i64,
+
+    #[serde(with = "utils::date_format")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "utils::date_format")]
+    pub updated_at: DateTime<Utc>
+}
+
+impl Environment {
+    pub fn new(name: &str) -> Self {
+        Environment {
+            id: None,
+            project: default_project(),
+            name: name.to_string(),
+            flags: vec![],
+            archived: false,
+            version: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Sets the override of a flag, replacing any previous one for the same flag
+    pub fn set_override(&mut self, flag_override: FlagOverride) {
+        match self.flags.iter_mut().find(|f| f.name == flag_override.name) {
+            Some(existing) => *existing = flag_override,
+            None => self.flags.push(flag_override),
+        }
+    }
+
+    pub fn remove_override(&mut self, flag_name: &str) {
+        self.flags.retain(|f| f.name != flag_name);
+    }
+
+    pub fn set_overrides(&mut self, flags: Vec<FlagOverride>) {
+        self.flags = vec![];
+        for flag_override in flags {
+            self.set_override(flag_override);
+        }
+    }
+
+    pub fn get_override(&self, flag_name: &str) -> Option<&FlagOverride> {
+        self.flags.iter().find(|f| f.name == flag_name)
+    }
+
+    /// Evaluates the global flags with the overrides of this environment, see `FeatureFlag::value`.
+    /// Flags disabled globally or by their override are evaluated as well, to `false`.
+    pub fn get_flags_from_context(&self, flags: &[FeatureFlag], context: &Map<String, Value>) -> Map<String, Value> {
+        let mut res = Map::new();
+        for flag in flags {
+            let value = match self.get_override(&flag.name) {
+                Some(flag_override) => flag_override.apply(flag).value(context),
+                None => flag.value(context),
+            };
+            res.insert(flag.name.clone(), value);
+        }
+        res
+    }
+
+    /// Evaluates one global flag with its override in this environment
+    pub fn evaluate(&self, flag: &FeatureFlag, context: &Map<String, Value>) -> Evaluation {
+        let flag = match self.get_override(&flag.name) {
+            Some(flag_override) => flag_override.apply(flag),
+            None => flag.clone(),
+        };
+        let value = flag.is_context_valid(context);
+        Evaluation {
+            value,
+            // Variants are only served to contexts the flag is on for
+            variant: flag.variant.clone().filter(|_| value),
+            reason: flag.reason(context),
+            flag: flag.name,
+        }
+    }
+}
+
+/// Environment specific settings of a global flag, unset fields fall back to the global flag
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct FlagOverride {
+    /// Name of the global flag
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<Vec<Rule>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+impl FlagOverride {
+    pub fn new(name: &str) -> Self {
+        FlagOverride {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// The global flag with this override applied
+    pub fn apply(&self, flag: &FeatureFlag) -> FeatureFlag {
+        let mut flag = flag.clone();
+        if let Some(enabled) = self.enabled {
+            flag.enabled = enabled;
+        }
+        if let Some(rules) = &self.rules {
+            flag.rules = rules.clone();
+        }
+        if let Some(variant) = &self.variant {
+            flag.variant = Some(variant.clone());
+        }
+        flag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feature_flag_instance() {
+        let flag = FeatureFlag::new(
+            "sample_flag",
+            "Sample Flag",
+            false,
+            vec![]
+        );
+        assert_eq!(flag.name, "sample_flag");
+    }
+
+    #[test]
+    fn test_environment_instance() {
+        let env = Environment::new("development");
+        assert_eq!(env.name, "development");
+        assert_eq!(env.flags.len(), 0);
+    }
+
+    #[test]
+    fn test_environment_set_override() {
+        let mut env = Environment::new("development");
+        let flag_override = FlagOverride::new("sample_flag");
+        env.set_override(flag_override.clone());
+        env.set_override(flag_override); // Should not add repeated flag
+        assert_eq!(env.flags.len(), 1);
+
+        env.remove_override("sample_flag");
+        assert_eq!(env.flags.len(), 0);
+    }
+
+    #[test]
+    fn test_metadata_update_keeps_missing_fields() {
+        let metadata = FlagMetadata {
+            tags: vec!["checkout".to_string()],
+            owner: Some("payments".to_string()),
+            expires_at: Some(Utc::now()),
+            ..Default::default()
+        };
+        let update: FlagMetadataUpdate = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(update.apply(metadata.clone()), metadata);
+
+        let update: FlagMetadataUpdate = serde_json::from_value(serde_json::json!({
+            "owner": null,
+            "expires_at": null,
+            "lifecycle": "permanent",
+        })).unwrap();
+        let updated = update.apply(metadata.clone());
+        assert_eq!((updated.owner, updated.expires_at, updated.lifecycle), (None, None, Lifecycle::Permanent));
+        assert_eq!(updated.tags, metadata.tags);
+    }
+
+    #[test]
+    fn test_legacy_flag_copy_is_read_as_override() {
+        let env: Environment = serde_json::from_value(serde_json::json!({
+            "name": "development",
+            "flags": [{
+                "name": "sample_flag",
+                "label": "Sample Flag",
+                "enabled": false,
+                "rules": [],
+                "created_at": "2022-11-20 10:00:00",
+                "updated_at": "2022-11-20 10:00:00",
+            }],
+            "created_at": "2022-11-20 10:00:00",
+            "updated_at": "2022-11-20 10:00:00",
+        })).unwrap();
+        let flag_override = env.get_override("sample_flag").unwrap();
+        assert_eq!(flag_override.enabled, Some(false));
+        assert_eq!(flag_override.rules, Some(vec![]));
+    }
+
+}
+
+#[cfg(test)]
+mod test_environment {
+    use super::*;
+    use crate::evaluation::Operator;
+
+    #[test]
+    fn test_environment_get_flags_from_context() {
+        let mut env = Environment::new("development");
+        let flag_1 = FeatureFlag::new(
+            "flag_1",
+            "Flag 1",
+            true,
+            vec![
+                Rule {
+                    parameter: "tenant".to_string(),
+                    operator: Operator::Is("tenant_1".to_string()),
+                },
+                Rule {
+                    parameter: "user".to_string(),
+                    operator: Operator::IsOneOf(vec![
+                        "user_1".to_string(),
+                        "user_2".to_string(),
+                    ]),
+                },
+            ]
+        );
+        let flag_2 = FeatureFlag::new(
+            "flag_2",
+            "Flag 2",
+            true,
+            vec![
+                Rule {
+                    parameter: "custom_prop".to_string(),
+                    operator: Operator::IsNot("custom".to_string()),
+                },
+            ]
+        );
+        let mut flag_3 = FeatureFlag::new("flag_3", "Flag 3", false, vec![]);
+        env.set_override(FlagOverride {
+            name: "flag_1".to_string(),
+            rules: Some(vec![]),
+            ..Default::default()
+        });
+        env.set_override(FlagOverride {
+            enabled: Some(false),
+            ..FlagOverride::new("flag_2")
+        });
+
+        let mut context = Map::new();
+        context.insert("tenant".to_string(), Value::String("tenant_2".to_string()));
+        let flags = env.get_flags_from_context(&[flag_1.clone(), flag_2.clone(), flag_3.clone()], &context);
+        assert_eq!(flags.len(), 3);
+        assert_eq!(flags.get("flag_1"), Some(&Value::Bool(true)));
+        // Disabled by the override or globally, both are evaluated to false
+        assert_eq!(flags.get("flag_2"), Some(&Value::Bool(false)));
+        assert_eq!(flags.get("flag_3"), Some(&Value::Bool(false)));
+
+        env.set_override(FlagOverride {
+            enabled: Some(true),
+            ..FlagOverride::new("flag_3")
+        });
+        flag_3.rules = vec![];
+        let flags = env.get_flags_from_context(&[flag_1.clone(), flag_2.clone(), flag_3.clone()], &context);
+        assert_eq!(flags.get("flag_3"), Some(&Value::Bool(true)));
+
+        // Variants replace `true` the same way `evaluate` returns them
+        env.set_override(FlagOverride {
+            rules: Some(vec![]),
+            variant: Some("treatment".to_string()),
+            ..FlagOverride::new("flag_1")
+        });
+        env.set_override(FlagOverride {
+            enabled: Some(false),
+            variant: Some("treatment".to_string()),
+            ..FlagOverride::new("flag_2")
+        });
+        let flags = env.get_flags_from_context(&[flag_1.clone(), flag_2.clone(), flag_3], &context);
+        assert_eq!(flags.get("flag_1"), Some(&Value::from("treatment")));
+        assert_eq!(flags.get("flag_2"), Some(&Value::Bool(false)));
+        assert_eq!(env.evaluate(&flag_1, &context).variant, Some("treatment".to_string()));
+        assert_eq!(env.evaluate(&flag_2, &context).variant, None);
+    }
+
+    #[test]
+    fn test_environment_evaluate() {
+        let mut env = Environment::new("development");
+        let rules = vec![Rule { parameter: "tenant".to_string(), operator: Operator::Is("tenant_1".to_string()) }];
+        let flag = FeatureFlag::new("flag_1", "Flag 1", true, rules);
+        let context = |tenant: &str| Map::from_iter([("tenant".to_string(), Value::from(tenant))]);
+
+        let evaluation = env.evaluate(&flag, &context("tenant_1"));
+        assert_eq!((evaluation.value, evaluation.reason), (true, Reason::RuleMatch));
+        let evaluation = env.evaluate(&flag, &context("tenant_2"));
+        assert_eq!((evaluation.value, evaluation.reason), (false, Reason::RuleMismatch));
+
+        env.set_override(FlagOverride {
+            rules: Some(vec![]),
+            variant: Some("treatment".to_string()),
+            ..FlagOverride::new("flag_1")
+        });
+        let evaluation = env.evaluate(&flag, &context("tenant_2"));
+        assert_eq!(evaluation, Evaluation {
+            flag: "flag_1".to_string(),
+            value: true,
+            variant: Some("treatment".to_string()),
+            reason: Reason::Default,
+        });
+
+        env.set_override(FlagOverride {
+            enabled: Some(false),
+            variant: Some("treatment".to_string()),
+            ..FlagOverride::new("flag_1")
+        });
+        let evaluation = env.evaluate(&flag, &context("tenant_1"));
+        assert_eq!((evaluation.value, evaluation.variant, evaluation.reason), (false, None, Reason::Disabled));
+    }
+}
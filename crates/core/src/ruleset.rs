@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::evaluation::Evaluation;
+use crate::models::{Environment, FeatureFlag};
+
+/// Flag definitions of an environment, enough to evaluate its flags locally the way
+/// `GET /flags/{environment_name}` does. Deltas only hold what changed since the revision
+/// they were requested for: the environment when it changed, changed flags replacing the
+/// ones with the same name, and the names of the flags to drop.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Ruleset {
+    /// Revision of the project, the definitions are at least as recent
+    pub revision: i64,
+    /// Whether the ruleset replaces the previous one instead of updating it
+    pub full: bool,
+    /// Always set in full rulesets, with its overrides and variants
+    pub environment: Option<Environment>,
+    /// Flags that are not archived, disabled ones included
+    pub flags: Vec<FeatureFlag>,
+    /// Flags archived or purged since the requested revision
+    pub removed: Vec<String>,
+}
+
+impl Ruleset {
+    /// Brings the ruleset to the revision of the given full ruleset or delta
+    pub fn apply(&mut self, update: Ruleset) {
+        if update.full {
+            *self = update;
+            return;
+        }
+        if update.environment.is_some() {
+            self.environment = update.environment;
+        }
+        self.flags.retain(|flag| {
+            !update.removed.contains(&flag.name) && !update.flags.iter().any(|f| f.name == flag.name)
+        });
+        self.flags.extend(update.flags);
+        self.revision = update.revision;
+    }
+
+    /// Same values as `POST /flags/{environment_name}` returns for the context
+    pub fn get_flags_from_context(&self, context: &Map<String, Value>) -> Map<String, Value> {
+        match &self.environment {
+            Some(env) => env.get_flags_from_context(&self.flags, context),
+            None => Map::new(),
+        }
+    }
+
+    /// Same evaluation as `/flags/{environment_name}/{flag_name}`, `None` for unknown flags
+    pub fn evaluate(&self, flag_name: &str, context: &Map<String, Value>) -> Option<Evaluation> {
+        let env = self.environment.as_ref()?;
+        let flag = self.flags.iter().find(|flag| flag.name == flag_name)?;
+        Some(env.evaluate(flag, context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::{Operator, Reason, Rule};
+    use crate::models::FlagOverride;
+
+    #[test]
+    fn test_apply_delta() {
+        let rules = vec![Rule { parameter: "tenant".to_string(), operator: Operator::Is("tenant_1".to_string()) }];
+        let mut ruleset = Ruleset {
+            revision: 3,
+            full: true,
+            environment: Some(Environment::new("production")),
+            flags: vec![
+                FeatureFlag::new("flag_1", "Flag 1", true, rules),
+                FeatureFlag::new("flag_2", "Flag 2", true, vec![]),
+            ],
+            removed: vec![],
+        };
+        let context = Map::from_iter([("tenant".to_string(), Value::from("tenant_2"))]);
+        let flags = ruleset.get_flags_from_context(&context);
+        assert_eq!(Value::Object(flags), serde_json::json!({"flag_1": false, "flag_2": true}));
+
+        let mut env = Environment::new("production");
+        env.set_override(FlagOverride { rules: Some(vec![]), ..FlagOverride::new("flag_1") });
+        ruleset.apply(Ruleset {
+            revision: 5,
+            full: false,
+            environment: Some(env),
+            flags: vec![FeatureFlag::new("flag_3", "Flag 3", false, vec![])],
+            removed: vec!["flag_2".to_string()],
+        });
+        assert_eq!(ruleset.revision, 5);
+        let flags = ruleset.get_flags_from_context(&context);
+        assert_eq!(Value::Object(flags), serde_json::json!({"flag_1": true, "flag_3": false}));
+        assert_eq!(ruleset.evaluate("flag_3", &context).unwrap().reason, Reason::Disabled);
+        assert!(ruleset.evaluate("flag_2", &context).is_none());
+    }
+}
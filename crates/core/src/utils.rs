@@ -0,0 +1,199 @@
+pub mod date_format {
+    use chrono::{DateTime, Utc, TimeZone};
+    use serde::{self, Deserialize, Serializer, Deserializer};
+
+    const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+    /// Formats a date as it is stored, the format sorts chronologically
+    pub fn format(date: &DateTime<Utc>) -> String {
+        format!("{}", date.format(FORMAT))
+    }
+
+    pub fn serialize<S>(
+        date: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format(date))
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Utc.datetime_from_str(&s, FORMAT).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Same as `date_format`, for optional dates
+pub mod optional_date_format {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(
+        date: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match date {
+            Some(date) => super::date_format::serialize(date, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "super::date_format")] DateTime<Utc>);
+
+        let date = Option::<Wrapper>::deserialize(deserializer)?;
+        Ok(date.map(|Wrapper(date)| date))
+    }
+}
+
+/// Fields of updates that can be cleared: missing fields are `None` when used with
+/// `#[serde(default)]`, `null` is `Some(None)`
+pub fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: serde::Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    serde::Deserialize::deserialize(deserializer).map(Some)
+}
+
+/// Same as `optional_date_format`, for dates of updates that can be cleared, see `nullable`
+pub mod nullable_date_format {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S>(
+        date: &Option<Option<DateTime<Utc>>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match date {
+            Some(date) => super::optional_date_format::serialize(date, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<Option<DateTime<Utc>>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        super::optional_date_format::deserialize(deserializer).map(Some)
+    }
+}
+
+/// Object ids as hex strings in JSON, stored as BSON object ids. The extended JSON
+/// form `{"$oid": ...}` is still accepted.
+pub mod object_id_format {
+    use bson::oid::ObjectId;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(id: &ObjectId, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&id.to_hex())
+        } else {
+            id.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<ObjectId, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Id {
+            Hex(String),
+            Extended(ObjectId),
+        }
+
+        if !deserializer.is_human_readable() {
+            return ObjectId::deserialize(deserializer);
+        }
+        match Id::deserialize(deserializer)? {
+            Id::Hex(hex) => ObjectId::parse_str(&hex).map_err(D::Error::custom),
+            Id::Extended(id) => Ok(id),
+        }
+    }
+}
+
+/// Same as `object_id_format`, for optional ids
+pub mod optional_object_id_format {
+    use bson::oid::ObjectId;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(id: &Option<ObjectId>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match id {
+            Some(id) => super::object_id_format::serialize(id, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<ObjectId>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "super::object_id_format")] ObjectId);
+
+        let id = Option::<Wrapper>::deserialize(deserializer)?;
+        Ok(id.map(|Wrapper(id)| id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::{oid::ObjectId, Document};
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn test_object_id_format() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Entity {
+            #[serde(with = "optional_object_id_format", default)]
+            id: Option<ObjectId>,
+        }
+
+        let id = ObjectId::new();
+        let entity = Entity { id: Some(id) };
+        let json = serde_json::to_value(&entity).unwrap();
+        assert_eq!(json, serde_json::json!({"id": id.to_hex()}));
+        assert_eq!(serde_json::from_value::<Entity>(json).unwrap(), entity);
+        let extended = serde_json::json!({"id": {"$oid": id.to_hex()}});
+        assert_eq!(serde_json::from_value::<Entity>(extended).unwrap(), entity);
+        assert!(serde_json::from_value::<Entity>(serde_json::json!({"id": "invalid"})).is_err());
+
+        // Stored as an object id
+        let bytes = bson::to_vec(&entity).unwrap();
+        let document = Document::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(document.get_object_id("id").unwrap(), id);
+        assert_eq!(bson::from_slice::<Entity>(&bytes).unwrap(), entity);
+        assert_eq!(bson::from_document::<Entity>(document).unwrap(), entity);
+    }
+}
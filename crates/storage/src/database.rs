@@ -1,16 +1,26 @@
 use dotenv;
+use mongodb::bson::doc;
 use mongodb::options::ClientOptions;
 use mongodb::{error::Error, Client, Database};
-use serde::{Deserialize, Serialize};
 use std::env;
+use crate::migrations::{self, MigrationError};
 
-pub async fn init_db() -> Result<Database, Error> {
+/// Connects to the database and applies the pending migrations
+pub async fn init_db() -> Result<Database, MigrationError> {
+    let (_, db) = init_client_and_db().await?;
+    Ok(db)
+}
+
+/// Same as `init_db`, also returning the client used to start sessions
+pub async fn init_client_and_db() -> Result<(Client, Database), MigrationError> {
     dotenv::dotenv().ok();
     let uri = env::var("MONGODB_URI").expect("MONGODB_URI not set");
     let db_name = env::var("DATABASE_NAME").expect("DATABASE_NAME not set");
 
     let client = connection_client(&uri).await?;
-    Ok(client.database(&db_name))
+    let db = client.database(&db_name);
+    migrations::run(&db).await?;
+    Ok((client, db))
 }
 
 pub async fn connection_client(uri: &str) -> Result<Client, Error> {
@@ -30,17 +40,25 @@ pub async fn connection_client(uri: &str) -> Result<Client, Error> {
     Ok(client)
 }
 
+/// Transactions are only supported by replica sets and sharded clusters, not by the
+/// standalone server of docker-compose.yaml
+pub async fn supports_transactions(client: &Client) -> Result<bool, Error> {
+    let hello = client.database("admin").run_command(doc! {"isMaster": 1}, None).await?;
+    Ok(hello.contains_key("setName") || hello.get_str("msg") == Ok("isdbgrid"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde::{Deserialize, Serialize};
 
-    #[actix_web::test]
+    #[tokio::test]
     async fn test_get_db() {
         let db = init_db().await;
         assert!(db.is_ok());
     }
 
-    #[actix_web::test]
+    #[tokio::test]
     async fn test_add_book() {
         let db = init_db().await.unwrap();
         #[derive(Debug, Serialize, Deserialize)]
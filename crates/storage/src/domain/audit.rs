@@ -0,0 +1,127 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{to_document, Document};
+use serde::{Deserialize, Serialize};
+use feature_flags_core::models::{Environment, FeatureFlag, Project};
+use feature_flags_core::utils;
+
+/// Entities whose writes are recorded in the audit log
+pub trait Auditable: Serialize {
+    fn audit_target(&self) -> AuditTarget;
+    /// Project the entity belongs to, projects belong to themselves
+    fn audit_project(&self) -> String;
+}
+
+impl Auditable for FeatureFlag {
+    fn audit_target(&self) -> AuditTarget {
+        AuditTarget {
+            kind: "feature_flag".to_string(),
+            id: self.id,
+            name: self.name.clone(),
+        }
+    }
+
+    fn audit_project(&self) -> String {
+        self.project.clone()
+    }
+}
+
+impl Auditable for Environment {
+    fn audit_target(&self) -> AuditTarget {
+        AuditTarget {
+            kind: "environment".to_string(),
+            id: self.id,
+            name: self.name.clone(),
+        }
+    }
+
+    fn audit_project(&self) -> String {
+        self.project.clone()
+    }
+}
+
+impl Auditable for Project {
+    fn audit_target(&self) -> AuditTarget {
+        AuditTarget {
+            kind: "project".to_string(),
+            id: self.id,
+            name: self.name.clone(),
+        }
+    }
+
+    fn audit_project(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditTarget {
+    pub kind: String,
+    #[serde(with = "utils::optional_object_id_format", default)]
+    pub id: Option<ObjectId>,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[serde(
+        rename = "_id",
+        with = "utils::optional_object_id_format",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub id: Option<ObjectId>,
+    pub project: String,
+    pub actor: String,
+    pub action: String,
+    pub target: AuditTarget,
+    pub before: Option<Document>,
+    pub after: Option<Document>,
+    pub request_id: String,
+
+    #[serde(with = "utils::date_format")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    pub fn new<T: Auditable>(
+        actor: &str,
+        action: &str,
+        request_id: &str,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> Option<Self> {
+        let entity = after.or(before)?;
+        Some(AuditEntry {
+            id: None,
+            project: entity.audit_project(),
+            actor: actor.to_string(),
+            action: action.to_string(),
+            target: entity.audit_target(),
+            before: before.and_then(|entity| to_document(entity).ok()),
+            after: after.and_then(|entity| to_document(entity).ok()),
+            request_id: request_id.to_string(),
+            created_at: Utc::now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feature_flags_core::models::DEFAULT_PROJECT;
+
+    #[test]
+    fn test_audit_entry() {
+        let mut flag = FeatureFlag::new("sample_flag", "Sample Flag", true, vec![]);
+        flag.id = Some(ObjectId::new());
+        let entry = AuditEntry::new("john", "feature_flag.delete", "req-1", Some(&flag), None).unwrap();
+        assert_eq!(entry.target.kind, "feature_flag");
+        assert_eq!(entry.target.name, "sample_flag");
+        assert_eq!(entry.target.id, flag.id);
+        assert_eq!(entry.project, DEFAULT_PROJECT);
+        assert!(entry.before.is_some());
+        assert!(entry.after.is_none());
+        assert!(AuditEntry::new::<FeatureFlag>("john", "feature_flag.delete", "req-1", None, None).is_none());
+    }
+}
@@ -0,0 +1,216 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use feature_flags_core::models::{Environment, FeatureFlag};
+use feature_flags_core::utils;
+
+/// Entities whose changes are kept as revisions
+pub trait Revisioned: Serialize {
+    fn entity_id(&self) -> Option<ObjectId>;
+
+    fn version(&self) -> i64;
+
+    /// Fields compared between revisions, bookkeeping fields are left out
+    fn tracked_fields(&self) -> Value;
+}
+
+impl Revisioned for FeatureFlag {
+    fn entity_id(&self) -> Option<ObjectId> {
+        self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn tracked_fields(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or(Value::Null);
+        if let Value::Object(fields) = &mut value {
+            for field in ["_id", "version", "created_at", "updated_at"] {
+                fields.remove(field);
+            }
+        }
+        value
+    }
+}
+
+impl Revisioned for Environment {
+    fn entity_id(&self) -> Option<ObjectId> {
+        self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn tracked_fields(&self) -> Value {
+        // Flags are keyed by name so changes are reported per flag
+        let mut flags = Map::new();
+        for flag in self.flags.iter() {
+            let mut value = serde_json::to_value(flag).unwrap_or(Value::Null);
+            if let Value::Object(fields) = &mut value {
+                fields.remove("name");
+            }
+            flags.insert(flag.name.clone(), value);
+        }
+        let mut fields = Map::new();
+        fields.insert("name".to_string(), Value::String(self.name.clone()));
+        fields.insert("archived".to_string(), Value::Bool(self.archived));
+        fields.insert("flags".to_string(), Value::Object(flags));
+        Value::Object(fields)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RevisionAction {
+    Created,
+    Updated,
+    Deleted,
+    Archived,
+    Restored,
+    RolledBack(i64),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// Immutable state of an entity after a change
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Revision<T> {
+    #[serde(
+        rename = "_id",
+        with = "utils::optional_object_id_format",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub id: Option<ObjectId>,
+    #[serde(with = "utils::object_id_format")]
+    pub entity_id: ObjectId,
+    /// Version of the entity after the change
+    pub revision: i64,
+    pub action: RevisionAction,
+    pub actor: String,
+    pub changes: Vec<FieldChange>,
+    pub snapshot: T,
+
+    #[serde(with = "utils::date_format")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl<T: Revisioned> Revision<T> {
+    pub fn new(action: RevisionAction, before: Option<&T>, after: T, actor: &str) -> Option<Self> {
+        let changes = match (&action, before) {
+            (RevisionAction::Deleted, _) => vec![],
+            (_, Some(before)) => diff(&before.tracked_fields(), &after.tracked_fields()),
+            (_, None) => diff(&Value::Null, &after.tracked_fields()),
+        };
+        let revision = match action {
+            RevisionAction::Deleted => after.version() + 1,
+            _ => after.version(),
+        };
+        Some(Revision {
+            id: None,
+            entity_id: after.entity_id()?,
+            revision,
+            action,
+            actor: actor.to_string(),
+            changes,
+            snapshot: after,
+            created_at: Utc::now(),
+        })
+    }
+}
+
+/// Lists the fields that differ between two values, nested objects are compared field by field
+pub fn diff(before: &Value, after: &Value) -> Vec<FieldChange> {
+    let mut changes = vec![];
+    let empty = Value::Object(Map::new());
+    let before = if before.is_null() { &empty } else { before };
+    let after = if after.is_null() { &empty } else { after };
+    diff_into("", before, after, &mut changes);
+    changes
+}
+
+fn diff_into(path: &str, before: &Value, after: &Value, changes: &mut Vec<FieldChange>) {
+    match (before, after) {
+        (Value::Object(before_fields), Value::Object(after_fields)) => {
+            let mut keys: Vec<&String> = before_fields.keys().chain(after_fields.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let field = if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff_into(
+                    &field,
+                    before_fields.get(key).unwrap_or(&Value::Null),
+                    after_fields.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        _ if before != after => changes.push(FieldChange {
+            field: path.to_string(),
+            before: before.clone(),
+            after: after.clone(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feature_flags_core::evaluation::{Operator, Rule};
+    use feature_flags_core::models::FlagOverride;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff() {
+        let before = json!({"label": "Flag", "enabled": false, "nested": {"a": 1}});
+        let after = json!({"label": "Flag", "enabled": true, "nested": {"a": 2, "b": 1}});
+        let changes = diff(&before, &after);
+        assert_eq!(changes, vec![
+            FieldChange { field: "enabled".to_string(), before: json!(false), after: json!(true) },
+            FieldChange { field: "nested.a".to_string(), before: json!(1), after: json!(2) },
+            FieldChange { field: "nested.b".to_string(), before: Value::Null, after: json!(1) },
+        ]);
+        assert!(diff(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn test_flag_revision() {
+        let mut before = FeatureFlag::new("sample_flag", "Sample Flag", false, vec![]);
+        before.id = Some(ObjectId::new());
+        let mut after = before.clone();
+        after.enabled = true;
+        after.rules = vec![Rule {
+            parameter: "tenant".to_string(),
+            operator: Operator::Is("tenant_1".to_string()),
+        }];
+        after.version = 1;
+        let revision = Revision::new(RevisionAction::Updated, Some(&before), after, "john").unwrap();
+        assert_eq!(revision.revision, 1);
+        assert_eq!(revision.changes.len(), 2);
+        assert_eq!(revision.changes[0].field, "enabled");
+        assert_eq!(revision.changes[1].field, "rules");
+    }
+
+    #[test]
+    fn test_environment_revision_reports_flags_by_name() {
+        let mut before = Environment::new("development");
+        before.id = Some(ObjectId::new());
+        let mut after = before.clone();
+        after.set_override(FlagOverride::new("sample_flag"));
+        let revision = Revision::new(RevisionAction::Updated, Some(&before), after, "john").unwrap();
+        assert_eq!(revision.changes.len(), 1);
+        assert_eq!(revision.changes[0].field, "flags.sample_flag");
+    }
+}
@@ -0,0 +1,4 @@
+pub mod audit;
+pub mod history;
+pub mod sync;
+pub mod usage;
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+/// Number of revisions whose changes are kept per project, changes since older revisions
+/// are answered with a full ruleset
+pub const MAX_CHANGES: usize = 1000;
+
+/// Revision of the flags and environments of a project, bumped by every write so the
+/// replicas know when to reload their snapshot
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RevisionCounter {
+    #[serde(rename = "_id")]
+    pub project: String,
+    pub revision: i64,
+    /// Entities changed by the latest revisions, one entry per revision
+    #[serde(default)]
+    pub changes: Vec<Vec<ChangedEntity>>,
+}
+
+impl RevisionCounter {
+    /// Entities changed after the given revision, `None` when the changes since then were
+    /// not kept or the revision is unknown
+    pub fn changes_since(&self, revision: i64) -> Option<Vec<&ChangedEntity>> {
+        let first = self.revision - self.changes.len() as i64;
+        if revision < first || revision > self.revision {
+            return None;
+        }
+        Some(self.changes[(revision - first) as usize..].iter().flatten().collect())
+    }
+}
+
+/// What a write changed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "name", rename_all = "snake_case")]
+pub enum ChangedEntity {
+    Flag(String),
+    Environment(String),
+    /// Anything in the project may have changed
+    Project,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_since() {
+        let counter = RevisionCounter {
+            project: "default".to_string(),
+            revision: 5,
+            changes: vec![
+                vec![ChangedEntity::Flag("flag_1".to_string()), ChangedEntity::Flag("flag_2".to_string())],
+                vec![ChangedEntity::Environment("development".to_string())],
+            ],
+        };
+        let development = ChangedEntity::Environment("development".to_string());
+        assert_eq!(counter.changes_since(2), None);
+        assert_eq!(counter.changes_since(3).unwrap().len(), 3);
+        assert_eq!(counter.changes_since(4), Some(vec![&development]));
+        assert_eq!(counter.changes_since(5), Some(vec![]));
+        assert_eq!(counter.changes_since(6), None);
+    }
+}
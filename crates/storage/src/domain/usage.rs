@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use feature_flags_core::utils;
+
+/// Evaluations of a flag by the client endpoints
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FlagUsage {
+    #[serde(
+        rename = "_id",
+        with = "utils::optional_object_id_format",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub id: Option<ObjectId>,
+    pub project: String,
+    pub name: String,
+    pub evaluations: i64,
+
+    #[serde(with = "utils::date_format")]
+    pub last_evaluated_at: DateTime<Utc>,
+}
+
+/// Evaluations counted in memory, by project and flag name, until they are stored
+#[derive(Debug, Default)]
+pub struct EvaluationCounter {
+    counts: HashMap<(String, String), i64>,
+}
+
+impl EvaluationCounter {
+    pub fn record<'a>(&mut self, project: &str, names: impl IntoIterator<Item = &'a String>) {
+        for name in names {
+            *self.counts.entry((project.to_string(), name.clone())).or_default() += 1;
+        }
+    }
+
+    /// Counts recorded since the last call
+    pub fn take(&mut self) -> HashMap<(String, String), i64> {
+        std::mem::take(&mut self.counts)
+    }
+
+    /// Adds back counts that could not be stored
+    pub fn restore(&mut self, counts: HashMap<(String, String), i64>) {
+        for (key, count) in counts {
+            *self.counts.entry(key).or_default() += count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluation_counter() {
+        let mut counter = EvaluationCounter::default();
+        let names = vec!["flag_a".to_string(), "flag_b".to_string()];
+        counter.record("default", &names);
+        counter.record("default", &names[..1]);
+        let counts = counter.take();
+        assert_eq!(counts[&("default".to_string(), "flag_a".to_string())], 2);
+        assert_eq!(counts[&("default".to_string(), "flag_b".to_string())], 1);
+        assert!(counter.take().is_empty());
+
+        counter.restore(counts);
+        counter.record("default", &names[..1]);
+        assert_eq!(counter.take()[&("default".to_string(), "flag_a".to_string())], 3);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod database;
+pub mod domain;
+pub mod migrations;
+pub mod repositories;
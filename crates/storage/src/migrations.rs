@@ -0,0 +1,568 @@
+use std::fmt;
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::{Error, ErrorKind};
+use mongodb::options::{IndexOptions, UpdateOptions};
+use mongodb::{Database, IndexModel};
+use crate::repositories::is_duplicate_key;
+use feature_flags_core::models::DEFAULT_PROJECT;
+use feature_flags_core::utils::date_format;
+
+/// Records the data migrations already applied, keyed by version
+pub const MIGRATIONS_COLLECTION: &str = "_migrations";
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+}
+
+/// Errors stopping the startup before the server runs on an inconsistent database
+#[derive(Debug)]
+pub enum MigrationError {
+    Database(Error),
+    UnknownVersion(i64),
+    /// Versions applied by a newer release, this one would run on data it can not read
+    NewerVersions(Vec<i64>),
+    /// Environments holding flags without a name, they can not be turned into overrides
+    UnnamedFlags(Vec<Bson>),
+    /// Documents breaking a unique index, they have to be renamed or purged before it can be created
+    Duplicates(Vec<Duplicate>),
+}
+
+/// Documents of a collection sharing the keys of a unique index
+#[derive(Debug)]
+pub struct Duplicate {
+    pub collection: &'static str,
+    pub keys: Document,
+    pub ids: Vec<Bson>,
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Database(err) => write!(f, "{}", err),
+            MigrationError::UnknownVersion(version) => write!(f, "Unknown migration {}", version),
+            MigrationError::NewerVersions(versions) => write!(
+                f, "Migrations {:?} were applied by a newer release, upgrade before starting", versions,
+            ),
+            MigrationError::UnnamedFlags(ids) => write!(
+                f, "Environments hold flags without a name, name or remove them: ids {:?}", ids,
+            ),
+            MigrationError::Duplicates(duplicates) => {
+                write!(f, "Unique indexes can not be created, rename or purge the duplicates:")?;
+                for duplicate in duplicates {
+                    write!(f, "\n- {} {} ids {:?}", duplicate.collection, duplicate.keys, duplicate.ids)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<Error> for MigrationError {
+    fn from(err: Error) -> Self {
+        MigrationError::Database(err)
+    }
+}
+
+/// Data migrations in the order they are applied, versions must never be reused
+const MIGRATIONS: [Migration; 5] = [
+    Migration { version: 1, name: "environment_flag_overrides" },
+    Migration { version: 2, name: "projects" },
+    Migration { version: 3, name: "flag_lifecycle" },
+    Migration { version: 4, name: "entity_timestamps" },
+    Migration { version: 5, name: "audit_projects" },
+];
+
+/// Applies the pending data migrations and creates the indexes, returns the applied migrations.
+/// Every step is idempotent so concurrent runs are safe.
+pub async fn run(db: &Database) -> Result<Vec<String>, MigrationError> {
+    let records = db.collection::<Document>(MIGRATIONS_COLLECTION);
+    let applied: Vec<i64> = records
+        .find(None, None)
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?
+        .iter()
+        .filter_map(|record| record.get_i64("_id").ok())
+        .collect();
+    check_applied(&applied)?;
+
+    let mut res = vec![];
+    for migration in MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+        apply(db, migration.version).await?;
+        let record = doc! {
+            "_id": migration.version,
+            "name": migration.name,
+            "applied_at": date_format::format(&Utc::now()),
+        };
+        match records.insert_one(record, None).await {
+            Ok(_) => res.push(migration.name.to_string()),
+            // Applied by a concurrent run
+            Err(err) if is_duplicate_key(&err) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    create_indexes(db).await?;
+    Ok(res)
+}
+
+/// Fails when the database was migrated by a newer release
+fn check_applied(applied: &[i64]) -> Result<(), MigrationError> {
+    let newer: Vec<i64> = applied
+        .iter()
+        .filter(|version| !MIGRATIONS.iter().any(|m| m.version == **version))
+        .copied()
+        .collect();
+    if !newer.is_empty() {
+        return Err(MigrationError::NewerVersions(newer));
+    }
+    Ok(())
+}
+
+async fn apply(db: &Database, version: i64) -> Result<(), MigrationError> {
+    match version {
+        1 => environment_flag_overrides(db).await?,
+        2 => projects(db).await?,
+        3 => flag_lifecycle(db).await?,
+        4 => entity_timestamps(db).await?,
+        5 => audit_projects(db).await?,
+        _ => return Err(MigrationError::UnknownVersion(version)),
+    }
+    Ok(())
+}
+
+/// Creates the indexes, existing duplicates of a unique index are reported instead of being
+/// renamed or removed
+async fn create_indexes(db: &Database) -> Result<(), MigrationError> {
+    let unique = || IndexOptions::builder().unique(true).build();
+    let indexes = [
+        ("projects", vec![
+            IndexModel::builder().keys(doc! {"name": 1}).options(unique()).build(),
+        ]),
+        ("feature_flags", vec![
+            IndexModel::builder().keys(doc! {"project": 1, "name": 1}).options(unique()).build(),
+        ]),
+        ("environments", vec![
+            IndexModel::builder().keys(doc! {"project": 1, "name": 1}).options(unique()).build(),
+            IndexModel::builder().keys(doc! {"project": 1, "flags.name": 1}).build(),
+        ]),
+        ("feature_flag_revisions", vec![
+            IndexModel::builder().keys(doc! {"entity_id": 1, "revision": -1}).options(unique()).build(),
+        ]),
+        ("environment_revisions", vec![
+            IndexModel::builder().keys(doc! {"entity_id": 1, "revision": -1}).options(unique()).build(),
+        ]),
+        ("flag_usage", vec![
+            IndexModel::builder().keys(doc! {"project": 1, "name": 1}).options(unique()).build(),
+        ]),
+        ("audit_log", vec![
+            IndexModel::builder().keys(doc! {"created_at": -1, "_id": -1}).build(),
+            IndexModel::builder().keys(doc! {"target.id": 1}).build(),
+            IndexModel::builder().keys(doc! {"actor": 1}).build(),
+            IndexModel::builder().keys(doc! {"project": 1, "created_at": -1}).build(),
+        ]),
+    ];
+    for (collection, models) in indexes {
+        let unique_keys: Vec<Document> = models
+            .iter()
+            .filter(|model| model.options.as_ref().and_then(|options| options.unique) == Some(true))
+            .map(|model| model.keys.clone())
+            .collect();
+        match db.collection::<Document>(collection).create_indexes(models, None).await {
+            Ok(_) => {}
+            Err(err) if is_duplicate_key(&err) => {
+                let mut duplicates = vec![];
+                for keys in &unique_keys {
+                    duplicates.extend(find_duplicates(db, collection, keys).await?);
+                }
+                return Err(MigrationError::Duplicates(duplicates));
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Groups the documents of the collection sharing the values of the index keys
+async fn find_duplicates(db: &Database, collection: &'static str, keys: &Document) -> Result<Vec<Duplicate>, Error> {
+    let mut group = doc! {};
+    for key in keys.keys() {
+        group.insert(key, format!("${}", key));
+    }
+    let pipeline = [
+        doc! {"$group": {"_id": group, "ids": {"$push": "$_id"}, "count": {"$sum": 1}}},
+        doc! {"$match": {"count": {"$gt": 1}}},
+    ];
+    let groups: Vec<Document> = db.collection::<Document>(collection)
+        .aggregate(pipeline, None)
+        .await?
+        .try_collect()
+        .await?;
+    Ok(groups
+        .into_iter()
+        .map(|group| Duplicate {
+            collection,
+            keys: group.get_document("_id").cloned().unwrap_or_default(),
+            ids: group.get_array("ids").cloned().unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Environments used to store full copies of the global flags, only the fields
+/// that can be overridden are kept. Copies without a global flag become disabled
+/// global flags, so they are still evaluated in their environment through the override.
+/// Nothing is migrated while copies without a name are left.
+async fn environment_flag_overrides(db: &Database) -> Result<(), MigrationError> {
+    let flags = db.collection::<Document>("feature_flags");
+    let legacy: Vec<Document> = db.collection::<Document>("environments")
+        .find(doc! {"flags.label": {"$exists": true}}, None)
+        .await?
+        .try_collect()
+        .await?;
+    let copies = |env: &Document| -> Vec<Document> {
+        let copies = env.get_array("flags").map(Vec::as_slice).unwrap_or_default();
+        copies.iter().filter_map(|copy| copy.as_document()).cloned().collect()
+    };
+    let unnamed: Vec<Bson> = legacy
+        .iter()
+        .filter(|env| copies(env).iter().any(|copy| copy.get_str("name").is_err()))
+        .filter_map(|env| env.get("_id").cloned())
+        .collect();
+    if !unnamed.is_empty() {
+        return Err(MigrationError::UnnamedFlags(unnamed));
+    }
+    let now = date_format::format(&Utc::now());
+    for env in &legacy {
+        for copy in copies(env) {
+            let name = copy.get_str("name").unwrap_or_default();
+            let mut filter = doc! {"name": name};
+            let mut flag = doc! {
+                "label": copy.get_str("label").unwrap_or(name),
+                "enabled": false,
+                "rules": [],
+                "created_at": &now,
+                "updated_at": &now,
+            };
+            // Environments created before projects have no project, `projects` moves both
+            if let Some(project) = env.get("project") {
+                filter.insert("project", project.clone());
+                flag.insert("project", project.clone());
+            }
+            let options = UpdateOptions::builder().upsert(true).build();
+            flags.update_one(filter, doc! {"$setOnInsert": flag}, options).await?;
+        }
+    }
+
+    let mut unset = doc! {};
+    for field in ["_id", "label", "tags", "archived", "version", "created_at", "updated_at"] {
+        unset.insert(format!("flags.$[].{}", field), "");
+    }
+    db.collection::<Document>("environments")
+        .update_many(doc! {"flags.label": {"$exists": true}}, doc! {"$unset": unset}, None)
+        .await?;
+    Ok(())
+}
+
+/// Flags and environments created before projects move to the default project, where
+/// names are unique instead of globally
+async fn projects(db: &Database) -> Result<(), Error> {
+    for collection in ["feature_flags", "environments"] {
+        db.collection::<Document>(collection)
+            .update_many(
+                doc! {"project": {"$exists": false}},
+                doc! {"$set": {"project": DEFAULT_PROJECT}},
+                None,
+            )
+            .await?;
+    }
+    drop_index(db, "feature_flags", "name_1").await?;
+    drop_index(db, "environments", "name_1").await?;
+    drop_index(db, "environments", "flags.name_1").await?;
+    let now = date_format::format(&Utc::now());
+    let options = UpdateOptions::builder().upsert(true).build();
+    db.collection::<Document>("projects")
+        .update_one(
+            doc! {"name": DEFAULT_PROJECT},
+            doc! {"$setOnInsert": {"label": "Default", "created_at": &now, "updated_at": &now}},
+            options,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Flags archived before lifecycles get the archived state, the others default to active
+async fn flag_lifecycle(db: &Database) -> Result<(), Error> {
+    db.collection::<Document>("feature_flags")
+        .update_many(
+            doc! {"archived": true, "lifecycle": {"$exists": false}},
+            doc! {"$set": {"lifecycle": "archived"}},
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Projects and environments stored before they had timestamps get the creation time of
+/// their id, so they read the same dates every time
+async fn entity_timestamps(db: &Database) -> Result<(), Error> {
+    for collection in ["projects", "environments"] {
+        let collection = db.collection::<Document>(collection);
+        let filter = doc! {"$or": [{"created_at": {"$exists": false}}, {"updated_at": {"$exists": false}}]};
+        let legacy: Vec<Document> = collection.find(filter, None).await?.try_collect().await?;
+        for entity in legacy {
+            let created_at = entity
+                .get_object_id("_id")
+                .map(|id| DateTime::<Utc>::from(id.timestamp().to_system_time()))
+                .unwrap_or_else(|_| Utc::now());
+            let created_at = date_format::format(&created_at);
+            let mut set = doc! {};
+            for field in ["created_at", "updated_at"] {
+                if !entity.contains_key(field) {
+                    set.insert(field, &created_at);
+                }
+            }
+            collection.update_one(doc! {"_id": entity.get("_id")}, doc! {"$set": set}, None).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Audit entries recorded before they had a project get the project of the audited
+/// entity, entries older than projects belong to the default project
+async fn audit_projects(db: &Database) -> Result<(), Error> {
+    let project = doc! {"$cond": [
+        {"$eq": ["$target.kind", "project"]},
+        "$target.name",
+        {"$ifNull": ["$after.project", {"$ifNull": ["$before.project", DEFAULT_PROJECT]}]},
+    ]};
+    db.collection::<Document>("audit_log")
+        .update_many(doc! {"project": {"$exists": false}}, vec![doc! {"$set": {"project": project}}], None)
+        .await?;
+    Ok(())
+}
+
+/// Drops an index, missing indexes and collections are ignored
+async fn drop_index(db: &Database, collection: &str, name: &str) -> Result<(), Error> {
+    const NAMESPACE_NOT_FOUND: i32 = 26;
+    const INDEX_NOT_FOUND: i32 = 27;
+    match db.collection::<Document>(collection).drop_index(name, None).await {
+        Ok(_) => Ok(()),
+        Err(err) => match err.kind.as_ref() {
+            ErrorKind::Command(cmd) if [NAMESPACE_NOT_FOUND, INDEX_NOT_FOUND].contains(&cmd.code) => Ok(()),
+            _ => Err(err),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::oid::ObjectId;
+    use crate::database::{init_client_and_db, init_db};
+
+    #[test]
+    fn test_migration_versions_are_sorted_and_unique() {
+        for pair in MIGRATIONS.windows(2) {
+            assert!(pair[0].version < pair[1].version);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_environment_flag_overrides() {
+        let db = init_db().await.unwrap();
+        let environments = db.collection::<Document>("environments");
+        environments.insert_one(doc! {
+            "name": "legacy_env",
+            "flags": [{"name": "sample_flag", "label": "Sample Flag", "enabled": true, "rules": []}],
+        }, None).await.unwrap();
+
+        environment_flag_overrides(&db).await.unwrap();
+        let migrated = environments.find_one(doc! {"name": "legacy_env"}, None).await.unwrap().unwrap();
+        let flag = migrated.get_array("flags").unwrap()[0].as_document().unwrap();
+        assert!(!flag.contains_key("label"));
+        assert!(flag.get_bool("enabled").unwrap());
+
+        // Migrations are recorded once
+        assert!(run(&db).await.unwrap().is_empty());
+        let records = db.collection::<Document>(MIGRATIONS_COLLECTION);
+        assert_eq!(records.count_documents(doc! {"_id": 1_i64}, None).await.unwrap(), 1);
+
+        environments.delete_one(doc! {"name": "legacy_env"}, None).await.unwrap();
+        db.collection::<Document>("feature_flags")
+            .delete_one(doc! {"name": "sample_flag", "project": {"$exists": false}}, None)
+            .await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_environment_only_flags_become_disabled_global_flags() {
+        let db = init_db().await.unwrap();
+        let environments = db.collection::<Document>("environments");
+        let flags = db.collection::<Document>("feature_flags");
+        environments.insert_one(doc! {
+            "project": DEFAULT_PROJECT,
+            "name": "legacy_env_only",
+            "flags": [{"name": "legacy_env_only_flag", "label": "Env Only", "enabled": true, "rules": []}],
+        }, None).await.unwrap();
+
+        environment_flag_overrides(&db).await.unwrap();
+        let flag = flags.find_one(doc! {"name": "legacy_env_only_flag"}, None).await.unwrap().unwrap();
+        assert_eq!(flag.get_str("project").unwrap(), DEFAULT_PROJECT);
+        assert_eq!(flag.get_str("label").unwrap(), "Env Only");
+        assert!(!flag.get_bool("enabled").unwrap());
+        // The environment keeps the flag enabled through its override
+        let migrated = environments.find_one(doc! {"name": "legacy_env_only"}, None).await.unwrap().unwrap();
+        let flag_override = migrated.get_array("flags").unwrap()[0].as_document().unwrap();
+        assert!(flag_override.get_bool("enabled").unwrap());
+
+        environments.delete_one(doc! {"name": "legacy_env_only"}, None).await.unwrap();
+        flags.delete_one(doc! {"name": "legacy_env_only_flag"}, None).await.unwrap();
+    }
+    #[test]
+    fn test_duplicates_are_reported() {
+        let err = MigrationError::Duplicates(vec![Duplicate {
+            collection: "feature_flags",
+            keys: doc! {"project": DEFAULT_PROJECT, "name": "duplicated"},
+            ids: vec![Bson::from("first"), Bson::from("second")],
+        }]);
+        let report = err.to_string();
+        assert!(report.contains("feature_flags"));
+        assert!(report.contains("duplicated"));
+        assert!(report.contains("first") && report.contains("second"));
+    }
+
+    #[tokio::test]
+    async fn test_entity_timestamps() {
+        let db = init_db().await.unwrap();
+        let environments = db.collection::<Document>("environments");
+        let id = ObjectId::parse_str("5f2c7a1e9b0d4c3a2e1f0a9b").unwrap();
+        environments.insert_one(doc! {"_id": id, "project": DEFAULT_PROJECT, "name": "legacy_timestamps_env"}, None)
+            .await.unwrap();
+
+        entity_timestamps(&db).await.unwrap();
+        let migrated = environments.find_one(doc! {"_id": id}, None).await.unwrap().unwrap();
+        let created_at = date_format::format(&DateTime::<Utc>::from(id.timestamp().to_system_time()));
+        assert_eq!(migrated.get_str("created_at").unwrap(), created_at);
+        assert_eq!(migrated.get_str("updated_at").unwrap(), created_at);
+
+        environments.delete_one(doc! {"_id": id}, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unknown_migration() {
+        let db = init_db().await.unwrap();
+        assert!(matches!(apply(&db, 0).await, Err(MigrationError::UnknownVersion(0))));
+    }
+
+    #[test]
+    fn test_newer_versions_are_rejected() {
+        assert!(check_applied(&[1, 2, 3, 4, 5]).is_ok());
+        assert!(matches!(check_applied(&[1, 99]), Err(MigrationError::NewerVersions(versions)) if versions == vec![99]));
+    }
+
+    #[tokio::test]
+    async fn test_run_on_newer_database() {
+        // A database of its own, the other tests would not start while it holds a newer version
+        let (client, _) = init_client_and_db().await.unwrap();
+        let db = client.database("migrations_newer_database_test");
+        let records = db.collection::<Document>(MIGRATIONS_COLLECTION);
+        records.insert_one(doc! {"_id": 9999_i64, "name": "newer_migration"}, None).await.unwrap();
+
+        let res = run(&db).await;
+        db.drop(None).await.unwrap();
+        assert!(matches!(res, Err(MigrationError::NewerVersions(versions)) if versions == vec![9999]));
+    }
+
+    #[tokio::test]
+    async fn test_unnamed_flags_are_reported() {
+        let db = init_db().await.unwrap();
+        let environments = db.collection::<Document>("environments");
+        let id = ObjectId::new();
+        environments.insert_one(doc! {
+            "_id": id,
+            "name": "legacy_unnamed_env",
+            "flags": [{"label": "Unnamed", "enabled": true, "rules": []}],
+        }, None).await.unwrap();
+
+        let res = environment_flag_overrides(&db).await;
+        environments.delete_one(doc! {"_id": id}, None).await.unwrap();
+        assert!(matches!(res, Err(MigrationError::UnnamedFlags(ids)) if ids == vec![Bson::ObjectId(id)]));
+    }
+
+    #[tokio::test]
+    async fn test_find_duplicates() {
+        let db = init_db().await.unwrap();
+        let collection = db.collection::<Document>("migration_duplicates");
+        collection.insert_many([
+            doc! {"project": DEFAULT_PROJECT, "name": "duplicated"},
+            doc! {"project": DEFAULT_PROJECT, "name": "duplicated"},
+            doc! {"project": "other", "name": "duplicated"},
+        ], None).await.unwrap();
+
+        let duplicates = find_duplicates(&db, "migration_duplicates", &doc! {"project": 1, "name": 1})
+            .await
+            .unwrap();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].keys, doc! {"project": DEFAULT_PROJECT, "name": "duplicated"});
+        assert_eq!(duplicates[0].ids.len(), 2);
+
+        collection.drop(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_projects() {
+        let db = init_db().await.unwrap();
+        let flags = db.collection::<Document>("feature_flags");
+        flags.insert_one(doc! {"name": "legacy_project_flag", "label": "", "enabled": true, "rules": []}, None)
+            .await.unwrap();
+
+        projects(&db).await.unwrap();
+        let migrated = flags.find_one(doc! {"name": "legacy_project_flag"}, None).await.unwrap().unwrap();
+        assert_eq!(migrated.get_str("project").unwrap(), DEFAULT_PROJECT);
+        let projects = db.collection::<Document>("projects");
+        assert_eq!(projects.count_documents(doc! {"name": DEFAULT_PROJECT}, None).await.unwrap(), 1);
+
+        flags.delete_one(doc! {"name": "legacy_project_flag"}, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_audit_projects() {
+        let db = init_db().await.unwrap();
+        let entries = db.collection::<Document>("audit_log");
+        entries.insert_many([
+            doc! {"actor": "legacy_audit", "target": {"kind": "feature_flag", "name": "a"}, "after": {"project": "checkout"}},
+            doc! {"actor": "legacy_audit", "target": {"kind": "project", "name": "payments"}, "before": {"name": "payments"}},
+            doc! {"actor": "legacy_audit", "target": {"kind": "environment", "name": "b"}, "before": {"name": "b"}},
+        ], None).await.unwrap();
+
+        audit_projects(&db).await.unwrap();
+        let mut projects: Vec<String> = entries
+            .find(doc! {"actor": "legacy_audit"}, None).await.unwrap()
+            .try_collect::<Vec<Document>>().await.unwrap()
+            .iter()
+            .map(|entry| entry.get_str("project").unwrap().to_string())
+            .collect();
+        projects.sort();
+        assert_eq!(projects, vec!["checkout", DEFAULT_PROJECT, "payments"]);
+
+        entries.delete_many(doc! {"actor": "legacy_audit"}, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flag_lifecycle() {
+        let db = init_db().await.unwrap();
+        let flags = db.collection::<Document>("feature_flags");
+        flags.insert_one(doc! {
+            "project": DEFAULT_PROJECT, "name": "legacy_archived_flag", "label": "", "enabled": true, "rules": [], "archived": true,
+        }, None).await.unwrap();
+
+        flag_lifecycle(&db).await.unwrap();
+        let migrated = flags.find_one(doc! {"name": "legacy_archived_flag"}, None).await.unwrap().unwrap();
+        assert_eq!(migrated.get_str("lifecycle").unwrap(), "archived");
+
+        flags.delete_one(doc! {"name": "legacy_archived_flag"}, None).await.unwrap();
+    }
+}
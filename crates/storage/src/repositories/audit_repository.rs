@@ -0,0 +1,37 @@
+use crate::repositories::{init_collection, BaseRepository, ErrorKind, RepositoryError};
+use crate::domain::audit::AuditEntry;
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Cursor, Database};
+
+pub async fn audit_repository_factory(db: &Database) -> AuditRepository<AuditEntry> {
+    AuditRepository::<AuditEntry>::new(db, "audit_log").await
+}
+
+pub struct AuditRepository<T> {
+    pub collection: Collection<T>,
+}
+
+impl<T> AuditRepository<T> {
+    pub async fn new(db: &Database, collection_name: &str) -> AuditRepository<T> {
+        let collection = init_collection::<T>(db, collection_name).await;
+        Self { collection }
+    }
+}
+
+impl BaseRepository<AuditEntry> for AuditRepository<AuditEntry> {
+    fn collection(&self) -> &Collection<AuditEntry> {
+        &self.collection
+    }
+}
+
+impl AuditRepository<AuditEntry> {
+    /// Cursor over the matching entries, oldest first, to stream large exports
+    pub async fn stream(&self, filter: Document) -> Result<Cursor<AuditEntry>, RepositoryError> {
+        let options = FindOptions::builder().sort(doc! {"created_at": 1, "_id": 1}).build();
+        self.collection
+            .find(filter, options)
+            .await
+            .map_err(|err| RepositoryError::new(ErrorKind::Internal, err.to_string()))
+    }
+}
@@ -1,9 +1,9 @@
-use crate::adapters::repositories::{
-    init_collection, BaseRepository, ErrorKind, RepositoryError,
+use crate::repositories::{
+    init_collection, inserted_id, is_duplicate_key, BaseRepository, ErrorKind, RepositoryError,
 };
-use crate::domain::models::Environment;
+use feature_flags_core::models::Environment;
 use async_trait::async_trait;
-use mongodb::bson::{doc, to_document};
+use mongodb::bson::to_document;
 use mongodb::{Collection, Database};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -13,7 +13,7 @@ pub async fn environment_repository_factory(db: &Database) -> EnvironmentReposit
 }
 
 pub struct EnvironmentRepository<T> {
-    pub(crate) collection: Collection<T>,
+    pub collection: Collection<T>,
 }
 
 impl<T> EnvironmentRepository<T> {
@@ -33,47 +33,39 @@ where
     }
 
     async fn create(&self, entity: &T) -> Result<String, RepositoryError> {
-        let name = to_document(entity).unwrap().get("name").unwrap().to_owned();
-        let existing = self
-            .find(doc! {
-                "name": name.clone()
-            })
-            .await;
-        if let Ok(items) = existing {
-            if !items.is_empty() {
-                return Err(RepositoryError::new(
+        // Names are unique within a project, enforced by an index created by the migrations
+        match self.collection().insert_one(entity, None).await {
+            Ok(res) => Ok(inserted_id(res)),
+            Err(err) if is_duplicate_key(&err) => {
+                let name = to_document(entity)
+                    .ok()
+                    .and_then(|doc| doc.get_str("name").ok().map(|name| name.to_string()))
+                    .unwrap_or_default();
+                Err(RepositoryError::new(
                     ErrorKind::AlreadyExists,
-                    format!("Environment with name {} already exists", &name),
-                ));
+                    format!("Environment with name {} already exists", name),
+                ))
             }
+            Err(err) => Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
         }
-        let res = self
-            .collection()
-            .insert_one(entity, None)
-            .await
-            .expect("Error creating entity")
-            .inserted_id
-            .as_object_id()
-            .expect("Failed parsing object id");
-        Ok(res.to_string())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::adapters::repositories::BaseRepository;
+    use crate::repositories::BaseRepository;
     use crate::database::init_db;
-    use crate::domain::models::{FeatureFlag};
-    use std::collections::HashSet;
+    use feature_flags_core::models::FlagOverride;
 
-    #[actix_web::test]
+    #[tokio::test]
     async fn test_environment_repository() {
         let db = init_db().await.unwrap();
-        let environment = environment_repository_factory(&db).await;
+        let repo = environment_repository_factory(&db).await;
+        assert!(repo.find(None).await.is_ok());
     }
 
-    #[actix_web::test]
+    #[tokio::test]
     async fn test_create_environment() {
         let db = init_db().await.unwrap();
         let repo = environment_repository_factory(&db).await;
@@ -85,7 +77,7 @@ mod tests {
         }
     }
 
-    #[actix_web::test]
+    #[tokio::test]
     async fn test_cannot_create_duplicated_environment() {
         let db = init_db().await.unwrap();
         let repo = environment_repository_factory(&db).await;
@@ -101,19 +93,13 @@ mod tests {
         }
     }
 
-    #[actix_web::test]
+    #[tokio::test]
     async fn test_create_environment_with_flags() {
         let db = init_db().await.unwrap();
         let repo = environment_repository_factory(&db).await;
         let mut environment = Environment::new("development");
 
-        let flag = FeatureFlag::new(
-            "sample_flag",
-            "Sample Flag",
-            false,
-            vec![]
-        );
-        environment.add_flag(&flag);
+        environment.set_override(FlagOverride::new("sample_flag"));
         let res = repo.create(&environment).await;
         if let Ok(inserted_id) = res {
             let res = repo.delete(&inserted_id).await;
@@ -121,33 +107,19 @@ mod tests {
         }
     }
 
-    #[actix_web::test]
+    #[tokio::test]
     async fn test_update_environment() {
         let db = init_db().await.unwrap();
         let repo = environment_repository_factory(&db).await;
         let mut environment = Environment::new("development");
 
-        let flag = FeatureFlag::new(
-            "sample_flag",
-            "Sample Flag",
-            false,
-            vec![]
-        );
-        environment.set_flags(HashSet::from([flag]));
-        match repo.create(&environment).await {
-            Ok(inserted_id) => {
-                environment.set_flags(HashSet::new());
-                let res = repo.update(&inserted_id, &environment).await;
-                assert!(res.is_ok());
-                match res {
-                    Ok(_) => {
-                        let res = repo.delete(&inserted_id).await;
-                        assert!(res.is_ok());
-                    }
-                    Err(_) => {}
-                }
-            }
-            Err(_) => {}
+        environment.set_overrides(vec![FlagOverride::new("sample_flag")]);
+        if let Ok(inserted_id) = repo.create(&environment).await {
+            environment.set_overrides(vec![]);
+            let res = repo.update(&inserted_id, &environment).await;
+            assert!(res.is_ok());
+            let res = repo.delete(&inserted_id).await;
+            assert!(res.is_ok());
         }
     }
 }
@@ -1,8 +1,8 @@
-use crate::adapters::repositories::{
-    init_collection, BaseRepository, ErrorKind, RepositoryError,
+use crate::repositories::{
+    init_collection, inserted_id, is_duplicate_key, BaseRepository, ErrorKind, RepositoryError,
 };
-use crate::domain::models::FeatureFlag;
-use mongodb::bson::{doc, to_document};
+use feature_flags_core::models::FeatureFlag;
+use mongodb::bson::to_document;
 use mongodb::{Collection, Database};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -13,7 +13,7 @@ pub async fn feature_flags_repository_factory(db: &Database) -> FeatureFlagRepos
 }
 
 pub struct FeatureFlagRepository<T> {
-    pub(crate) collection: Collection<T>,
+    pub collection: Collection<T>,
 }
 
 impl<T> FeatureFlagRepository<T> {
@@ -33,42 +33,33 @@ where
     }
 
     async fn create(&self, entity: &T) -> Result<String, RepositoryError> {
-        let name = to_document(entity).unwrap().get("name").unwrap().to_owned();
-        let existing = self
-            .find(doc! {
-                "name": name.clone()
-            })
-            .await;
-        if let Ok(items) = existing {
-            if !items.is_empty() {
-                return Err(RepositoryError::new(
+        // Names are unique within a project, enforced by an index created by the migrations
+        match self.collection().insert_one(entity, None).await {
+            Ok(res) => Ok(inserted_id(res)),
+            Err(err) if is_duplicate_key(&err) => {
+                let name = to_document(entity)
+                    .ok()
+                    .and_then(|doc| doc.get_str("name").ok().map(|name| name.to_string()))
+                    .unwrap_or_default();
+                Err(RepositoryError::new(
                     ErrorKind::AlreadyExists,
-                    format!("Feature flag with name {} already exists", &name),
-                ));
+                    format!("Feature flag with name {} already exists", name),
+                ))
             }
+            Err(err) => Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
         }
-        let res = self
-            .collection()
-            .insert_one(entity, None)
-            .await
-            .expect("Error creating entity")
-            .inserted_id
-            .as_object_id()
-            .expect("Failed parsing object id");
-        Ok(res.to_string())
     }
 }
 
 #[cfg(test)]
 mod test_flag_definition_repository {
-    use mongodb::bson;
-    use mongodb::bson::oid::ObjectId;
+    use mongodb::bson::doc;
     use super::*;
-    use crate::adapters::repositories::feature_flags_repository::feature_flags_repository_factory;
-    use crate::adapters::repositories::BaseRepository;
+    use crate::repositories::feature_flags_repository::feature_flags_repository_factory;
+    use crate::repositories::BaseRepository;
     use crate::database::init_db;
 
-    #[actix_web::test]
+    #[tokio::test]
     async fn repo_create() {
         let db = init_db().await.unwrap();
         let repo = feature_flags_repository_factory(&db).await;
@@ -93,7 +84,7 @@ mod test_flag_definition_repository {
         }
     }
 
-    #[actix_web::test]
+    #[tokio::test]
     async fn repo_find_all() {
         let db = init_db().await.unwrap();
         let repo = feature_flags_repository_factory(&db).await;
@@ -101,7 +92,7 @@ mod test_flag_definition_repository {
         assert!(res.is_ok());
     }
 
-    #[actix_web::test]
+    #[tokio::test]
     async fn repo_update() {
         let db = init_db().await.unwrap();
         let repo = feature_flags_repository_factory(&db).await;
@@ -112,7 +103,6 @@ mod test_flag_definition_repository {
         let entity_to_update = FeatureFlag::new("updated_flag", "Updated flag", true, vec![]);
         let res = repo.update(&inserted_id, &entity_to_update).await;
         assert!(res.is_ok());
-        let updated_flag = res.unwrap();
 
         let updated_item = repo.get(&inserted_id).await;
         assert!(updated_item.is_ok());
@@ -120,7 +110,7 @@ mod test_flag_definition_repository {
         assert_eq!(updated_item.name, "updated_flag");
     }
 
-    #[actix_web::test]
+    #[tokio::test]
     async fn repo_delete() {
         let db = init_db().await.unwrap();
         let repo = feature_flags_repository_factory(&db).await;
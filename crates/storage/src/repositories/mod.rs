@@ -0,0 +1,327 @@
+pub mod audit_repository;
+pub mod environment_repository;
+pub mod feature_flags_repository;
+pub mod pagination;
+pub mod project_repository;
+pub mod revision_counter_repository;
+pub mod revision_repository;
+pub mod usage_repository;
+use crate::repositories::pagination::{Cursor, Page, PageRequest};
+use async_trait::async_trait;
+use futures::stream::TryStreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, to_document_with_options, Document, SerializerOptions};
+use mongodb::error::{Error, WriteFailure};
+use mongodb::options::FindOptions;
+use mongodb::results::InsertOneResult;
+use mongodb::{ClientSession, Collection, Database};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
+#[async_trait]
+pub trait BaseRepository<T>
+where
+    T: Serialize + Serialize + DeserializeOwned + Unpin + Send + Sync,
+{
+    fn collection(&self) -> &Collection<T>;
+
+    async fn create(&self, entity: &T) -> Result<String, RepositoryError> {
+        match self.collection().insert_one(entity, None).await {
+            Ok(res) => Ok(inserted_id(res)),
+            Err(err) if is_duplicate_key(&err) => Err(RepositoryError::new(
+                ErrorKind::AlreadyExists,
+                "Entity already exists".to_string(),
+            )),
+            Err(err) => Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
+        }
+    }
+
+    async fn get(&self, id: &str) -> Result<T, RepositoryError> {
+        let obj_id = match ObjectId::parse_str(id) {
+            Ok(entity_id) => entity_id,
+            Err(err) => {
+                return Err(RepositoryError {
+                    message: err.to_string(),
+                    kind: ErrorKind::NotFound,
+                })
+            }
+        };
+        let filter = doc! {"_id": obj_id};
+        match self.collection().find_one(filter, None).await {
+            Ok(res) => match res {
+                None => Err(RepositoryError {
+                    message: "Entity not found".to_string(),
+                    kind: ErrorKind::NotFound,
+                }),
+                Some(item) => Ok(item),
+            },
+            Err(err) => Err(RepositoryError {
+                message: format!("Error getting entity: {}", err),
+                kind: ErrorKind::NotFound,
+            }),
+        }
+    }
+
+    async fn find(
+        &self,
+        filter: impl Into<Option<Document>> + Send,
+    ) -> Result<Vec<T>, RepositoryError> {
+        match self.collection().find(filter, None).await {
+            Ok(mut cursors) => {
+                let mut res = Vec::<T>::new();
+                while let Some(f) = cursors
+                    .try_next()
+                    .await
+                    .expect("Error mapping through cursor")
+                {
+                    res.push(f)
+                }
+                Ok(res)
+            }
+            Err(err) => Err(RepositoryError {
+                message: err.to_string(),
+                kind: ErrorKind::NotFound,
+            }),
+        }
+    }
+
+    async fn find_page(
+        &self,
+        filter: Document,
+        page: &PageRequest,
+    ) -> Result<Page<T>, RepositoryError> {
+        let total = match self.collection().count_documents(filter.clone(), None).await {
+            Ok(total) => total,
+            Err(err) => return Err(RepositoryError::new(ErrorKind::NotFound, err.to_string())),
+        };
+        let query = match &page.cursor {
+            None => filter,
+            Some(cursor) => doc! {"$and": [filter, cursor.filter(&page.sort)]},
+        };
+        // Fetch one extra item to know whether there is a next page
+        let options = FindOptions::builder()
+            .sort(page.sort.document())
+            .limit(page.limit + 1)
+            .build();
+        let mut items = Vec::<T>::new();
+        match self.collection().find(query, options).await {
+            Ok(mut cursors) => {
+                while let Some(item) = cursors
+                    .try_next()
+                    .await
+                    .expect("Error mapping through cursor")
+                {
+                    items.push(item)
+                }
+            }
+            Err(err) => return Err(RepositoryError::new(ErrorKind::NotFound, err.to_string())),
+        }
+        let mut next_cursor = None;
+        if items.len() as i64 > page.limit {
+            items.truncate(page.limit as usize);
+            next_cursor = items
+                .last()
+                .and_then(|item| stored_document(item).ok())
+                .and_then(|item| Cursor::after(&item, &page.sort))
+                .map(|cursor| cursor.encode());
+        }
+        Ok(Page {
+            items,
+            total,
+            next_cursor,
+        })
+    }
+
+    /// Replaces the entity fields. Entities having a `version` field are only
+    /// updated if the stored version matches it, and the version is incremented.
+    async fn update(&self, id: &str, entity: &T) -> Result<(), RepositoryError> {
+        let (filter, new_doc) = versioned_update(id, entity)?;
+        let res = match self.collection().update_one(filter, new_doc, None).await {
+            Ok(res) => res,
+            Err(err) => return Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
+        };
+        if res.matched_count == 0 {
+            return match self.get(id).await {
+                Ok(_) => Err(version_conflict(id)),
+                Err(err) => Err(err),
+            };
+        }
+        Ok(())
+    }
+
+    /// Same as `create`, as part of the session transaction
+    async fn create_with_session(&self, entity: &T, session: &mut ClientSession) -> Result<String, RepositoryError> {
+        match self.collection().insert_one_with_session(entity, None, session).await {
+            Ok(res) => Ok(inserted_id(res)),
+            Err(err) if is_duplicate_key(&err) => Err(RepositoryError::new(
+                ErrorKind::AlreadyExists,
+                "Entity already exists".to_string(),
+            )),
+            Err(err) => Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
+        }
+    }
+
+    /// Same as `get`, as part of the session transaction
+    async fn get_with_session(&self, id: &str, session: &mut ClientSession) -> Result<T, RepositoryError> {
+        let obj_id = match ObjectId::parse_str(id) {
+            Ok(obj_id) => obj_id,
+            Err(err) => return Err(RepositoryError::new(ErrorKind::NotFound, err.to_string())),
+        };
+        match self.collection().find_one_with_session(doc! {"_id": obj_id}, None, session).await {
+            Ok(Some(item)) => Ok(item),
+            Ok(None) => Err(RepositoryError::new(ErrorKind::NotFound, "Entity not found".to_string())),
+            Err(err) => Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
+        }
+    }
+
+    /// Same as `update`, as part of the session transaction
+    async fn update_with_session(&self, id: &str, entity: &T, session: &mut ClientSession) -> Result<(), RepositoryError> {
+        let (filter, new_doc) = versioned_update(id, entity)?;
+        let res = match self.collection().update_one_with_session(filter, new_doc, None, session).await {
+            Ok(res) => res,
+            Err(err) => return Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
+        };
+        if res.matched_count == 0 {
+            return match self.get_with_session(id, session).await {
+                Ok(_) => Err(version_conflict(id)),
+                Err(err) => Err(err),
+            };
+        }
+        Ok(())
+    }
+
+    /// Same as `delete`, as part of the session transaction
+    async fn delete_with_session(&self, id: &str, session: &mut ClientSession) -> Result<(), RepositoryError> {
+        let obj_id = match ObjectId::parse_str(id) {
+            Ok(obj_id) => obj_id,
+            Err(err) => return Err(RepositoryError::new(ErrorKind::NotFound, err.to_string())),
+        };
+        match self.collection().delete_one_with_session(doc! {"_id": obj_id}, None, session).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
+        }
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), Error> {
+        let obj_id =
+            ObjectId::parse_str(id).expect("Error parsing id as ObjectID, id should be a string");
+        let filter = doc! {"_id": obj_id};
+        self.collection()
+            .find_one_and_delete(filter, None)
+            .await
+            .ok()
+            .unwrap();
+        Ok(())
+    }
+}
+
+pub struct Repository<T> {
+    collection: Collection<T>,
+}
+
+impl<T> BaseRepository<T> for Repository<T>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync,
+{
+    fn collection(&self) -> &Collection<T> {
+        &self.collection
+    }
+}
+
+impl<T> Repository<T> {
+    pub async fn new(db: &Database, collection_name: &str) -> Repository<T> {
+        let collection = init_collection::<T>(db, collection_name).await;
+        Self { collection }
+    }
+}
+
+/// Filter and update document replacing the entity fields, see `BaseRepository::update`
+fn versioned_update<T: Serialize>(id: &str, entity: &T) -> Result<(Document, Document), RepositoryError> {
+    let obj_id = match ObjectId::parse_str(id) {
+        Ok(obj_id) => obj_id,
+        Err(err) => return Err(RepositoryError::new(ErrorKind::NotFound, err.to_string())),
+    };
+    let mut filter = doc! {"_id": obj_id};
+    let mut doc = match stored_document(entity) {
+        Ok(doc) => doc,
+        Err(err) => return Err(RepositoryError::new(ErrorKind::InvalidArgument, err.to_string())),
+    };
+    doc.remove("_id");
+    if let Ok(version) = doc.get_i64("version") {
+        // Documents created before versioning have no version field
+        if version == 0 {
+            filter.insert("version", doc! {"$in": [0_i64, null]});
+        } else {
+            filter.insert("version", version);
+        }
+        doc.insert("version", version + 1);
+    }
+    Ok((filter, doc! {"$set": doc}))
+}
+
+/// Entity as it is stored, ids serialize as hex strings in human readable formats only
+fn stored_document<T: Serialize>(entity: &T) -> mongodb::bson::ser::Result<Document> {
+    let options = SerializerOptions::builder().human_readable(false).build();
+    to_document_with_options(entity, options)
+}
+
+fn version_conflict(id: &str) -> RepositoryError {
+    RepositoryError::new(
+        ErrorKind::VersionConflict,
+        format!("Entity {} was modified by another request", id),
+    )
+}
+
+pub(crate) fn inserted_id(res: InsertOneResult) -> String {
+    res.inserted_id
+        .as_object_id()
+        .expect("Failed parsing object id")
+        .to_string()
+}
+
+/// Whether the write was rejected by a unique index
+pub(crate) fn is_duplicate_key(err: &Error) -> bool {
+    const DUPLICATE_KEY: i32 = 11000;
+    match err.kind.as_ref() {
+        mongodb::error::ErrorKind::Write(WriteFailure::WriteError(err)) => err.code == DUPLICATE_KEY,
+        mongodb::error::ErrorKind::Command(err) => err.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
+
+async fn init_collection<T>(db: &Database, collection_name: &str) -> Collection<T> {
+    let collection: Collection<T> = db.collection(collection_name);
+    collection
+}
+
+#[derive(Clone, Debug)]
+pub struct RepositoryError {
+    pub message: String,
+    pub kind: ErrorKind,
+}
+
+impl RepositoryError {
+    pub fn new(kind: ErrorKind, message: String) -> Self {
+        Self { message, kind }
+    }
+}
+
+impl Display for RepositoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ErrorKind {
+    NotFound,
+    AlreadyExists,
+    InvalidArgument,
+    VersionConflict,
+    /// The entity state does not allow the operation
+    InvalidState,
+    /// The deployment does not support the operation
+    Unsupported,
+    Internal,
+}
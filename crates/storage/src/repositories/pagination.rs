@@ -0,0 +1,153 @@
+use crate::repositories::{ErrorKind, RepositoryError};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Bson, Document};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sort {
+    pub field: String,
+    pub descending: bool,
+}
+
+impl Sort {
+    pub fn new(field: &str, descending: bool) -> Self {
+        Sort {
+            field: field.to_string(),
+            descending,
+        }
+    }
+
+    fn direction(&self) -> i32 {
+        if self.descending {
+            -1
+        } else {
+            1
+        }
+    }
+
+    /// Sort document, `_id` is used as a tie breaker so the ordering is total.
+    pub fn document(&self) -> Document {
+        let mut sort = Document::new();
+        sort.insert(self.field.clone(), self.direction());
+        sort.insert("_id", self.direction());
+        sort
+    }
+}
+
+/// Position of the last item of a page, encoded as an opaque string for clients.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cursor {
+    pub value: Bson,
+    pub id: ObjectId,
+}
+
+impl Cursor {
+    pub fn after(item: &Document, sort: &Sort) -> Option<Self> {
+        let id = item.get_object_id("_id").ok()?;
+        let value = item.get(&sort.field).cloned().unwrap_or(Bson::Null);
+        Some(Cursor { value, id })
+    }
+
+    pub fn encode(&self) -> String {
+        let mut bytes = Vec::new();
+        doc! {"v": self.value.clone(), "id": self.id}
+            .to_writer(&mut bytes)
+            .expect("Error encoding cursor");
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, RepositoryError> {
+        let invalid = || RepositoryError::new(ErrorKind::InvalidArgument, "Invalid cursor".to_string());
+        if cursor.len() % 2 == 1 || !cursor.is_ascii() {
+            return Err(invalid());
+        }
+        let bytes = (0..cursor.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        let document = Document::from_reader(bytes.as_slice()).map_err(|_| invalid())?;
+        let id = document.get_object_id("id").map_err(|_| invalid())?;
+        let value = document.get("v").cloned().ok_or_else(invalid)?;
+        Ok(Cursor { value, id })
+    }
+
+    /// Filter matching the documents placed after this cursor for the given sort.
+    pub fn filter(&self, sort: &Sort) -> Document {
+        let op = if sort.descending { "$lt" } else { "$gt" };
+        doc! {
+            "$or": [
+                { sort.field.clone(): { op: self.value.clone() } },
+                { sort.field.clone(): self.value.clone(), "_id": { op: self.id } },
+            ]
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PageRequest {
+    pub limit: i64,
+    pub sort: Sort,
+    pub cursor: Option<Cursor>,
+}
+
+impl PageRequest {
+    pub fn new(limit: Option<i64>, cursor: Option<&str>, sort: Sort) -> Result<Self, RepositoryError> {
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(RepositoryError::new(
+                ErrorKind::InvalidArgument,
+                format!("limit must be between 1 and {}", MAX_PAGE_SIZE),
+            ));
+        }
+        let cursor = match cursor {
+            None => None,
+            Some(c) => Some(Cursor::decode(c)?),
+        };
+        Ok(PageRequest { limit, sort, cursor })
+    }
+}
+
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: u64,
+    pub next_cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor {
+            value: Bson::String("2022-11-20 10:00:00".to_string()),
+            id: ObjectId::new(),
+        };
+        let encoded = cursor.encode();
+        assert_eq!(Cursor::decode(&encoded).unwrap(), cursor);
+    }
+
+    #[test]
+    fn test_invalid_cursor() {
+        let err = Cursor::decode("not a cursor").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidArgument);
+        assert!(Cursor::decode("abcd").is_err());
+    }
+
+    #[test]
+    fn test_page_request_limits() {
+        let sort = Sort::new("name", false);
+        assert_eq!(PageRequest::new(None, None, sort.clone()).unwrap().limit, DEFAULT_PAGE_SIZE);
+        assert!(PageRequest::new(Some(0), None, sort.clone()).is_err());
+        assert!(PageRequest::new(Some(MAX_PAGE_SIZE + 1), None, sort).is_err());
+    }
+
+    #[test]
+    fn test_sort_document() {
+        let sort = Sort::new("updated_at", true);
+        assert_eq!(sort.document(), doc! {"updated_at": -1, "_id": -1});
+    }
+}
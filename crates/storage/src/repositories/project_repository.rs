@@ -0,0 +1,52 @@
+use crate::repositories::{
+    init_collection, inserted_id, is_duplicate_key, BaseRepository, ErrorKind, RepositoryError,
+};
+use feature_flags_core::models::Project;
+use async_trait::async_trait;
+use mongodb::bson::to_document;
+use mongodb::{Collection, Database};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub async fn project_repository_factory(db: &Database) -> ProjectRepository<Project> {
+    ProjectRepository::<Project>::new(db, "projects").await
+}
+
+pub struct ProjectRepository<T> {
+    pub collection: Collection<T>,
+}
+
+impl<T> ProjectRepository<T> {
+    pub async fn new(db: &Database, collection_name: &str) -> ProjectRepository<T> {
+        let collection = init_collection::<T>(db, collection_name).await;
+        Self { collection }
+    }
+}
+
+#[async_trait]
+impl<T> BaseRepository<T> for ProjectRepository<T>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync,
+{
+    fn collection(&self) -> &Collection<T> {
+        &self.collection
+    }
+
+    async fn create(&self, entity: &T) -> Result<String, RepositoryError> {
+        // Names are unique, enforced by an index created by the migrations
+        match self.collection().insert_one(entity, None).await {
+            Ok(res) => Ok(inserted_id(res)),
+            Err(err) if is_duplicate_key(&err) => {
+                let name = to_document(entity)
+                    .ok()
+                    .and_then(|doc| doc.get_str("name").ok().map(|name| name.to_string()))
+                    .unwrap_or_default();
+                Err(RepositoryError::new(
+                    ErrorKind::AlreadyExists,
+                    format!("Project with name {} already exists", name),
+                ))
+            }
+            Err(err) => Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use crate::repositories::{init_collection, BaseRepository, ErrorKind, RepositoryError};
+use crate::domain::sync::{ChangedEntity, RevisionCounter, MAX_CHANGES};
+use mongodb::bson::{doc, to_bson};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use futures::TryStreamExt;
+use mongodb::{Collection, Database};
+
+pub async fn revision_counter_repository_factory(db: &Database) -> RevisionCounterRepository<RevisionCounter> {
+    RevisionCounterRepository::<RevisionCounter>::new(db, "revision_counters").await
+}
+
+pub struct RevisionCounterRepository<T> {
+    pub collection: Collection<T>,
+}
+
+impl<T> RevisionCounterRepository<T> {
+    pub async fn new(db: &Database, collection_name: &str) -> RevisionCounterRepository<T> {
+        let collection = init_collection::<T>(db, collection_name).await;
+        Self { collection }
+    }
+}
+
+impl BaseRepository<RevisionCounter> for RevisionCounterRepository<RevisionCounter> {
+    fn collection(&self) -> &Collection<RevisionCounter> {
+        &self.collection
+    }
+}
+
+impl RevisionCounterRepository<RevisionCounter> {
+    /// Increments the revision of the project and records what changed, creating its
+    /// counter on the first write
+    pub async fn bump(&self, project: &str, changes: &[ChangedEntity]) -> Result<i64, RepositoryError> {
+        let change = to_bson(changes).map_err(|err| RepositoryError::new(ErrorKind::Internal, err.to_string()))?;
+        let update = doc! {
+            "$inc": {"revision": 1},
+            "$push": {"changes": {"$each": [change], "$slice": -(MAX_CHANGES as i64)}},
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .projection(doc! {"changes": 0})
+            .build();
+        match self.collection.find_one_and_update(doc! {"_id": project}, update, options).await {
+            Ok(Some(counter)) => Ok(counter.revision),
+            Ok(None) => Err(RepositoryError::new(ErrorKind::Internal, "Revision counter not created".to_string())),
+            Err(err) => Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
+        }
+    }
+
+    /// Revisions of every project, without their changes
+    pub async fn revisions(&self) -> Result<Vec<RevisionCounter>, RepositoryError> {
+        let options = FindOptions::builder().projection(doc! {"changes": 0}).build();
+        let cursor = self.collection.find(None, options).await
+            .map_err(|err| RepositoryError::new(ErrorKind::Internal, err.to_string()))?;
+        cursor.try_collect().await.map_err(|err| RepositoryError::new(ErrorKind::Internal, err.to_string()))
+    }
+
+    pub async fn get(&self, project: &str) -> Result<Option<RevisionCounter>, RepositoryError> {
+        self.collection.find_one(doc! {"_id": project}, None).await
+            .map_err(|err| RepositoryError::new(ErrorKind::Internal, err.to_string()))
+    }
+}
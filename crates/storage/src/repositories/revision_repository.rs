@@ -0,0 +1,70 @@
+use crate::repositories::{init_collection, BaseRepository, ErrorKind, RepositoryError};
+use crate::domain::history::Revision;
+use feature_flags_core::models::{Environment, FeatureFlag};
+use futures::stream::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub async fn feature_flag_revisions_repository_factory(db: &Database) -> RevisionRepository<Revision<FeatureFlag>> {
+    RevisionRepository::<Revision<FeatureFlag>>::new(db, "feature_flag_revisions").await
+}
+
+pub async fn environment_revisions_repository_factory(db: &Database) -> RevisionRepository<Revision<Environment>> {
+    RevisionRepository::<Revision<Environment>>::new(db, "environment_revisions").await
+}
+
+pub struct RevisionRepository<T> {
+    pub collection: Collection<T>,
+}
+
+impl<T> RevisionRepository<T> {
+    pub async fn new(db: &Database, collection_name: &str) -> RevisionRepository<T> {
+        let collection = init_collection::<T>(db, collection_name).await;
+        Self { collection }
+    }
+}
+
+impl<T> BaseRepository<T> for RevisionRepository<T>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync,
+{
+    fn collection(&self) -> &Collection<T> {
+        &self.collection
+    }
+}
+
+impl<T> RevisionRepository<T>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync,
+{
+    /// Revisions of an entity, most recent first
+    pub async fn find_by_entity(&self, entity_id: &ObjectId) -> Result<Vec<T>, RepositoryError> {
+        let options = FindOptions::builder().sort(doc! {"revision": -1}).build();
+        match self.collection.find(doc! {"entity_id": entity_id}, options).await {
+            Ok(cursor) => cursor
+                .try_collect()
+                .await
+                .map_err(|err| RepositoryError::new(ErrorKind::Internal, err.to_string())),
+            Err(err) => Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
+        }
+    }
+
+    pub async fn find_revision(&self, entity_id: &ObjectId, revision: i64) -> Result<T, RepositoryError> {
+        match self
+            .collection
+            .find_one(doc! {"entity_id": entity_id, "revision": revision}, None)
+            .await
+        {
+            Ok(Some(item)) => Ok(item),
+            Ok(None) => Err(RepositoryError::new(
+                ErrorKind::NotFound,
+                format!("Revision {} not found", revision),
+            )),
+            Err(err) => Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::repositories::{init_collection, BaseRepository, ErrorKind, RepositoryError};
+use crate::domain::usage::FlagUsage;
+use feature_flags_core::utils::date_format;
+use chrono::{DateTime, Utc};
+use mongodb::bson::doc;
+use mongodb::options::UpdateOptions;
+use mongodb::{Collection, Database};
+
+pub async fn usage_repository_factory(db: &Database) -> UsageRepository<FlagUsage> {
+    UsageRepository::<FlagUsage>::new(db, "flag_usage").await
+}
+
+pub struct UsageRepository<T> {
+    pub collection: Collection<T>,
+}
+
+impl<T> UsageRepository<T> {
+    pub async fn new(db: &Database, collection_name: &str) -> UsageRepository<T> {
+        let collection = init_collection::<T>(db, collection_name).await;
+        Self { collection }
+    }
+}
+
+impl BaseRepository<FlagUsage> for UsageRepository<FlagUsage> {
+    fn collection(&self) -> &Collection<FlagUsage> {
+        &self.collection
+    }
+}
+
+impl UsageRepository<FlagUsage> {
+    /// Adds evaluations of a flag, creating its usage on the first ones
+    pub async fn increment(
+        &self,
+        project: &str,
+        name: &str,
+        evaluations: i64,
+        evaluated_at: &DateTime<Utc>,
+    ) -> Result<(), RepositoryError> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        // Dates are stored as strings sorting chronologically, so `$max` keeps the latest
+        let update = doc! {
+            "$inc": {"evaluations": evaluations},
+            "$max": {"last_evaluated_at": date_format::format(evaluated_at)},
+        };
+        match self.collection.update_one(doc! {"project": project, "name": name}, update, options).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(RepositoryError::new(ErrorKind::Internal, err.to_string())),
+        }
+    }
+}
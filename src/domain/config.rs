@@ -0,0 +1,136 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use feature_flags_core::models::{Environment, FeatureFlag, FlagOverride, Lifecycle, Rule};
+use feature_flags_core::utils;
+
+/// Version of the configuration document format, increased on incompatible changes
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Configuration of one project. Entities are identified by name so the document
+/// can be loaded in the project of the same name of another instance.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Configuration {
+    pub version: u32,
+    /// Exported project, documents without one can be imported in any project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    #[serde(with = "utils::date_format", default = "Utc::now")]
+    pub exported_at: DateTime<Utc>,
+    #[serde(default)]
+    pub feature_flags: Vec<FlagConfiguration>,
+    #[serde(default)]
+    pub environments: Vec<EnvironmentConfiguration>,
+}
+
+impl Configuration {
+    pub fn new(project: &str, flags: &[FeatureFlag], envs: &[Environment]) -> Self {
+        let mut feature_flags: Vec<FlagConfiguration> = flags.iter().map(FlagConfiguration::from).collect();
+        feature_flags.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut environments: Vec<EnvironmentConfiguration> = envs.iter().map(EnvironmentConfiguration::from).collect();
+        environments.sort_by(|a, b| a.name.cmp(&b.name));
+        Configuration {
+            version: CONFIG_VERSION,
+            project: Some(project.to_string()),
+            exported_at: Utc::now(),
+            feature_flags,
+            environments,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FlagConfiguration {
+    pub name: String,
+    pub label: String,
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub links: Vec<String>,
+    #[serde(default)]
+    pub lifecycle: Lifecycle,
+    #[serde(with = "utils::optional_date_format", default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl FlagConfiguration {
+    /// Sets the configured fields of the flag
+    pub fn apply(&self, flag: &mut FeatureFlag) {
+        flag.label = self.label.clone();
+        flag.enabled = self.enabled;
+        flag.rules = self.rules.clone();
+        flag.description = self.description.clone();
+        flag.tags = self.tags.clone();
+        flag.owner = self.owner.clone();
+        flag.links = self.links.clone();
+        flag.lifecycle = self.lifecycle;
+        flag.expires_at = self.expires_at;
+        flag.set_archived(self.archived);
+    }
+
+    pub fn to_flag(&self) -> FeatureFlag {
+        let mut flag = FeatureFlag::new(&self.name, &self.label, self.enabled, vec![]);
+        self.apply(&mut flag);
+        flag
+    }
+}
+
+impl From<&FeatureFlag> for FlagConfiguration {
+    fn from(flag: &FeatureFlag) -> Self {
+        FlagConfiguration {
+            name: flag.name.clone(),
+            label: flag.label.clone(),
+            enabled: flag.enabled,
+            rules: flag.rules.clone(),
+            description: flag.description.clone(),
+            tags: flag.tags.clone(),
+            owner: flag.owner.clone(),
+            links: flag.links.clone(),
+            lifecycle: flag.lifecycle,
+            expires_at: flag.expires_at,
+            archived: flag.archived,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentConfiguration {
+    pub name: String,
+    #[serde(default)]
+    pub flags: Vec<FlagOverride>,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl EnvironmentConfiguration {
+    /// Sets the configured fields of the environment
+    pub fn apply(&self, env: &mut Environment) {
+        env.set_overrides(self.flags.clone());
+        env.archived = self.archived;
+    }
+
+    pub fn to_environment(&self) -> Environment {
+        let mut env = Environment::new(&self.name);
+        self.apply(&mut env);
+        env
+    }
+}
+
+impl From<&Environment> for EnvironmentConfiguration {
+    fn from(env: &Environment) -> Self {
+        EnvironmentConfiguration {
+            name: env.name.clone(),
+            flags: env.flags.clone(),
+            archived: env.archived,
+        }
+    }
+}
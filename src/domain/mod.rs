@@ -1 +1 @@
-pub(crate) mod models;
+pub(crate) mod config;
@@ -1,48 +1,141 @@
-mod adapters;
-mod database;
 mod domain;
 mod resources;
 mod services;
+mod snapshot;
 mod utils;
 
-use std::collections::HashMap;
 use std::sync::Mutex;
-use crate::database::init_db;
-use crate::resources::{feature_flags_api, environments_api, client_api};
+use std::time::Duration;
+use feature_flags_storage::database::{init_client_and_db, init_db};
+use crate::resources::{audit_api, cache_api, config_api, feature_flags_api, environments_api, client_api, projects_api, reports_api, sdk_api};
+use actix_web::dev::Service;
 use actix_web::{web, App, HttpServer, http};
-use mongodb::Database;
+use mongodb::{Client, Database};
 use actix_cors::Cors;
 use actix_web::web::Data;
-use crate::domain::models::{Environment, FeatureFlag};
+use feature_flags_storage::domain::usage::EvaluationCounter;
+use crate::snapshot::{Snapshots, DEFAULT_CACHE_TTL_SECONDS, DEFAULT_MAX_STALENESS_SECONDS};
 
+/// State shared by the workers, nothing is locked across `.await`
 struct AppState {
     app_name: String,
+    client: Client,
     db: Database,
-    flags: Vec<FeatureFlag>,
-    envs: HashMap<String, Environment>
+    /// Flags and environments used for evaluation
+    snapshots: Snapshots,
+    /// Evaluations not stored yet, see `client_api::flush_evaluations`
+    evaluations: Mutex<EvaluationCounter>,
 }
 
-async fn get_state() -> Data<Mutex<AppState>> {
-    Data::new(Mutex::new(AppState {
+async fn get_state() -> Data<AppState> {
+    let (client, db) = init_client_and_db().await.unwrap();
+    Data::new(AppState {
         app_name: String::from("Feature Flags"),
-        db: init_db().await.unwrap(),
-        flags: Vec::new(),
-        envs: HashMap::new()
-    }))
+        client,
+        db,
+        snapshots: Snapshots::new(cache_ttl(), Some(max_staleness())),
+        evaluations: Mutex::new(EvaluationCounter::default()),
+    })
+}
+
+/// Age after which cached flags and environments are reloaded
+fn cache_ttl() -> Duration {
+    env_seconds("CACHE_TTL_SECONDS", DEFAULT_CACHE_TTL_SECONDS)
+}
+
+/// Longest time writes made by other replicas may go unnoticed
+fn max_staleness() -> Duration {
+    env_seconds("CACHE_MAX_STALENESS_SECONDS", DEFAULT_MAX_STALENESS_SECONDS)
+}
+
+fn env_seconds(name: &str, default: u64) -> Duration {
+    let seconds = std::env::var(name)
+        .ok()
+        .and_then(|seconds| seconds.parse().ok())
+        .unwrap_or(default);
+    Duration::from_secs(seconds)
 }
 
+/// How often evaluation counts are stored
+const EVALUATIONS_FLUSH_SECONDS: u64 = 60;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // Migrations run when connecting, `--migrate` exits once they are applied
+    if std::env::args().any(|arg| arg == "--migrate") {
+        init_db().await.expect("Error running migrations");
+        return Ok(());
+    }
     let app_data = get_state().await;
+    let flush_data = Data::clone(&app_data);
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(EVALUATIONS_FLUSH_SECONDS));
+        loop {
+            interval.tick().await;
+            // Counts are kept in memory and retried on the next tick when storing fails
+            let _ = client_api::flush_evaluations(&flush_data).await;
+        }
+    });
+    let refresh_data = Data::clone(&app_data);
+    actix_web::rt::spawn(async move {
+        let period = (refresh_data.snapshots.ttl() / 2).max(Duration::from_secs(1));
+        let mut interval = actix_web::rt::time::interval(period);
+        loop {
+            interval.tick().await;
+            // Entries failing to reload keep being served and are retried on the next tick
+            let _ = cache_api::refresh(&refresh_data).await;
+        }
+    });
+    // Revisions are read twice per staleness period, so one slow read does not stop the cache
+    let staleness = max_staleness();
+    let sync_data = Data::clone(&app_data);
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval((staleness / 2).max(Duration::from_millis(100)));
+        loop {
+            interval.tick().await;
+            let _ = cache_api::sync(&sync_data).await;
+        }
+    });
+    // Change streams pick up writes right away on replica sets, polling covers standalone servers
+    let watch_data = Data::clone(&app_data);
+    actix_web::rt::spawn(async move {
+        loop {
+            let _ = cache_api::watch(&watch_data).await;
+            actix_web::rt::time::sleep(staleness).await;
+        }
+    });
     HttpServer::new(move || {
         let cors = get_cors();
         App::new()
+            .wrap_fn(|mut req, srv| {
+                let request_id = resources::ensure_request_id(&mut req);
+                let res = srv.call(req);
+                async move {
+                    let mut res = res.await?;
+                    res.headers_mut().insert(
+                        http::header::HeaderName::from_static(resources::REQUEST_ID_HEADER),
+                        request_id,
+                    );
+                    Ok(res)
+                }
+            })
+            .wrap_fn(|mut req, srv| {
+                resources::scope_project(&mut req);
+                srv.call(req)
+            })
             .wrap(cors)
             .app_data(Data::clone(&app_data))
             .service(client_api::create_scope())
+            .service(feature_flags_api::create_batch_resource())
+            .service(feature_flags_api::create_tags_resource())
             .service(feature_flags_api::create_scope())
             .service(environments_api::create_scope())
+            .service(audit_api::create_scope())
+            .service(projects_api::create_scope())
+            .service(reports_api::create_scope())
+            .service(sdk_api::create_scope())
+            .service(cache_api::create_scope())
+            .service(config_api::create_scope())
     })
     .bind(("0.0.0.0", 8080))?
     .run()
@@ -71,5 +164,9 @@ fn get_cors() -> Cors {
         .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
         .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
         .allowed_header(http::header::CONTENT_TYPE)
+        .allowed_header(http::header::IF_MATCH)
+        .allowed_header("X-Actor")
+        .allowed_header("X-Request-Id")
+        .expose_headers(vec![http::header::ETAG, http::header::HeaderName::from_static("x-request-id")])
         .max_age(3600)
 }
\ No newline at end of file
@@ -0,0 +1,185 @@
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse, Scope};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use feature_flags_storage::repositories::audit_repository::audit_repository_factory;
+use feature_flags_storage::repositories::pagination::{PageRequest, Sort};
+use feature_flags_storage::domain::audit::AuditEntry;
+use crate::resources::CustomError;
+use crate::services::audit_handlers;
+use crate::AppState;
+
+async fn find(
+    data: web::Data<AppState>,
+    query: web::Query<AuditListQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let filters = query.filters()?;
+    // Most recent entries first
+    let page = PageRequest::new(query.limit, query.cursor.as_deref(), Sort::new("created_at", true))
+        .map_err(|err| CustomError::BadRequest(err.to_string()))?;
+    let db = &data.db;
+    let repo = audit_repository_factory(db).await;
+    let page = audit_handlers::find_page(&repo, &filters, &page).await?;
+    Ok(HttpResponse::Ok().json(AuditList {
+        items: page.items,
+        total: page.total,
+        next_cursor: page.next_cursor,
+    }))
+}
+
+/// Streams the matching entries as newline delimited JSON, oldest first
+async fn export(
+    data: web::Data<AppState>,
+    query: web::Query<AuditListQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let filters = query.filters()?;
+    let db = &data.db;
+    let repo = audit_repository_factory(db).await;
+    let cursor = audit_handlers::export(&repo, &filters).await?;
+    let lines = cursor.map(|entry| {
+        let entry = entry.map_err(actix_web::error::ErrorInternalServerError)?;
+        let mut line = serde_json::to_vec(&entry).map_err(actix_web::error::ErrorInternalServerError)?;
+        line.push(b'\n');
+        Ok::<Bytes, actix_web::Error>(Bytes::from(line))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines))
+}
+
+pub fn create_scope() -> Scope {
+    web::scope("/admin/audit")
+        .route("", web::get().to(find))
+        .route("/export", web::get().to(export))
+}
+
+#[derive(Serialize, Deserialize)]
+struct AuditList {
+    items: Vec<AuditEntry>,
+    total: u64,
+    next_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AuditListQuery {
+    limit: Option<i64>,
+    cursor: Option<String>,
+    project: Option<String>,
+    actor: Option<String>,
+    action: Option<String>,
+    target_kind: Option<String>,
+    target_id: Option<String>,
+    target_name: Option<String>,
+    /// RFC 3339 date, entries created at or after it
+    from: Option<String>,
+    /// RFC 3339 date, entries created at or before it
+    to: Option<String>,
+}
+
+impl AuditListQuery {
+    fn filters(&self) -> Result<audit_handlers::Filters, CustomError> {
+        let target_id = match &self.target_id {
+            None => None,
+            Some(id) => Some(ObjectId::parse_str(id).map_err(|_| {
+                CustomError::BadRequest("target_id must be a valid id".to_string())
+            })?),
+        };
+        Ok(audit_handlers::Filters {
+            project: self.project.clone(),
+            actor: self.actor.clone(),
+            action: self.action.clone(),
+            target_kind: self.target_kind.clone(),
+            target_id,
+            target_name: self.target_name.clone(),
+            from: parse_date("from", self.from.as_deref())?,
+            to: parse_date("to", self.to.as_deref())?,
+        })
+    }
+}
+
+fn parse_date(name: &str, value: Option<&str>) -> Result<Option<DateTime<Utc>>, CustomError> {
+    match value {
+        None => Ok(None),
+        Some(value) => DateTime::parse_from_rfc3339(value)
+            .map(|date| Some(date.with_timezone(&Utc)))
+            .map_err(|_| CustomError::BadRequest(format!("{} must be an RFC 3339 date", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_state;
+    use crate::resources::feature_flags_api;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use mongodb::bson::doc;
+    use serde_json::json;
+    use feature_flags_core::models::DEFAULT_PROJECT;
+
+    #[actix_web::test]
+    async fn test_audit_resource() {
+        let state = get_state().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&state))
+                .service(feature_flags_api::create_scope())
+                .service(create_scope()),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags")
+            .insert_header(("X-Actor", "audit_api_test"))
+            .insert_header(("X-Request-Id", "audit-api-test-request"))
+            .set_json(json!({
+                "name": "audit_api_test_flag",
+                "label": "Audit test",
+                "enabled": false,
+                "rules": [],
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/audit?actor=audit_api_test&action=feature_flag.create")
+            .to_request();
+        let resp: AuditList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.total, 1);
+        assert_eq!(resp.items[0].request_id, "audit-api-test-request");
+        assert_eq!(resp.items[0].project, DEFAULT_PROJECT);
+        assert_eq!(resp.items[0].target.name, "audit_api_test_flag");
+        assert!(resp.items[0].before.is_none());
+
+        let req = test::TestRequest::get()
+            .uri("/admin/audit?actor=audit_api_test&project=other_audit_project")
+            .to_request();
+        let resp: AuditList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.total, 0);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/audit/export?actor=audit_api_test")
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let lines: Vec<&[u8]> = body.split(|b| *b == b'\n').filter(|l| !l.is_empty()).collect();
+        assert_eq!(lines.len(), 1);
+
+        let req = test::TestRequest::get().uri("/admin/audit?from=yesterday").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let db = &state.db;
+        db.collection::<AuditEntry>("audit_log").delete_many(doc! {"actor": "audit_api_test"}, None).await.unwrap();
+        db.collection::<AuditEntry>("feature_flags").delete_one(doc! {"name": "audit_api_test_flag"}, None).await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_parse_date() {
+        assert!(parse_date("from", None).unwrap().is_none());
+        assert!(parse_date("from", Some("2022-11-20T10:00:00Z")).unwrap().is_some());
+        assert!(matches!(parse_date("from", Some("yesterday")), Err(CustomError::BadRequest(_))));
+    }
+}
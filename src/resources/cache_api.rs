@@ -0,0 +1,36 @@
+use actix_web::web::Data;
+use actix_web::{web, HttpResponse, Scope};
+use feature_flags_storage::repositories::environment_repository::environment_repository_factory;
+use feature_flags_storage::repositories::feature_flags_repository::feature_flags_repository_factory;
+use feature_flags_storage::repositories::revision_counter_repository::revision_counter_repository_factory;
+use crate::resources::CustomError;
+use crate::services::{cache_handlers, sync_handlers, ServiceError};
+use crate::AppState;
+
+async fn metrics(data: web::Data<AppState>) -> Result<HttpResponse, CustomError> {
+    Ok(HttpResponse::Ok().json(data.snapshots.metrics()))
+}
+
+/// Reloads the cached entries older than the TTL
+pub async fn refresh(data: &Data<AppState>) -> Result<(), ServiceError> {
+    let repo = feature_flags_repository_factory(&data.db).await;
+    let env_repo = environment_repository_factory(&data.db).await;
+    cache_handlers::refresh(&data.snapshots, &repo, &env_repo).await
+}
+
+/// Drops the entries of the projects changed by other replicas
+pub async fn sync(data: &Data<AppState>) -> Result<(), ServiceError> {
+    let repo = revision_counter_repository_factory(&data.db).await;
+    sync_handlers::sync(&repo, &data.snapshots).await
+}
+
+/// Follows the revisions with a change stream, returns when it fails or is not supported
+pub async fn watch(data: &Data<AppState>) -> Result<(), ServiceError> {
+    let repo = revision_counter_repository_factory(&data.db).await;
+    sync_handlers::watch(&repo, &data.snapshots).await
+}
+
+pub fn create_scope() -> Scope {
+    web::scope("/admin/cache")
+        .route("", web::get().to(metrics))
+}
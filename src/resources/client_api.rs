@@ -1,107 +1,358 @@
-use std::sync::Mutex;
-use actix_web::{HttpResponse, Scope, web};
-use actix_web::web::{Data, Json};
-use mongodb::bson::doc;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+use actix_web::{guard, HttpMessage, HttpRequest, HttpResponse, Scope, web};
+use actix_web::http::header::{self, EntityTag, ETag, IfNoneMatch};
+use actix_web::web::{Bytes, Data, Json};
 use serde_json::{Map, Value};
-use crate::adapters::repositories::{BaseRepository, RepositoryError};
-use crate::adapters::repositories::feature_flags_repository::feature_flags_repository_factory;
+use feature_flags_storage::repositories::feature_flags_repository::feature_flags_repository_factory;
 use crate::AppState;
-use crate::domain::models::{Environment, FeatureFlag};
-use crate::resources::CustomError;
+use crate::resources::{client_ws, CustomError, ProjectScope};
 use serde::{Serialize, Deserialize};
-use crate::adapters::repositories::environment_repository::environment_repository_factory;
-use crate::services::{environment_handlers, ServiceError};
+use feature_flags_storage::repositories::environment_repository::environment_repository_factory;
+use feature_flags_storage::repositories::usage_repository::usage_repository_factory;
+use feature_flags_storage::repositories::ErrorKind;
+use feature_flags_core::models::{Environment, FeatureFlag};
+use crate::services::{cache_handlers, usage_handlers, ServiceError};
+use crate::utils::{hash_json, StableHasher};
+use tokio::sync::watch;
 
 async fn get_flags_from_context(
-    data: web::Data<Mutex<AppState>>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
     body: Json<FeatureFlagsContextSchema>,
 ) -> Result<HttpResponse, CustomError> {
 
-    match get_all_flags(&data).await {
+    let repo = feature_flags_repository_factory(&data.db).await;
+    match cache_handlers::flags(&data.snapshots, &repo, &project.0).await {
         Ok(all_flags) => {
             let mut valid_flags = Map::new();
-            for flag in all_flags {
+            for flag in all_flags.iter().filter(|flag| flag.enabled) {
                 valid_flags.insert(
                     String::from(&flag.name),
                     Value::Bool(flag.is_context_valid(&body.context))
                 );
             }
-            Ok(HttpResponse::Ok().json(Json(valid_flags)))
+            data.evaluations.lock().unwrap().record(&project.0, valid_flags.keys());
+            let tag = evaluation_tag(&all_flags, None, &body.context);
+            Ok(evaluation_response(&req, tag, valid_flags))
         }
-        Err(err) => Err(CustomError::ApplicationError)
+        Err(_) => Err(CustomError::ApplicationError)
     }
 }
 
-async fn get_all_flags(data: &Data<Mutex<AppState>>) -> Result<Vec<FeatureFlag>, RepositoryError> {
-    let mut app_data = data.lock().unwrap();
-    if app_data.flags.is_empty() {
-        let db = &app_data.db;
-        let repo = feature_flags_repository_factory(db).await;
-        return match repo.find(doc! {"enabled": true}).await {
-            Ok(all_flags) => {
-                app_data.flags = all_flags;
-                Ok(app_data.flags.clone())
-                // Ok(all_flags)
-            }
-            Err(err) => Err(err)
-        }
-    }
-    Ok(app_data.flags.clone())
-}
-
 async fn get_environment_flags_from_context(
-    data: web::Data<Mutex<AppState>>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
     body: Json<FeatureFlagsContextSchema>,
     environment_name: web::Path<String>,
 ) -> Result<HttpResponse, CustomError> {
     let name = environment_name.into_inner();
 
-    match get_environment(&data, &name).await {
+    let env_repo = environment_repository_factory(&data.db).await;
+    let repo = feature_flags_repository_factory(&data.db).await;
+    match cache_handlers::environment(&data.snapshots, &env_repo, &project.0, &name).await {
         Ok(env) => {
-            match get_all_flags(&data).await {
+            match cache_handlers::flags(&data.snapshots, &repo, &project.0).await {
                 Ok(all_flags) => {
-                    let mut valid_flags = env.get_flags_from_context(&body.context);
-                    for flag in all_flags {
-                        if !valid_flags.contains_key(&flag.name) {
-                            valid_flags.insert(
-                                String::from(&flag.name),
-                                Value::Bool(flag.is_context_valid(&body.context))
-                            );
-                        }
-                    }
-                    Ok(HttpResponse::Ok().json(Json(valid_flags)))
+                    let valid_flags = env.get_flags_from_context(&all_flags, &body.context);
+                    data.evaluations.lock().unwrap().record(&project.0, valid_flags.keys());
+                    let tag = evaluation_tag(&all_flags, Some(&env), &body.context);
+                    Ok(evaluation_response(&req, tag, valid_flags))
                 }
-                Err(err) => Err(CustomError::ApplicationError)
+                Err(_) => Err(CustomError::ApplicationError)
             }
         },
-        Err(_) => return Err(CustomError::NotFound)
+        Err(_) => Err(CustomError::NotFound)
+    }
+}
+
+async fn evaluate_flag(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    body: Json<FeatureFlagsContextSchema>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, CustomError> {
+    let (environment_name, flag_name) = path.into_inner();
+    evaluate_single(&data, &project, &environment_name, &flag_name, &body.context).await
+}
+
+/// Same as `evaluate_flag` with the context taken from the query parameters
+async fn evaluate_flag_from_query(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    query: web::Query<Vec<(String, String)>>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, CustomError> {
+    let (environment_name, flag_name) = path.into_inner();
+    let context = query_context(query.into_inner());
+    evaluate_single(&data, &project, &environment_name, &flag_name, &context).await
+}
+
+/// Evaluates a single flag of the environment, archived flags are not found
+async fn evaluate_single(
+    data: &AppState,
+    project: &ProjectScope,
+    environment_name: &str,
+    flag_name: &str,
+    context: &Map<String, Value>,
+) -> Result<HttpResponse, CustomError> {
+    let env_repo = environment_repository_factory(&data.db).await;
+    let repo = feature_flags_repository_factory(&data.db).await;
+    let env = cache_handlers::environment(&data.snapshots, &env_repo, &project.0, environment_name).await?;
+    let all_flags = cache_handlers::flags(&data.snapshots, &repo, &project.0).await?;
+    let flag = all_flags.iter().find(|flag| flag.name == flag_name).ok_or(CustomError::NotFound)?;
+    let evaluation = env.evaluate(flag, context);
+    data.evaluations.lock().unwrap().record(&project.0, [&evaluation.flag]);
+    Ok(HttpResponse::Ok().json(evaluation))
+}
+
+/// Context of query parameters, repeated parameters become arrays
+fn query_context(params: Vec<(String, String)>) -> Map<String, Value> {
+    let mut context = Map::new();
+    for (key, value) in params {
+        match context.get_mut(&key) {
+            Some(Value::Array(values)) => values.push(Value::String(value)),
+            Some(previous) => {
+                let first = previous.take();
+                *previous = Value::Array(vec![first, Value::String(value)]);
+            }
+            None => {
+                context.insert(key, Value::String(value));
+            }
+        }
     }
+    context
 }
 
-async fn get_environment(data: &Data<Mutex<AppState>>, environment_name: &str) -> Result<Environment, ServiceError> {
-    let mut app_data = data.lock().unwrap();
-    if app_data.envs.contains_key(environment_name) {
-        let env = app_data.envs.get(environment_name).unwrap();
-        return Ok(Environment {
-            id: env.id.clone(),
-            name: env.name.clone(),
-            flags: env.flags.clone()
-        });
+/// Evaluates many contexts against the same flags and environment, in the order they are given
+async fn get_environment_flags_batch(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    limits: web::Data<BatchLimits>,
+    body: Json<BatchEvaluationSchema>,
+    environment_name: web::Path<String>,
+) -> Result<HttpResponse, CustomError> {
+    if body.contexts.is_empty() || body.contexts.len() > limits.max_contexts {
+        return Err(CustomError::BadRequest(format!(
+            "contexts must contain between 1 and {} items", limits.max_contexts
+        )));
+    }
+    let env_repo = environment_repository_factory(&data.db).await;
+    let repo = feature_flags_repository_factory(&data.db).await;
+    let env = cache_handlers::environment(&data.snapshots, &env_repo, &project.0, &environment_name).await?;
+    let all_flags = cache_handlers::flags(&data.snapshots, &repo, &project.0).await?;
+    let selected: Vec<FeatureFlag>;
+    let flags = match &body.flags {
+        Some(names) => {
+            selected = all_flags.iter().filter(|flag| names.contains(&flag.name)).cloned().collect();
+            &selected[..]
+        }
+        None => &all_flags[..],
+    };
+    let items: Vec<Map<String, Value>> = body.contexts
+        .iter()
+        .map(|context| env.get_flags_from_context(flags, context))
+        .collect();
+    let mut evaluations = data.evaluations.lock().unwrap();
+    for item in &items {
+        evaluations.record(&project.0, item.keys());
     }
-    let db = &app_data.db;
-    let repo = environment_repository_factory(db).await;
+    Ok(HttpResponse::Ok().json(BatchEvaluationResponse { items }))
+}
 
-    match environment_handlers::get_by_name(&repo, environment_name).await {
-        Ok(env) => {
-            app_data.envs.insert(env.name.clone(), Environment {
-                id: env.id.clone(),
-                name: env.name.clone(),
-                flags: env.flags.clone()
-            });
-            Ok(env)
-        },
-        Err(err) => return Err(err)
+/// Hash of the versions of the evaluated flags and environment and of the context, the
+/// same on every replica serving the same data
+fn evaluation_tag(flags: &[FeatureFlag], env: Option<&Environment>, context: &Map<String, Value>) -> ETag {
+    ETag(EntityTag::new_strong(evaluation_hash(flags, env, context)))
+}
+
+fn evaluation_hash(flags: &[FeatureFlag], env: Option<&Environment>, context: &Map<String, Value>) -> String {
+    let mut hasher = StableHasher::default();
+    let mut flags: Vec<&FeatureFlag> = flags.iter().collect();
+    flags.sort_by(|a, b| a.name.cmp(&b.name));
+    for flag in flags {
+        (&flag.name, flag.id.map(|id| id.bytes()), flag.version).hash(&mut hasher);
+    }
+    if let Some(env) = env {
+        (&env.name, env.id.map(|id| id.bytes()), env.version).hash(&mut hasher);
+    }
+    hash_json(&Value::Object(context.clone()), &mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Evaluated flags, or `304 Not Modified` when the `If-None-Match` header matches the tag
+fn evaluation_response(req: &HttpRequest, tag: ETag, flags: Map<String, Value>) -> HttpResponse {
+    let not_modified = match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(items)) => items.iter().any(|item| item.weak_eq(&tag)),
+        None => false,
+    };
+    if not_modified {
+        return HttpResponse::NotModified().insert_header(tag).finish();
+    }
+    HttpResponse::Ok().insert_header(tag).json(Json(flags))
+}
+
+/// Interval of the comments keeping idle event streams open
+const KEEP_ALIVE_SECONDS: u64 = 15;
+
+/// Server-Sent Events of the changes of the environment flags. With a context, events hold
+/// the values evaluated for it, otherwise the definitions changed since the previous event.
+/// Event ids identify the configuration, so resuming with `Last-Event-ID` skips the first
+/// event when nothing changed meanwhile.
+async fn stream_environment_flags(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    query: web::Query<StreamQuery>,
+    environment_name: web::Path<String>,
+) -> Result<HttpResponse, CustomError> {
+    let context = match &query.context {
+        Some(context) => Some(
+            serde_json::from_str::<Map<String, Value>>(context)
+                .map_err(|_| CustomError::BadRequest("context must be a JSON object".to_string()))?,
+        ),
+        None => None,
+    };
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    let mut stream = FlagStream {
+        changes: data.snapshots.subscribe(),
+        data,
+        project: project.0,
+        environment_name: environment_name.into_inner(),
+        context,
+        last_event_id,
+        definitions: HashMap::new(),
+    };
+    // Unknown environments are rejected before the stream starts
+    let first = stream.next_event().await?;
+    let events = futures::stream::unfold((stream, first), |(mut stream, pending)| async move {
+        let event = match pending {
+            Some(event) => event,
+            None => stream.wait_event().await?,
+        };
+        Some((Ok::<_, actix_web::Error>(event), (stream, None)))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events))
+}
+
+/// Events of one client, see `stream_environment_flags`
+struct FlagStream {
+    data: Data<AppState>,
+    project: String,
+    environment_name: String,
+    context: Option<Map<String, Value>>,
+    last_event_id: Option<String>,
+    /// Definitions sent so far, by flag name
+    definitions: HashMap<String, FeatureFlag>,
+    changes: watch::Receiver<u64>,
+}
+
+impl FlagStream {
+    /// Event of the current configuration, none when the client already has it
+    async fn next_event(&mut self) -> Result<Option<Bytes>, ServiceError> {
+        let env_repo = environment_repository_factory(&self.data.db).await;
+        let repo = feature_flags_repository_factory(&self.data.db).await;
+        let env = cache_handlers::environment(&self.data.snapshots, &env_repo, &self.project, &self.environment_name).await?;
+        let flags = cache_handlers::flags(&self.data.snapshots, &repo, &self.project).await?;
+        let id = evaluation_hash(&flags, Some(&env), self.context.as_ref().unwrap_or(&Map::new()));
+        if self.last_event_id.as_ref() == Some(&id) {
+            return Ok(None);
+        }
+        let (event, payload) = match &self.context {
+            Some(context) => ("flags", Value::Object(env.get_flags_from_context(&flags, context))),
+            None => {
+                let (changes, definitions) = definition_changes(&self.definitions, &env, &flags);
+                let payload = serde_json::to_value(changes)
+                    .map_err(|err| ServiceError::new(ErrorKind::Internal, err.to_string()))?;
+                // Only marked as sent once the event can be sent
+                self.definitions = definitions;
+                ("definitions", payload)
+            }
+        };
+        let bytes = Bytes::from(format!("event: {}\nid: {}\ndata: {}\n\n", event, id, payload));
+        self.last_event_id = Some(id);
+        Ok(Some(bytes))
     }
+
+    /// Waits for the configuration to change, sending keep-alive comments meanwhile. The
+    /// stream ends once the environment is gone.
+    async fn wait_event(&mut self) -> Option<Bytes> {
+        loop {
+            let changed = actix_web::rt::time::timeout(
+                Duration::from_secs(KEEP_ALIVE_SECONDS),
+                self.changes.changed(),
+            ).await;
+            if let Ok(Err(_)) = changed {
+                return None;
+            }
+            // Also checked on keep-alives, in case a change failed to load
+            match self.next_event().await {
+                Ok(Some(event)) => return Some(event),
+                Err(err) if err.kind == ErrorKind::NotFound => return None,
+                // Skipped, the event is retried on the next change or keep-alive
+                Err(err) => eprintln!("Failed to load the event of environment {}: {}", self.environment_name, err),
+                Ok(None) => {}
+            }
+            if changed.is_err() {
+                return Some(Bytes::from_static(b": keep-alive\n\n"));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct DefinitionChanges {
+    /// Flags with the environment override applied
+    flags: Vec<FeatureFlag>,
+    removed: Vec<String>,
+}
+
+/// Definitions changed since the previous ones, along with the current ones
+fn definition_changes(
+    previous: &HashMap<String, FeatureFlag>,
+    env: &Environment,
+    flags: &[FeatureFlag],
+) -> (DefinitionChanges, HashMap<String, FeatureFlag>) {
+    let current: HashMap<String, FeatureFlag> = flags
+        .iter()
+        .map(|flag| match env.get_override(&flag.name) {
+            Some(flag_override) => flag_override.apply(flag),
+            None => flag.clone(),
+        })
+        .map(|flag| (flag.name.clone(), flag))
+        .collect();
+    let mut changes = DefinitionChanges {
+        flags: current.values().filter(|flag| previous.get(&flag.name) != Some(flag)).cloned().collect(),
+        removed: previous.keys().filter(|name| !current.contains_key(*name)).cloned().collect(),
+    };
+    changes.flags.sort_by(|a, b| a.name.cmp(&b.name));
+    changes.removed.sort();
+    (changes, current)
+}
+
+/// Stores the evaluations counted since the last flush, they are kept for the next one on failure
+pub async fn flush_evaluations(data: &Data<AppState>) -> Result<(), ServiceError> {
+    let counts = data.evaluations.lock().unwrap().take();
+    if counts.is_empty() {
+        return Ok(());
+    }
+    let repo = usage_repository_factory(&data.db).await;
+    if let Err(err) = usage_handlers::record(&repo, &counts).await {
+        data.evaluations.lock().unwrap().restore(counts);
+        return Err(err);
+    }
+    Ok(())
 }
 
 #[derive(Serialize, Deserialize)]
@@ -109,27 +360,139 @@ struct FeatureFlagsContextSchema {
     context: Map<String, Value>,
 }
 
+#[derive(Deserialize)]
+struct StreamQuery {
+    /// JSON object the values are evaluated for
+    context: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BatchEvaluationSchema {
+    contexts: Vec<Map<String, Value>>,
+    /// Flags to evaluate, all of them when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    flags: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BatchEvaluationResponse {
+    items: Vec<Map<String, Value>>,
+}
+
+/// Size limits of batch evaluations, set with `BATCH_EVALUATION_MAX_CONTEXTS` and
+/// `BATCH_EVALUATION_PAYLOAD_LIMIT` (in bytes)
+#[derive(Clone, Copy, Debug)]
+struct BatchLimits {
+    max_contexts: usize,
+    payload_limit: usize,
+}
+
+impl BatchLimits {
+    fn from_env() -> Self {
+        let var = |name: &str, default: usize| {
+            std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+        };
+        BatchLimits {
+            max_contexts: var("BATCH_EVALUATION_MAX_CONTEXTS", 10_000),
+            payload_limit: var("BATCH_EVALUATION_PAYLOAD_LIMIT", 16 * 1024 * 1024),
+        }
+    }
+}
+
 pub fn create_scope() -> Scope {
+    let limits = BatchLimits::from_env();
     web::scope("/flags")
         .route("", web::post().to(
             get_flags_from_context
         ))
+        // Guarded so other methods fall through to the environment routes
+        .service(web::resource("/ws").guard(guard::Get()).to(client_ws::connect))
         .route("/{environment_name}", web::post().to(
             get_environment_flags_from_context
         ))
+        .service(
+            web::resource("/{environment_name}/batch")
+                .app_data(web::JsonConfig::default().limit(limits.payload_limit))
+                .app_data(web::Data::new(limits))
+                .route(web::post().to(get_environment_flags_batch)),
+        )
+        .route("/{environment_name}/stream", web::get().to(stream_environment_flags))
+        // After the routes above, `batch` and `stream` are reserved flag names
+        .route("/{environment_name}/{flag_name}", web::post().to(evaluate_flag))
+        .route("/{environment_name}/{flag_name}", web::get().to(evaluate_flag_from_query))
 }
 
 #[cfg(test)]
 mod tests {
-    use actix_web::{App, test};
+    use std::pin::Pin;
+    use actix_web::body::MessageBody;
+    use actix_web::{App, http, test};
     use chrono::Utc;
-    use crate::database::init_db;
-    use crate::domain::models::{Environment, Operator, Rule};
+    use feature_flags_core::evaluation::Operator;
+    use feature_flags_core::models::{
+        Environment, Evaluation, FeatureFlag, FlagMetadata, FlagMetadataUpdate, FlagOverride, Lifecycle, Reason, Rule, DEFAULT_PROJECT,
+    };
     use crate::get_state;
     use crate::resources::{environments_api, feature_flags_api};
-    use crate::resources::feature_flags_api::FeatureFlagCreateSchema;
+    use crate::resources::feature_flags_api::{FeatureFlagCreateSchema, FeatureFlagUpdateSchema};
     use super::*;
 
+    #[actix_web::test]
+    async fn test_evaluation_tag() {
+        let mut flag = FeatureFlag::new("flag_1", "Flag 1", true, vec![]);
+        let env = Environment::new("production");
+        let context = |tenant: &str, user: &str| {
+            Map::from_iter([("tenant".to_string(), Value::from(tenant)), ("user".to_string(), Value::from(user))])
+        };
+        let tag = evaluation_tag(&[flag.clone()], Some(&env), &context("tenant_1", "user_1"));
+        let reordered = Map::from_iter([("user".to_string(), Value::from("user_1")), ("tenant".to_string(), Value::from("tenant_1"))]);
+        assert_eq!(evaluation_tag(&[flag.clone()], Some(&env), &reordered), tag);
+        assert_ne!(evaluation_tag(&[flag.clone()], Some(&env), &context("tenant_2", "user_1")), tag);
+        assert_ne!(evaluation_tag(&[flag.clone()], None, &context("tenant_1", "user_1")), tag);
+        flag.version += 1;
+        assert_ne!(evaluation_tag(&[flag], Some(&env), &context("tenant_1", "user_1")), tag);
+
+        let req = test::TestRequest::default()
+            .insert_header((http::header::IF_NONE_MATCH, tag.to_string()))
+            .to_http_request();
+        let resp = evaluation_response(&req, tag.clone(), Map::new());
+        assert_eq!(resp.status(), http::StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers().get(http::header::ETAG).unwrap().to_str().unwrap(), tag.to_string());
+        let req = test::TestRequest::default()
+            .insert_header((http::header::IF_NONE_MATCH, "\"outdated\""))
+            .to_http_request();
+        assert_eq!(evaluation_response(&req, tag, Map::new()).status(), http::StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_query_context() {
+        let params = vec![
+            ("tenant".to_string(), "tenant_1".to_string()),
+            ("roles".to_string(), "admin".to_string()),
+            ("roles".to_string(), "billing".to_string()),
+        ];
+        let context = query_context(params);
+        assert_eq!(Value::Object(context), serde_json::json!({"tenant": "tenant_1", "roles": ["admin", "billing"]}));
+    }
+
+    #[actix_web::test]
+    async fn test_definition_changes() {
+        let mut env = Environment::new("production");
+        let flags = vec![
+            FeatureFlag::new("flag_1", "Flag 1", true, vec![]),
+            FeatureFlag::new("flag_2", "Flag 2", true, vec![]),
+        ];
+        let (changes, definitions) = definition_changes(&HashMap::new(), &env, &flags);
+        assert_eq!(changes.flags.len(), 2);
+
+        // Only the overridden flag changed, the removed one is reported by name
+        env.set_override(FlagOverride { enabled: Some(false), ..FlagOverride::new("flag_1") });
+        let (changes, _) = definition_changes(&definitions, &env, &flags[..1]);
+        assert_eq!(changes.flags.len(), 1);
+        assert!(!changes.flags[0].enabled);
+        assert_eq!(changes.removed, vec!["flag_2"]);
+    }
+
     #[actix_web::test]
     async fn test_get_client_flags() {
         let state = get_state().await;
@@ -150,6 +513,7 @@ mod tests {
                     operator: Operator::Is("tenant1".to_string()),
                 }
             ],
+            metadata: FlagMetadata::default(),
         };
         let flag_2 = FeatureFlagCreateSchema {
             name: "flag_2".to_string(),
@@ -164,6 +528,7 @@ mod tests {
                     ]),
                 }
             ],
+            metadata: FlagMetadata::default(),
         };
 
         let req = test::TestRequest::post()
@@ -198,13 +563,30 @@ mod tests {
         let req = test::TestRequest::delete()
             .uri(&format!("/admin/feature_flags/{}", &id_1))
             .to_request();
-        let resp = test::call_service(&app, req).await;
+        test::call_service(&app, req).await;
 
          // Delete item
         let req = test::TestRequest::delete()
             .uri(&format!("/admin/feature_flags/{}", &id_2))
             .to_request();
-        let resp = test::call_service(&app, req).await;
+        test::call_service(&app, req).await;
+
+        // Archived flags are not evaluated
+        let req = test::TestRequest::post()
+            .uri("/flags")
+            .set_json(Json(FeatureFlagsContextSchema {
+                context: Map::new()
+            }))
+            .to_request();
+        let resp: Map<String, Value> = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.get("flag_1").is_none());
+
+        for id in [&id_1, &id_2] {
+            let req = test::TestRequest::delete()
+                .uri(&format!("/admin/feature_flags/{}/purge", id))
+                .to_request();
+            test::call_service(&app, req).await;
+        }
     }
 
     #[actix_web::test]
@@ -227,6 +609,7 @@ mod tests {
                     operator: Operator::Is("tenant1".to_string()),
                 }
             ],
+            metadata: FlagMetadata::default(),
         };
         let flag_2 = FeatureFlagCreateSchema {
             name: "flag_2".to_string(),
@@ -241,12 +624,14 @@ mod tests {
                     ]),
                 }
             ],
+            metadata: FlagMetadata::default(),
         };
 
         let req = test::TestRequest::post()
             .uri("/admin/feature_flags")
             .set_json(Json(FeatureFlag {
                 id: None,
+                project: DEFAULT_PROJECT.to_string(),
                 name: "flag_1".to_string(),
                 label: "Flag 1".to_string(),
                 enabled: true,
@@ -256,6 +641,15 @@ mod tests {
                         operator: Operator::Is("tenant1".to_string()),
                     }
                 ],
+                variant: None,
+                description: String::new(),
+                tags: vec![],
+                owner: None,
+                links: vec![],
+                lifecycle: Lifecycle::Active,
+                expires_at: None,
+                archived: false,
+                version: 0,
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
             }))
@@ -280,9 +674,10 @@ mod tests {
         // Add flag to env
         let req = test::TestRequest::put()
             .uri(&format!("/admin/environments/{}/flags", env_id))
+            .insert_header((http::header::IF_MATCH, resp.version.to_string()))
             .set_json(Json(flag_1))
             .to_request();
-        let resp: Environment = test::call_and_read_body_json(&app, req).await;
+        let _: Environment = test::call_and_read_body_json(&app, req).await;
 
         let id_1 = resp_1.id.unwrap().to_string();
         let id_2 = resp_2.id.unwrap().to_string();
@@ -304,18 +699,347 @@ mod tests {
         let req = test::TestRequest::delete()
             .uri(&format!("/admin/feature_flags/{}", &id_1))
             .to_request();
-        let resp = test::call_service(&app, req).await;
+        test::call_service(&app, req).await;
 
          // Delete item
         let req = test::TestRequest::delete()
             .uri(&format!("/admin/feature_flags/{}", &id_2))
             .to_request();
-        let resp = test::call_service(&app, req).await;
+        test::call_service(&app, req).await;
 
          // Delete env
         let req = test::TestRequest::delete()
             .uri(&format!("/admin/environments/{}", &env_id))
             .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/environments/{}/purge", &env_id))
+            .to_request();
+        test::call_service(&app, req).await;
+        for id in [&id_1, &id_2] {
+            let req = test::TestRequest::delete()
+                .uri(&format!("/admin/feature_flags/{}/purge", id))
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_environment_flags_reflect_updates() {
+        let state = get_state().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&state))
+                .service(feature_flags_api::create_scope())
+                .service(environments_api::create_scope())
+                .service(create_scope()),
+        ).await;
+        let flag = FeatureFlagCreateSchema {
+            name: "cached_flag".to_string(),
+            label: "Cached Flag".to_string(),
+            enabled: true,
+            rules: vec![],
+            metadata: FlagMetadata::default(),
+        };
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags")
+            .set_json(Json(flag))
+            .to_request();
+        let flag: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        let flag_id = flag.id.unwrap().to_string();
+
+        let req = test::TestRequest::post()
+            .uri("/admin/environments")
+            .set_json(Json(Environment::new("test_env_cache")))
+            .to_request();
+        let env: Environment = test::call_and_read_body_json(&app, req).await;
+        let env_id = env.id.unwrap().to_string();
+
+        let evaluate = || test::TestRequest::post()
+            .uri("/flags/test_env_cache")
+            .set_json(Json(FeatureFlagsContextSchema { context: Map::new() }))
+            .to_request();
+
+        // Loaded into the cache, then served from it
+        let resp: Map<String, Value> = test::call_and_read_body_json(&app, evaluate()).await;
+        assert_eq!(resp.get("cached_flag").unwrap(), &Value::Bool(true));
+        let resp: Map<String, Value> = test::call_and_read_body_json(&app, evaluate()).await;
+        assert_eq!(resp.get("cached_flag").unwrap(), &Value::Bool(true));
+        assert!(state.snapshots.metrics().hits > 0);
+
+        // Flag update is reflected immediately
+        let req = test::TestRequest::put()
+            .uri(&format!("/admin/feature_flags/{}", &flag_id))
+            .insert_header((http::header::IF_MATCH, flag.version.to_string()))
+            .set_json(Json(FeatureFlagUpdateSchema {
+                label: "Cached Flag".to_string(),
+                enabled: false,
+                rules: vec![],
+                metadata: FlagMetadataUpdate::default(),
+            }))
+            .to_request();
+        let updated: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        let resp: Map<String, Value> = test::call_and_read_body_json(&app, evaluate()).await;
+        assert_eq!(resp.get("cached_flag").unwrap(), &Value::Bool(false));
+
+        // Environment override is reflected immediately
+        let mut overridden = updated;
+        overridden.enabled = true;
+        let req = test::TestRequest::put()
+            .uri(&format!("/admin/environments/{}/flags", env_id))
+            .insert_header((http::header::IF_MATCH, env.version.to_string()))
+            .set_json(Json(overridden))
+            .to_request();
+        test::call_service(&app, req).await;
+        let resp: Map<String, Value> = test::call_and_read_body_json(&app, evaluate()).await;
+        assert_eq!(resp.get("cached_flag").unwrap(), &Value::Bool(true));
+
+        for uri in [
+            format!("/admin/environments/{}", &env_id),
+            format!("/admin/environments/{}/purge", &env_id),
+            format!("/admin/feature_flags/{}", &flag_id),
+            format!("/admin/feature_flags/{}/purge", &flag_id),
+        ] {
+            let req = test::TestRequest::delete().uri(&uri).to_request();
+            test::call_service(&app, req).await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_batch_evaluation() {
+        let state = get_state().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&state))
+                .service(feature_flags_api::create_scope())
+                .service(environments_api::create_scope())
+                .service(create_scope()),
+        ).await;
+        let mut ids = vec![];
+        for (name, tenant) in [("batch_flag_1", "tenant1"), ("batch_flag_2", "tenant2")] {
+            let flag = FeatureFlagCreateSchema {
+                name: name.to_string(),
+                label: name.to_string(),
+                enabled: true,
+                rules: vec![Rule { parameter: "tenant".to_string(), operator: Operator::Is(tenant.to_string()) }],
+                metadata: FlagMetadata::default(),
+            };
+            let req = test::TestRequest::post()
+                .uri("/admin/feature_flags")
+                .set_json(Json(flag))
+                .to_request();
+            let flag: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+            ids.push(flag.id.unwrap().to_string());
+        }
+        let req = test::TestRequest::post()
+            .uri("/admin/environments")
+            .set_json(Json(Environment::new("test_env_batch")))
+            .to_request();
+        let env: Environment = test::call_and_read_body_json(&app, req).await;
+        let env_id = env.id.unwrap().to_string();
+
+        let context = |tenant: &str| Map::from_iter([("tenant".to_string(), Value::from(tenant))]);
+        let req = test::TestRequest::post()
+            .uri("/flags/test_env_batch/batch")
+            .set_json(Json(BatchEvaluationSchema {
+                contexts: vec![context("tenant2"), context("tenant1"), Map::new()],
+                flags: Some(vec!["batch_flag_1".to_string(), "unknown_flag".to_string()]),
+            }))
+            .to_request();
+        let resp: BatchEvaluationResponse = test::call_and_read_body_json(&app, req).await;
+        let values: Vec<Option<&Value>> = resp.items.iter().map(|item| item.get("batch_flag_1")).collect();
+        assert_eq!(values, vec![Some(&Value::Bool(false)), Some(&Value::Bool(true)), Some(&Value::Bool(false))]);
+        assert!(resp.items.iter().all(|item| item.len() == 1));
+
+        let req = test::TestRequest::post()
+            .uri("/flags/test_env_batch/batch")
+            .set_json(Json(BatchEvaluationSchema { contexts: vec![], flags: None }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), http::StatusCode::BAD_REQUEST);
+        let req = test::TestRequest::post()
+            .uri("/flags/unknown_env/batch")
+            .set_json(Json(BatchEvaluationSchema { contexts: vec![Map::new()], flags: None }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), http::StatusCode::NOT_FOUND);
+
+        let mut uris = vec![
+            format!("/admin/environments/{}", &env_id),
+            format!("/admin/environments/{}/purge", &env_id),
+        ];
+        for id in &ids {
+            uris.push(format!("/admin/feature_flags/{}", id));
+            uris.push(format!("/admin/feature_flags/{}/purge", id));
+        }
+        for uri in uris {
+            let req = test::TestRequest::delete().uri(&uri).to_request();
+            test::call_service(&app, req).await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_single_flag_evaluation() {
+        let state = get_state().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&state))
+                .service(feature_flags_api::create_scope())
+                .service(environments_api::create_scope())
+                .service(create_scope()),
+        ).await;
+        let flag = FeatureFlagCreateSchema {
+            name: "single_flag".to_string(),
+            label: "Single Flag".to_string(),
+            enabled: true,
+            rules: vec![Rule { parameter: "tenant".to_string(), operator: Operator::Is("tenant1".to_string()) }],
+            metadata: FlagMetadata::default(),
+        };
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags")
+            .set_json(Json(flag))
+            .to_request();
+        let flag: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        let flag_id = flag.id.unwrap().to_string();
+        let req = test::TestRequest::post()
+            .uri("/admin/environments")
+            .set_json(Json(Environment::new("test_env_single")))
+            .to_request();
+        let env: Environment = test::call_and_read_body_json(&app, req).await;
+        let env_id = env.id.unwrap().to_string();
+
+        let mut context = Map::new();
+        context.insert("tenant".to_string(), Value::from("tenant1"));
+        let req = test::TestRequest::post()
+            .uri("/flags/test_env_single/single_flag")
+            .set_json(Json(FeatureFlagsContextSchema { context }))
+            .to_request();
+        let evaluation: Evaluation = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((evaluation.value, evaluation.reason), (true, Reason::RuleMatch));
+
+        let req = test::TestRequest::get()
+            .uri("/flags/test_env_single/single_flag?tenant=tenant2")
+            .to_request();
+        let evaluation: Evaluation = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((evaluation.value, evaluation.reason), (false, Reason::RuleMismatch));
+
+        let req = test::TestRequest::get()
+            .uri("/flags/test_env_single/unknown_flag")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), http::StatusCode::NOT_FOUND);
+
+        // Flags can not be named like the environment routes
+        for name in ["batch", "stream"] {
+            let flag = FeatureFlagCreateSchema {
+                name: name.to_string(),
+                label: name.to_string(),
+                enabled: true,
+                rules: vec![],
+                metadata: FlagMetadata::default(),
+            };
+            let req = test::TestRequest::post()
+                .uri("/admin/feature_flags")
+                .set_json(Json(flag))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), http::StatusCode::BAD_REQUEST);
+        }
+
+        for uri in [
+            format!("/admin/environments/{}", &env_id),
+            format!("/admin/environments/{}/purge", &env_id),
+            format!("/admin/feature_flags/{}", &flag_id),
+            format!("/admin/feature_flags/{}/purge", &flag_id),
+        ] {
+            let req = test::TestRequest::delete().uri(&uri).to_request();
+            test::call_service(&app, req).await;
+        }
+    }
+
+    async fn next_event<B: MessageBody + Unpin>(body: &mut B) -> String {
+        let chunk = futures::future::poll_fn(|cx| Pin::new(&mut *body).poll_next(cx)).await;
+        match chunk {
+            Some(Ok(bytes)) => String::from_utf8(bytes.to_vec()).unwrap(),
+            _ => panic!("Event stream ended"),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_stream_environment_flags() {
+        let state = get_state().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&state))
+                .service(feature_flags_api::create_scope())
+                .service(environments_api::create_scope())
+                .service(create_scope()),
+        ).await;
+        let flag = FeatureFlagCreateSchema {
+            name: "streamed_flag".to_string(),
+            label: "Streamed Flag".to_string(),
+            enabled: true,
+            rules: vec![],
+            metadata: FlagMetadata::default(),
+        };
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags")
+            .set_json(Json(flag))
+            .to_request();
+        let flag: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        let flag_id = flag.id.unwrap().to_string();
+        let req = test::TestRequest::post()
+            .uri("/admin/environments")
+            .set_json(Json(Environment::new("test_env_stream")))
+            .to_request();
+        let env: Environment = test::call_and_read_body_json(&app, req).await;
+        let env_id = env.id.unwrap().to_string();
+
+        let req = test::TestRequest::get()
+            .uri("/flags/test_env_stream/stream?context=%7B%7D")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get(http::header::CONTENT_TYPE).unwrap(), "text/event-stream");
+        let mut body = resp.into_body();
+        let event = next_event(&mut body).await;
+        assert!(event.starts_with("event: flags\n"));
+        assert!(event.contains("\"streamed_flag\":true"));
+        let id = event.lines().find_map(|line| line.strip_prefix("id: ")).unwrap().to_string();
+
+        // Kill switch pushed right away
+        let mut disabled = flag;
+        disabled.enabled = false;
+        let req = test::TestRequest::put()
+            .uri(&format!("/admin/environments/{}/flags", env_id))
+            .insert_header((http::header::IF_MATCH, env.version.to_string()))
+            .set_json(Json(disabled))
+            .to_request();
+        test::call_service(&app, req).await;
+        let event = next_event(&mut body).await;
+        assert!(event.contains("\"streamed_flag\":false"));
+
+        // Resuming with the last id skips the configuration the client already has
+        let event_id = event.lines().find_map(|line| line.strip_prefix("id: ")).unwrap();
+        assert_ne!(event_id, id);
+        let req = test::TestRequest::get()
+            .uri("/flags/test_env_stream/stream?context=%7B%7D")
+            .insert_header(("Last-Event-ID", event_id))
+            .to_request();
         let resp = test::call_service(&app, req).await;
+        let mut body = resp.into_body();
+        let pending = futures::future::poll_fn(|cx| {
+            std::task::Poll::Ready(Pin::new(&mut body).poll_next(cx).is_pending())
+        }).await;
+        assert!(pending);
+
+        let req = test::TestRequest::get().uri("/flags/unknown_env/stream").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), http::StatusCode::NOT_FOUND);
+
+        for uri in [
+            format!("/admin/environments/{}", &env_id),
+            format!("/admin/environments/{}/purge", &env_id),
+            format!("/admin/feature_flags/{}", &flag_id),
+            format!("/admin/feature_flags/{}/purge", &flag_id),
+        ] {
+            let req = test::TestRequest::delete().uri(&uri).to_request();
+            test::call_service(&app, req).await;
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use feature_flags_storage::repositories::environment_repository::environment_repository_factory;
+use feature_flags_storage::repositories::feature_flags_repository::feature_flags_repository_factory;
+use feature_flags_storage::repositories::ErrorKind;
+use crate::resources::ProjectScope;
+use crate::services::{cache_handlers, ServiceError};
+use crate::AppState;
+
+/// Interval of the pings sent to the client
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Connections without any message from the client for longer are closed
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Clients not reading their messages for longer are disconnected
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest number of environments a connection subscribes to
+const MAX_SUBSCRIPTIONS: usize = 20;
+
+/// Live flag values over a WebSocket. Clients subscribe to environments and set the context
+/// of the session, they are sent the values that changed whenever the flags are written.
+/// Changes made while a client is slow to read are coalesced into one message.
+pub async fn connect(
+    req: HttpRequest,
+    body: web::Payload,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    let connection = Connection {
+        data,
+        project: project.0,
+        context: Map::new(),
+        subscriptions: HashMap::new(),
+    };
+    actix_web::rt::spawn(connection.run(session, messages));
+    Ok(response)
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe { environment: String },
+    Unsubscribe { environment: String },
+    /// Replaces the context the subscribed environments are evaluated for
+    Context { context: Map<String, Value> },
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    /// Values changed since the previous message of the environment, every value after subscribing
+    Flags {
+        environment: String,
+        changed: Map<String, Value>,
+        removed: Vec<String>,
+    },
+    /// The environment was archived or deleted
+    Unsubscribed { environment: String },
+    Error { message: String },
+}
+
+struct Connection {
+    data: web::Data<AppState>,
+    project: String,
+    context: Map<String, Value>,
+    /// Values last sent, by environment name
+    subscriptions: HashMap<String, Map<String, Value>>,
+}
+
+/// The client is gone or too slow, the connection is closed
+struct Disconnected;
+
+impl Connection {
+    async fn run(mut self, mut session: Session, mut messages: MessageStream) {
+        let mut changes = self.data.snapshots.subscribe();
+        let mut heartbeat = actix_web::rt::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_seen = Instant::now();
+        let reason = loop {
+            let res = tokio::select! {
+                message = messages.recv() => {
+                    last_seen = Instant::now();
+                    match message {
+                        Some(Ok(Message::Text(text))) => self.handle(&mut session, &text).await,
+                        Some(Ok(Message::Binary(_))) => {
+                            let message = "Messages must be JSON text".to_string();
+                            send(&mut session, &ServerMessage::Error { message }).await
+                        }
+                        Some(Ok(Message::Ping(bytes))) => session.pong(&bytes).await.map_err(|_| Disconnected),
+                        Some(Ok(Message::Close(reason))) => break reason,
+                        Some(Ok(_)) => Ok(()),
+                        Some(Err(_)) => break Some(CloseCode::Protocol.into()),
+                        None => break None,
+                    }
+                }
+                changed = changes.changed() => match changed {
+                    Ok(()) => self.push_changes(&mut session).await,
+                    Err(_) => break Some(CloseCode::Restart.into()),
+                },
+                _ = heartbeat.tick() => {
+                    if last_seen.elapsed() > CLIENT_TIMEOUT {
+                        break Some(CloseReason::from((CloseCode::Away, "Heartbeat timeout")));
+                    }
+                    session.ping(b"").await.map_err(|_| Disconnected)
+                }
+            };
+            if res.is_err() {
+                break Some(CloseReason::from((CloseCode::Away, "Client too slow")));
+            }
+        };
+        let _ = session.close(reason).await;
+    }
+
+    async fn handle(&mut self, session: &mut Session, text: &str) -> Result<(), Disconnected> {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(err) => return send(session, &ServerMessage::Error { message: err.to_string() }).await,
+        };
+        match message {
+            ClientMessage::Subscribe { environment } => {
+                if self.subscriptions.len() >= MAX_SUBSCRIPTIONS && !self.subscriptions.contains_key(&environment) {
+                    let message = format!("At most {} environments can be subscribed to", MAX_SUBSCRIPTIONS);
+                    return send(session, &ServerMessage::Error { message }).await;
+                }
+                match self.evaluate(&environment).await {
+                    Ok(values) => self.send_changes(session, &environment, values, true).await,
+                    Err(err) => send(session, &ServerMessage::Error { message: err.to_string() }).await,
+                }
+            }
+            ClientMessage::Unsubscribe { environment } => {
+                self.subscriptions.remove(&environment);
+                Ok(())
+            }
+            ClientMessage::Context { context } => {
+                self.context = context;
+                self.push_changes(session).await
+            }
+        }
+    }
+
+    /// Sends the values changed in every subscribed environment
+    async fn push_changes(&mut self, session: &mut Session) -> Result<(), Disconnected> {
+        let environments: Vec<String> = self.subscriptions.keys().cloned().collect();
+        for environment in environments {
+            match self.evaluate(&environment).await {
+                Ok(values) => self.send_changes(session, &environment, values, false).await?,
+                Err(err) if err.kind == ErrorKind::NotFound => {
+                    self.subscriptions.remove(&environment);
+                    send(session, &ServerMessage::Unsubscribed { environment }).await?;
+                }
+                // Retried on the next change
+                Err(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the values changed since the previous message, or every value when subscribing
+    async fn send_changes(
+        &mut self,
+        session: &mut Session,
+        environment: &str,
+        values: Map<String, Value>,
+        subscribing: bool,
+    ) -> Result<(), Disconnected> {
+        let previous = match self.subscriptions.get(environment) {
+            Some(previous) if !subscribing => previous.clone(),
+            _ => Map::new(),
+        };
+        let (changed, removed) = value_changes(&previous, &values);
+        if !subscribing && changed.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+        self.subscriptions.insert(environment.to_string(), values);
+        let message = ServerMessage::Flags { environment: environment.to_string(), changed, removed };
+        send(session, &message).await
+    }
+
+    async fn evaluate(&self, environment: &str) -> Result<Map<String, Value>, ServiceError> {
+        let env_repo = environment_repository_factory(&self.data.db).await;
+        let repo = feature_flags_repository_factory(&self.data.db).await;
+        let env = cache_handlers::environment(&self.data.snapshots, &env_repo, &self.project, environment).await?;
+        let flags = cache_handlers::flags(&self.data.snapshots, &repo, &self.project).await?;
+        Ok(env.get_flags_from_context(&flags, &self.context))
+    }
+}
+
+/// Waits for the client to make room for the message, up to `SEND_TIMEOUT`
+async fn send(session: &mut Session, message: &ServerMessage) -> Result<(), Disconnected> {
+    let text = serde_json::to_string(message).unwrap();
+    match actix_web::rt::time::timeout(SEND_TIMEOUT, session.text(text)).await {
+        Ok(Ok(())) => Ok(()),
+        _ => Err(Disconnected),
+    }
+}
+
+/// Values added or changed, and names of the flags no longer evaluated
+fn value_changes(previous: &Map<String, Value>, current: &Map<String, Value>) -> (Map<String, Value>, Vec<String>) {
+    let changed = current
+        .iter()
+        .filter(|(name, value)| previous.get(*name) != Some(value))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    let removed = previous.keys().filter(|name| !current.contains_key(*name)).cloned().collect();
+    (changed, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_client_messages() {
+        let message: ClientMessage = serde_json::from_value(json!({"type": "subscribe", "environment": "production"})).unwrap();
+        assert_eq!(message, ClientMessage::Subscribe { environment: "production".to_string() });
+        let message: ClientMessage = serde_json::from_value(json!({"type": "context", "context": {"tenant": "tenant_1"}})).unwrap();
+        assert!(matches!(message, ClientMessage::Context { context } if context["tenant"] == "tenant_1"));
+        assert!(serde_json::from_value::<ClientMessage>(json!({"type": "unknown"})).is_err());
+
+        let message = ServerMessage::Unsubscribed { environment: "production".to_string() };
+        assert_eq!(serde_json::to_value(message).unwrap(), json!({"type": "unsubscribed", "environment": "production"}));
+    }
+
+    #[test]
+    fn test_value_changes() {
+        let previous = json!({"flag_1": true, "flag_2": false, "flag_3": true});
+        let current = json!({"flag_1": true, "flag_2": true, "flag_4": false});
+        let (changed, removed) = value_changes(previous.as_object().unwrap(), current.as_object().unwrap());
+        assert_eq!(Value::Object(changed), json!({"flag_2": true, "flag_4": false}));
+        assert_eq!(removed, vec!["flag_3"]);
+    }
+}
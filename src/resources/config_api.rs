@@ -0,0 +1,335 @@
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use feature_flags_storage::repositories::environment_repository::environment_repository_factory;
+use feature_flags_storage::repositories::feature_flags_repository::feature_flags_repository_factory;
+use feature_flags_storage::repositories::revision_repository::{
+    environment_revisions_repository_factory, feature_flag_revisions_repository_factory, RevisionRepository,
+};
+use feature_flags_storage::domain::audit::Auditable;
+use crate::domain::config::Configuration;
+use feature_flags_storage::domain::history::{diff, FieldChange, Revision, RevisionAction, Revisioned};
+use feature_flags_storage::domain::sync::ChangedEntity;
+use crate::resources::{actor, auditor, ensure_project, publish_change, record_revision, CustomError, ProjectScope};
+use crate::services::config_handlers::{ImportOptions, ImportStrategy, Planned};
+use crate::services::config_handlers;
+use crate::AppState;
+
+/// Largest accepted configuration document
+const IMPORT_PAYLOAD_LIMIT: usize = 16 * 1024 * 1024;
+
+async fn export(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let format = match query.format.as_deref() {
+        None => Format::Json,
+        Some(format) => Format::parse(format)?,
+    };
+    let db = &data.db;
+    let repo = feature_flags_repository_factory(db).await;
+    let env_repo = environment_repository_factory(db).await;
+    let config = config_handlers::export(&repo, &env_repo, &project.0).await?;
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"configuration.{}\"", format.extension()),
+        ))
+        .body(format.serialize(&config)?))
+}
+
+/// Loads a configuration document, YAML when the content type says so and JSON otherwise
+async fn import(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    query: web::Query<ImportOptions>,
+    body: Bytes,
+) -> Result<HttpResponse, CustomError> {
+    let config = Format::of_request(&req).deserialize(&body)?;
+    let db = &data.db;
+    ensure_project(db, &project).await?;
+    let repo = feature_flags_repository_factory(db).await;
+    let env_repo = environment_repository_factory(db).await;
+    let auditor = auditor(&req, db).await;
+    let plan = match config_handlers::import(
+        &repo, &env_repo, &data.client, &project.0, &config, *query, &auditor,
+    ).await {
+        Ok(plan) => plan,
+        Err(err) => {
+            // Imports without transactions may have been partly written
+            if !query.dry_run {
+                data.snapshots.invalidate_project(&project.0);
+                publish_change(&data, &project.0, &[ChangedEntity::Project]).await;
+            }
+            return Err(err.into());
+        }
+    };
+    if !query.dry_run && !plan.is_empty() {
+        // Configuration replaced, invalidate caches
+        data.snapshots.invalidate_project(&project.0);
+        publish_change(&data, &project.0, &[ChangedEntity::Project]).await;
+        let history_repo = feature_flag_revisions_repository_factory(db).await;
+        record(&req, &history_repo, &plan.feature_flags).await;
+        let history_repo = environment_revisions_repository_factory(db).await;
+        record(&req, &history_repo, &plan.environments).await;
+    }
+    let mut changes: Vec<ImportChange> = plan.feature_flags.iter().map(report).collect();
+    changes.extend(plan.environments.iter().map(report));
+    Ok(HttpResponse::Ok().json(ImportReport {
+        strategy: query.strategy,
+        dry_run: query.dry_run,
+        changes,
+    }))
+}
+
+/// Records the history of the imported writes, they are audited by the import
+async fn record<T>(
+    req: &HttpRequest,
+    history_repo: &RevisionRepository<Revision<T>>,
+    planned: &[Planned<T>],
+)
+where
+    T: Revisioned + DeserializeOwned + Clone + Unpin + Send + Sync,
+{
+    for planned in planned {
+        let (action, before, after) = match planned {
+            Planned::Create(entity) => (RevisionAction::Created, None, entity),
+            Planned::Update(change) => (RevisionAction::Updated, Some(&change.before), &change.after),
+            Planned::Archive(change) => (RevisionAction::Archived, Some(&change.before), &change.after),
+        };
+        record_revision(history_repo, action, before, after, &actor(req)).await;
+    }
+}
+
+fn report<T: Revisioned + Auditable>(planned: &Planned<T>) -> ImportChange {
+    let (action, entity, changes) = match planned {
+        Planned::Create(entity) => ("create", entity, diff(&Value::Null, &entity.tracked_fields())),
+        Planned::Update(change) => (
+            "update",
+            &change.after,
+            diff(&change.before.tracked_fields(), &change.after.tracked_fields()),
+        ),
+        Planned::Archive(change) => (
+            "archive",
+            &change.after,
+            diff(&change.before.tracked_fields(), &change.after.tracked_fields()),
+        ),
+    };
+    let target = entity.audit_target();
+    ImportChange {
+        kind: target.kind,
+        name: target.name,
+        action: action.to_string(),
+        changes,
+    }
+}
+
+pub fn create_scope() -> Scope {
+    // Registered after the other `/admin` scopes, which would otherwise not be reached
+    web::scope("/admin")
+        .route("/export", web::get().to(export))
+        .service(
+            web::resource("/import")
+                .app_data(web::PayloadConfig::new(IMPORT_PAYLOAD_LIMIT))
+                .route(web::post().to(import)),
+        )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Json,
+    Yaml,
+}
+
+impl Format {
+    fn parse(value: &str) -> Result<Self, CustomError> {
+        match value {
+            "json" => Ok(Format::Json),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            _ => Err(CustomError::BadRequest(format!("Unknown format {}", value))),
+        }
+    }
+
+    fn of_request(req: &HttpRequest) -> Self {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if content_type.contains("yaml") {
+            Format::Yaml
+        } else {
+            Format::Json
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Yaml => "application/yaml",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Yaml => "yaml",
+        }
+    }
+
+    fn serialize(&self, config: &Configuration) -> Result<Vec<u8>, CustomError> {
+        let mut body = vec![];
+        let res = match self {
+            Format::Json => serde_json::to_writer_pretty(&mut body, config).map_err(|err| err.to_string()),
+            // Enums are written as maps, as in JSON, instead of YAML tags
+            Format::Yaml => serde_yaml::with::singleton_map_recursive::serialize(
+                config,
+                &mut serde_yaml::Serializer::new(&mut body),
+            ).map_err(|err| err.to_string()),
+        };
+        match res {
+            Ok(_) => Ok(body),
+            Err(_) => Err(CustomError::ApplicationError),
+        }
+    }
+
+    fn deserialize(&self, body: &[u8]) -> Result<Configuration, CustomError> {
+        let res = match self {
+            Format::Json => serde_json::from_slice(body).map_err(|err| err.to_string()),
+            Format::Yaml => serde_yaml::with::singleton_map_recursive::deserialize(
+                serde_yaml::Deserializer::from_slice(body),
+            ).map_err(|err| err.to_string()),
+        };
+        res.map_err(|err| CustomError::BadRequest(format!("Invalid configuration document: {}", err)))
+    }
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    /// `json` or `yaml`
+    format: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ImportReport {
+    strategy: ImportStrategy,
+    dry_run: bool,
+    changes: Vec<ImportChange>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ImportChange {
+    kind: String,
+    name: String,
+    action: String,
+    changes: Vec<FieldChange>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use crate::domain::config::{EnvironmentConfiguration, FlagConfiguration};
+    use feature_flags_core::evaluation::{Operator, Rule};
+    use feature_flags_core::models::{Environment, FeatureFlag, FlagOverride, DEFAULT_PROJECT};
+    use crate::get_state;
+
+    #[actix_web::test]
+    async fn test_formats_round_trip() {
+        let mut flag = FeatureFlag::new("sample_flag", "Sample Flag", true, vec![Rule {
+            parameter: "tenant".to_string(),
+            operator: Operator::Is("tenant_1".to_string()),
+        }]);
+        flag.tags = vec!["checkout".to_string()];
+        let mut env = Environment::new("production");
+        env.set_override(FlagOverride { enabled: Some(false), ..FlagOverride::new("sample_flag") });
+        let config = Configuration::new(DEFAULT_PROJECT, &[flag], &[env]);
+
+        for format in [Format::Json, Format::Yaml] {
+            let body = format.serialize(&config).unwrap();
+            let decoded = format.deserialize(&body).unwrap();
+            assert_eq!(decoded.feature_flags, config.feature_flags);
+            assert_eq!(decoded.environments, config.environments);
+        }
+        let yaml = String::from_utf8(Format::Yaml.serialize(&config).unwrap()).unwrap();
+        assert!(yaml.contains("Is: tenant_1"));
+        assert!(matches!(Format::Json.deserialize(b"{}"), Err(CustomError::BadRequest(_))));
+    }
+
+    #[actix_web::test]
+    async fn test_export_and_import() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&get_state().await))
+                .service(create_scope()),
+        )
+        .await;
+
+        let config = Configuration {
+            version: crate::domain::config::CONFIG_VERSION,
+            project: None,
+            exported_at: chrono::Utc::now(),
+            feature_flags: vec![FlagConfiguration::from(&FeatureFlag::new("imported_flag", "Imported", true, vec![]))],
+            environments: vec![EnvironmentConfiguration {
+                name: "imported_env".to_string(),
+                flags: vec![FlagOverride::new("imported_flag")],
+                archived: false,
+            }],
+        };
+        let body = Format::Yaml.serialize(&config).unwrap();
+
+        // Dry run
+        let req = test::TestRequest::post()
+            .uri("/admin/import?dry_run=true")
+            .insert_header((header::CONTENT_TYPE, "application/yaml"))
+            .set_payload(body.clone())
+            .to_request();
+        let resp: ImportReport = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.changes.len(), 2);
+        let req = test::TestRequest::get().uri("/admin/export").to_request();
+        let exported: Configuration = test::call_and_read_body_json(&app, req).await;
+        assert!(!exported.feature_flags.iter().any(|flag| flag.name == "imported_flag"));
+
+        // Import, importing again changes nothing
+        let req = test::TestRequest::post()
+            .uri("/admin/import")
+            .insert_header((header::CONTENT_TYPE, "application/yaml"))
+            .set_payload(body.clone())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let req = test::TestRequest::post()
+            .uri("/admin/import")
+            .insert_header((header::CONTENT_TYPE, "application/yaml"))
+            .set_payload(body)
+            .to_request();
+        let resp: ImportReport = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.changes.is_empty());
+
+        let req = test::TestRequest::get().uri("/admin/export?format=yaml").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "application/yaml");
+        let exported = Format::Yaml.deserialize(&test::read_body(resp).await).unwrap();
+        assert!(exported.environments.iter().any(|env| env.name == "imported_env"));
+        assert_eq!(exported.project.as_deref(), Some(DEFAULT_PROJECT));
+
+        // Replacing with the original configuration archives the imported entities
+        let mut original = exported.clone();
+        original.feature_flags.retain(|flag| flag.name != "imported_flag");
+        original.environments.retain(|env| env.name != "imported_env");
+        let req = test::TestRequest::post()
+            .uri("/admin/import?strategy=replace")
+            .set_json(&original)
+            .to_request();
+        let resp: ImportReport = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.changes.len(), 2);
+        assert!(resp.changes.iter().all(|change| change.action == "archive"));
+    }
+}
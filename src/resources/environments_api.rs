@@ -1,178 +1,434 @@
-use std::future::Future;
-use std::sync::Mutex;
-use actix_web::{HttpResponse, Scope, web};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, Scope, web};
 use actix_web::web::Json;
-use chrono::Utc;
-use mongodb::bson;
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
-use crate::adapters::repositories::environment_repository::environment_repository_factory;
-use crate::adapters::repositories::feature_flags_repository::feature_flags_repository_factory;
+use feature_flags_storage::repositories::environment_repository::environment_repository_factory;
+use feature_flags_storage::repositories::feature_flags_repository::feature_flags_repository_factory;
+use feature_flags_storage::repositories::revision_repository::environment_revisions_repository_factory;
 use crate::AppState;
-use crate::domain::models::{Environment, FeatureFlag};
-use crate::resources::CustomError;
-use crate::services::{environment_handlers, feature_flag_handlers, ServiceError};
-use crate::resources::feature_flags_api::{FeatureFlagCreateSchema};
+use feature_flags_storage::domain::history::{diff, FieldChange, Revision, RevisionAction, Revisioned};
+use feature_flags_core::models::{Environment, FlagOverride};
+use feature_flags_storage::domain::sync::ChangedEntity;
+use crate::resources::{
+    actor, audit, batch_response, check_batch_size, ensure_project, entity_tag, if_match, page_request,
+    publish_change, record_revision, split_list, BatchMode, CustomError, ProjectScope, BATCH_PAYLOAD_LIMIT,
+};
+use crate::services::environment_handlers::{OverrideOperation, PromoteOptions};
+use crate::services::{environment_handlers, history_handlers};
+use feature_flags_storage::repositories::ErrorKind;
 
 
-async fn find(data: web::Data<Mutex<AppState>>) -> Result<HttpResponse, CustomError> {
-    let db = &data.lock().unwrap().db;
-    let repo = environment_repository_factory(db).await;
-    let res = environment_handlers::find(&repo, None).await;
-    let envs = match res {
-        Ok(envs) => envs,
-        Err(_) => vec![],
+async fn find(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    query: web::Query<EnvironmentListQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let page = page_request(
+        query.limit,
+        query.cursor.as_deref(),
+        query.sort.as_deref(),
+        query.order.as_deref(),
+    )?;
+    let filters = environment_handlers::Filters {
+        project: Some(project.0),
+        name: query.name.clone(),
+        archived: query.archived.unwrap_or(false),
     };
-    Ok(HttpResponse::Ok().json(EnvironmentList { items: envs }))
+    let db = &data.db;
+    let repo = environment_repository_factory(db).await;
+    match environment_handlers::find_page(&repo, &filters, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(EnvironmentList {
+            items: page.items,
+            total: page.total,
+            next_cursor: page.next_cursor,
+        })),
+        Err(_) => Err(CustomError::ApplicationError),
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct EnvironmentList {
-    items: Vec<Environment>
+    items: Vec<Environment>,
+    total: u64,
+    next_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EnvironmentListQuery {
+    limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+    order: Option<String>,
+    name: Option<String>,
+    archived: Option<bool>,
 }
 
 async fn create(
-    data: web::Data<Mutex<AppState>>,
-    body: Json<Environment>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    body: Json<EnvironmentCreateSchema>,
 ) -> Result<HttpResponse, CustomError> {
-    let mut app_data = data.lock().unwrap();
-    let db = &app_data.db;
+    let db = &data.db;
+    ensure_project(db, &project).await?;
     let repo = environment_repository_factory(db).await;
-    match environment_handlers::create(&repo, &body.name).await {
+    let history_repo = environment_revisions_repository_factory(db).await;
+    match environment_handlers::create(&repo, &project.0, &body.name).await {
         Ok(id) => {
             let mut env = Environment::new(&body.name);
+            env.project = project.0;
             let env_id = ObjectId::parse_str(id).expect("");
             env.id = Some(env_id);
-            app_data.envs.insert(env.name.clone(), Environment {
-                id: Some(env_id),
-                name: env.name.clone(),
-                flags: env.flags.clone(),
-            });
-            Ok(HttpResponse::Created().json(Json(env)))
+            data.snapshots.invalidate_env(&env.project, &env.name);
+            publish_change(&data, &env.project, &[ChangedEntity::Environment(env.name.clone())]).await;
+            record_revision(&history_repo, RevisionAction::Created, None, &env, &actor(&req)).await;
+            audit(&req, db, "environment.create", None, Some(&env)).await;
+            Ok(HttpResponse::Created().insert_header(entity_tag(env.version)).json(Json(env)))
         }
-        Err(_) => Err(CustomError::Conflict),
+        Err(err) => match err.kind {
+            ErrorKind::InvalidArgument => Err(err.into()),
+            _ => Err(CustomError::Conflict),
+        },
     }
 }
 
 async fn get(
-    data: web::Data<Mutex<AppState>>,
+    data: web::Data<AppState>,
+    project: ProjectScope,
     id: web::Path<String>,
 ) -> Result<HttpResponse, CustomError> {
-    let db = &data.lock().unwrap().db;
+    let db = &data.db;
     let repo = environment_repository_factory(db).await;
-    let env_id = id.into_inner();
-    match environment_handlers::get(&repo, &env_id).await {
-        Ok(mut env) => {
-            let _id = ObjectId::parse_str(env_id).unwrap();
-            env.id = Some(_id);
-            Ok(HttpResponse::Ok().json(Json(env)))
+    match environment_handlers::get(&repo, &project.0, &id.into_inner()).await {
+        Ok(env) => {
+            Ok(HttpResponse::Ok().insert_header(entity_tag(env.version)).json(Json(env)))
         }
         Err(_) => Err(CustomError::NotFound),
     }
 }
 
+/// Archives the environment, see `purge` to remove it permanently
 async fn delete(
-    data: web::Data<Mutex<AppState>>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
     id: web::Path<String>,
 ) -> Result<HttpResponse, CustomError> {
-    let mut app_data = data.lock().unwrap();
-    let db = &app_data.db;
+    let version = if_match(&req, false)?;
+    let db = &data.db;
     let repo = environment_repository_factory(db).await;
-    let env_id = id.into_inner();
-    match environment_handlers::get(&repo, &env_id).await {
-        Ok(env) => {
-            match environment_handlers::delete(&repo, &env_id).await {
-                Ok(_) => {
-                    app_data.envs.remove(&env.name);
-                    Ok(HttpResponse::NoContent().finish())
-                },
-                Err(_) => Err(CustomError::NotFound),
-            }
-        },
-        Err(_) => Err(CustomError::NotFound),
-    }
+    let history_repo = environment_revisions_repository_factory(db).await;
+    let change = environment_handlers::archive(&repo, &project.0, &id.into_inner(), version).await?;
+    data.snapshots.invalidate_env(&project.0, &change.after.name);
+    publish_change(&data, &project.0, &[ChangedEntity::Environment(change.after.name.clone())]).await;
+    record_revision(
+        &history_repo, RevisionAction::Archived, Some(&change.before), &change.after, &actor(&req),
+    ).await;
+    audit(&req, db, "environment.archive", Some(&change.before), Some(&change.after)).await;
+    Ok(HttpResponse::NoContent().finish())
 }
 
-async fn set_flag(
-    data: web::Data<Mutex<AppState>>,
+async fn restore(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
     id: web::Path<String>,
-    body: Json<FeatureFlagCreateSchema>
 ) -> Result<HttpResponse, CustomError> {
-    let mut app_data = data.lock().unwrap();
-    let db = &app_data.db;
+    let version = if_match(&req, false)?;
+    let db = &data.db;
     let repo = environment_repository_factory(db).await;
+    let history_repo = environment_revisions_repository_factory(db).await;
+    let change = environment_handlers::restore(&repo, &project.0, &id.into_inner(), version).await?;
+    data.snapshots.invalidate_env(&change.after.project, &change.after.name);
+    publish_change(&data, &change.after.project, &[ChangedEntity::Environment(change.after.name.clone())]).await;
+    record_revision(
+        &history_repo, RevisionAction::Restored, Some(&change.before), &change.after, &actor(&req),
+    ).await;
+    audit(&req, db, "environment.restore", Some(&change.before), Some(&change.after)).await;
+    let env = change.after;
+    Ok(HttpResponse::Accepted().insert_header(entity_tag(env.version)).json(Json(env)))
+}
 
-    let new_flag = FeatureFlag {
-        id: None,
-        name: body.name.clone(),
-        label: body.label.clone(),
-        enabled: body.enabled,
-        rules: body.rules.clone(),
-        created_at: Utc::now(),
-        updated_at: Utc::now(),
-    };
+/// Permanently removes an archived environment
+async fn purge(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    id: web::Path<String>,
+) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
+    let repo = environment_repository_factory(db).await;
+    let history_repo = environment_revisions_repository_factory(db).await;
+    let env = environment_handlers::purge(&repo, &project.0, &id.into_inner()).await?;
+    data.snapshots.invalidate_env(&env.project, &env.name);
+    publish_change(&data, &env.project, &[ChangedEntity::Environment(env.name.clone())]).await;
+    record_revision(&history_repo, RevisionAction::Deleted, None, &env, &actor(&req)).await;
+    audit(&req, db, "environment.purge", Some(&env), None).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn set_flag(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    id: web::Path<String>,
+    body: Json<FlagOverride>
+) -> Result<HttpResponse, CustomError> {
+    let version = if_match(&req, true)?;
+    let db = &data.db;
+    let repo = environment_repository_factory(db).await;
+    let flag_repo = feature_flags_repository_factory(db).await;
+    let history_repo = environment_revisions_repository_factory(db).await;
 
     let env_id = id.into_inner();
-    match environment_handlers::set_flag(&repo, &env_id, &new_flag).await {
-        Ok(env) => {
-            app_data.envs.insert(env.name.clone(), Environment {
-                id: env.id,
-                name: env.name.clone(),
-                flags: env.flags.clone(),
-            });
-            Ok(HttpResponse::Accepted().json(Json(env)))
+    match environment_handlers::set_flag(&repo, &project.0, &flag_repo, &env_id, body.into_inner(), version).await {
+        Ok(change) => {
+            data.snapshots.invalidate_env(&change.after.project, &change.after.name);
+            publish_change(&data, &change.after.project, &[ChangedEntity::Environment(change.after.name.clone())]).await;
+            record_revision(
+                &history_repo, RevisionAction::Updated, Some(&change.before), &change.after, &actor(&req),
+            ).await;
+            audit(&req, db, "environment.set_flag", Some(&change.before), Some(&change.after)).await;
+            let env = change.after;
+            Ok(HttpResponse::Accepted().insert_header(entity_tag(env.version)).json(Json(env)))
         },
+        Err(err) if err.kind == ErrorKind::VersionConflict => Err(CustomError::PreconditionFailed),
         Err(_) => Err(CustomError::NotFound)
     }
 }
 
 async fn remove_flag(
-    data: web::Data<Mutex<AppState>>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
     path: web::Path<(String, String)>,
 ) -> Result<HttpResponse, CustomError> {
-    let mut app_data = data.lock().unwrap();
-    let db = &app_data.db;
+    let version = if_match(&req, false)?;
+    let db = &data.db;
     let repo = environment_repository_factory(db).await;
+    let history_repo = environment_revisions_repository_factory(db).await;
     let (env_id, flag_name) = path.into_inner();
 
-    match environment_handlers::remove_flag(&repo, &env_id, &flag_name).await {
-        Ok(env) => {
-            app_data.envs.insert(env.name.clone(), Environment {
-                id: env.id,
-                name: env.name.clone(),
-                flags: env.flags.clone(),
-            });
-            Ok(HttpResponse::Accepted().json(Json(env)))
+    match environment_handlers::remove_flag(&repo, &project.0, &env_id, &flag_name, version).await {
+        Ok(change) => {
+            data.snapshots.invalidate_env(&change.after.project, &change.after.name);
+            publish_change(&data, &change.after.project, &[ChangedEntity::Environment(change.after.name.clone())]).await;
+            record_revision(
+                &history_repo, RevisionAction::Updated, Some(&change.before), &change.after, &actor(&req),
+            ).await;
+            audit(&req, db, "environment.remove_flag", Some(&change.before), Some(&change.after)).await;
+            let env = change.after;
+            Ok(HttpResponse::Accepted().insert_header(entity_tag(env.version)).json(Json(env)))
         },
+        Err(err) if err.kind == ErrorKind::VersionConflict => Err(CustomError::PreconditionFailed),
         Err(_) => Err(CustomError::NotFound)
     }
 
 }
 
+async fn batch_flags(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    id: web::Path<String>,
+    body: Json<OverrideBatchSchema>,
+) -> Result<HttpResponse, CustomError> {
+    let version = if_match(&req, false)?;
+    let OverrideBatchSchema { mode, operations } = body.into_inner();
+    check_batch_size(operations.len())?;
+    let size = operations.len();
+    let db = &data.db;
+    let repo = environment_repository_factory(db).await;
+    let flag_repo = feature_flags_repository_factory(db).await;
+    let history_repo = environment_revisions_repository_factory(db).await;
+
+    let (res, change) = environment_handlers::batch(
+        &repo, &flag_repo, &project.0, &id.into_inner(), operations, mode == BatchMode::Atomic, version,
+    ).await?;
+    let mut tag = None;
+    if let Some(change) = change {
+        data.snapshots.invalidate_env(&change.after.project, &change.after.name);
+        publish_change(&data, &change.after.project, &[ChangedEntity::Environment(change.after.name.clone())]).await;
+        record_revision(
+            &history_repo, RevisionAction::Updated, Some(&change.before), &change.after, &actor(&req),
+        ).await;
+        audit(&req, db, "environment.batch", Some(&change.before), Some(&change.after)).await;
+        let env = change.after;
+        tag = Some(entity_tag(env.version));
+    }
+    Ok(batch_response(&res, size, tag, |_| None, |_| StatusCode::OK))
+}
+
+async fn history(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    id: web::Path<String>,
+) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
+    let repo = environment_repository_factory(db).await;
+    let history_repo = environment_revisions_repository_factory(db).await;
+    let env_id = environment_handlers::resolve_id(&repo, &project.0, &id.into_inner()).await?;
+    let mut revisions = history_handlers::find(&history_repo, &env_id).await?;
+    revisions.retain(|revision| revision.snapshot.project == project.0);
+    Ok(HttpResponse::Ok().json(RevisionList { items: revisions }))
+}
+
+async fn rollback(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    path: web::Path<(String, i64)>,
+) -> Result<HttpResponse, CustomError> {
+    let version = if_match(&req, false)?;
+    let db = &data.db;
+    let repo = environment_repository_factory(db).await;
+    let history_repo = environment_revisions_repository_factory(db).await;
+    let (env_id, revision) = path.into_inner();
+    let change = environment_handlers::rollback(&repo, &project.0, &history_repo, &env_id, revision, version).await?;
+    data.snapshots.invalidate_env(&change.after.project, &change.after.name);
+    publish_change(&data, &change.after.project, &[ChangedEntity::Environment(change.after.name.clone())]).await;
+    record_revision(
+        &history_repo, RevisionAction::RolledBack(revision), Some(&change.before), &change.after, &actor(&req),
+    ).await;
+    audit(&req, db, "environment.rollback", Some(&change.before), Some(&change.after)).await;
+    let env = change.after;
+    Ok(HttpResponse::Accepted().insert_header(entity_tag(env.version)).json(Json(env)))
+}
+
+async fn diff_environments(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    query: web::Query<DiffQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
+    let repo = environment_repository_factory(db).await;
+    let flag_repo = feature_flags_repository_factory(db).await;
+    let res = environment_handlers::compare(&repo, &flag_repo, &project.0, &query.left, &query.right).await?;
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// Promotes the flags of the source environment to the target one, previews report the changes without applying them
+async fn promote(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    path: web::Path<(String, String)>,
+    query: web::Query<PromoteQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let version = if_match(&req, false)?;
+    let flags = query.flags.as_deref().map(split_list);
+    let db = &data.db;
+    let repo = environment_repository_factory(db).await;
+    let flag_repo = feature_flags_repository_factory(db).await;
+    let (source_id, target_id) = path.into_inner();
+    let change = environment_handlers::promote(
+        &repo,
+        &project.0,
+        &flag_repo,
+        &source_id,
+        &target_id,
+        PromoteOptions { flags: flags.as_deref(), preview: query.preview },
+        version,
+    ).await?;
+    let changes = diff(&change.before.tracked_fields(), &change.after.tracked_fields());
+    if query.preview {
+        return Ok(HttpResponse::Ok().json(Promotion {
+            preview: true,
+            changes,
+            environment: change.after,
+        }));
+    }
+    let history_repo = environment_revisions_repository_factory(db).await;
+    data.snapshots.invalidate_env(&change.after.project, &change.after.name);
+    publish_change(&data, &change.after.project, &[ChangedEntity::Environment(change.after.name.clone())]).await;
+    record_revision(
+        &history_repo, RevisionAction::Updated, Some(&change.before), &change.after, &actor(&req),
+    ).await;
+    audit(&req, db, "environment.promote", Some(&change.before), Some(&change.after)).await;
+    let env = change.after;
+    Ok(HttpResponse::Accepted().insert_header(entity_tag(env.version)).json(Promotion {
+        preview: false,
+        changes,
+        environment: env,
+    }))
+}
+
+/// Environments are created without overrides, they are set with `PUT /{id}/flags`
+#[derive(Deserialize)]
+struct EnvironmentCreateSchema {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct DiffQuery {
+    /// Environment name
+    left: String,
+    /// Environment name
+    right: String,
+}
+
+#[derive(Deserialize)]
+struct PromoteQuery {
+    /// Comma separated list of flag names, every flag is promoted when missing
+    flags: Option<String>,
+    #[serde(default)]
+    preview: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Promotion {
+    preview: bool,
+    changes: Vec<FieldChange>,
+    environment: Environment,
+}
+
+#[derive(Deserialize)]
+struct OverrideBatchSchema {
+    #[serde(default)]
+    mode: BatchMode,
+    operations: Vec<OverrideOperation>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RevisionList {
+    items: Vec<Revision<Environment>>,
+}
+
 pub fn create_scope() -> Scope {
     web::scope("/admin/environments")
         .route("", web::get().to(find))
+        // Before `/{id}`, which would match it otherwise
+        .route("/diff", web::get().to(diff_environments))
         .route("/{id}", web::get().to(get))
         .route("", web::post().to(create))
         .route("/{id}", web::delete().to(delete))
+        .route("/{id}/restore", web::post().to(restore))
+        .route("/{id}/purge", web::delete().to(purge))
         .route("/{id}/flags", web::put().to(set_flag))
         .route("/{id}/flags/{name}", web::delete().to(remove_flag))
+        .service(
+            web::resource("/{id}/flags:batch")
+                .app_data(web::JsonConfig::default().limit(BATCH_PAYLOAD_LIMIT))
+                .route(web::post().to(batch_flags)),
+        )
+        .route("/{id}/history", web::get().to(history))
+        .route("/{id}/rollback/{revision}", web::post().to(rollback))
+        .route("/{source}/promote/{target}", web::post().to(promote))
 }
 
 
 #[cfg(test)]
 mod tests {
-    use std::process::id;
     use actix_web::{App, test};
-    use actix_web::http::StatusCode;
-    use mongodb::bson::doc;
+    use actix_web::http::{self, StatusCode};
     use serde_json::json;
-    use crate::adapters::repositories::environment_repository::environment_repository_factory;
-    use crate::adapters::repositories::feature_flags_repository::feature_flags_repository_factory;
-    use crate::{AppState, get_state};
-    use crate::database::init_db;
-    use crate::domain::models::Environment;
+    use feature_flags_storage::repositories::environment_repository::environment_repository_factory;
+    use crate::get_state;
+    use feature_flags_storage::database::init_db;
+    use feature_flags_core::models::{Environment, FeatureFlag};
     use crate::resources::feature_flags_api;
+    use feature_flags_storage::repositories::BaseRepository;
     use super::*;
 
     #[actix_web::test]
@@ -183,7 +439,6 @@ mod tests {
                 .service(create_scope()),
         )
         .await;
-        let env = Environment::new("development");
         let req = test::TestRequest::get()
             .uri("/admin/environments").to_request();
         let resp = test::call_service(&app, req).await;
@@ -192,7 +447,6 @@ mod tests {
 
     #[actix_web::test]
     async fn test_environment_integration() {
-        let db = init_db().await.unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::clone(&get_state().await))
@@ -209,14 +463,29 @@ mod tests {
 
         // Get env by id
         let req = test::TestRequest::get()
-            .uri(&format!("/admin/environments/{}", resp.id.unwrap().to_string()))
+            .uri(&format!("/admin/environments/{}", resp.id.unwrap()))
             .to_request();
         let resp: Environment = test::call_and_read_body_json(&app, req).await;
         assert_eq!(resp.name, "dev_integration_test");
 
-        // Delete env
+        let env_id = resp.id.unwrap();
+
+        // Delete archives the env
         let req = test::TestRequest::delete()
-            .uri(&format!("/admin/environments/{}", resp.id.unwrap()))
+            .uri(&format!("/admin/environments/{}", env_id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/environments?name=dev_integration_test&archived=true")
+            .to_request();
+        let resp: EnvironmentList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.total, 1);
+
+        // Purge env
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/environments/{}/purge", env_id))
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), StatusCode::NO_CONTENT);
@@ -224,7 +493,6 @@ mod tests {
 
     #[actix_web::test]
     async fn test_env_manage_flags() {
-        let db = init_db().await.unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::clone(&get_state().await))
@@ -254,11 +522,23 @@ mod tests {
         // Add flag to env
         let req = test::TestRequest::put()
             .uri(&format!("/admin/environments/{}/flags", env_id))
+            .insert_header((http::header::IF_MATCH, resp.version.to_string()))
             .set_json(Json(flag))
             .to_request();
         let resp: Environment = test::call_and_read_body_json(&app, req).await;
         assert_eq!(resp.flags.len(), 1);
 
+        // Flag can't be purged while the env uses it
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/feature_flags/{}", &flag_id))
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/feature_flags/{}/purge", &flag_id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
         // Remove flag from env
         let req = test::TestRequest::delete()
             .uri(&format!("/admin/environments/{}/flags/{}", &env_id, "flag_to_be_added"))
@@ -266,17 +546,218 @@ mod tests {
         let resp: Environment = test::call_and_read_body_json(&app, req).await;
         assert_eq!(resp.flags.len(), 0);
 
+        // Purge flag
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/feature_flags/{}/purge", &flag_id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
         // Delete env
         let req = test::TestRequest::delete()
             .uri(&format!("/admin/environments/{}", &env_id))
             .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/environments/{}/purge", &env_id))
+            .to_request();
+        test::call_service(&app, req).await;
+    }
+
+    #[actix_web::test]
+    async fn test_batch_flags() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&get_state().await))
+                .service(create_scope())
+                .service(feature_flags_api::create_scope()),
+        )
+        .await;
+
+        let flag = FeatureFlag::new("flag_for_batch", "Sample Flag", false, vec![]);
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags")
+            .set_json(Json(flag))
+            .to_request();
+        let resp: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        let flag_id = resp.id.unwrap().to_string();
+
+        let req = test::TestRequest::post()
+            .uri("/admin/environments")
+            .set_json(Json(Environment::new("test_env_batch")))
+            .to_request();
+        let resp: Environment = test::call_and_read_body_json(&app, req).await;
+        let env_id = resp.id.unwrap().to_string();
+
+        // Atomic, the unknown flag aborts the batch
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/environments/{}/flags:batch", env_id))
+            .set_json(json!({
+                "mode": "atomic",
+                "operations": [
+                    {"op": "create", "name": "flag_for_batch", "enabled": true},
+                    {"op": "create", "name": "unknown_flag", "enabled": true},
+                ],
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // Best effort
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/environments/{}/flags:batch", env_id))
+            .insert_header((http::header::IF_MATCH, "0"))
+            .set_json(json!({
+                "mode": "best_effort",
+                "operations": [
+                    {"op": "create", "name": "flag_for_batch", "enabled": true},
+                    {"op": "delete", "name": "unknown_flag"},
+                ],
+            }))
+            .to_request();
         let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(http::header::ETAG).unwrap(), "\"1\"");
 
-        // Delete flag
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/environments/{}", env_id))
+            .to_request();
+        let resp: Environment = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.flags.len(), 1);
+        assert_eq!(resp.flags[0].enabled, Some(true));
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/environments/{}", &env_id))
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/environments/{}/purge", &env_id))
+            .to_request();
+        test::call_service(&app, req).await;
         let req = test::TestRequest::delete()
             .uri(&format!("/admin/feature_flags/{}", &flag_id))
             .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/feature_flags/{}/purge", &flag_id))
+            .to_request();
+        test::call_service(&app, req).await;
+    }
+
+    #[actix_web::test]
+    async fn test_promote() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&get_state().await))
+                .service(create_scope())
+                .service(feature_flags_api::create_scope()),
+        )
+        .await;
+
+        let flag = FeatureFlag::new("flag_to_promote", "Sample Flag", false, vec![]);
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags")
+            .set_json(Json(flag))
+            .to_request();
+        let resp: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        let flag_id = resp.id.unwrap().to_string();
+
+        let mut ids = vec![];
+        for name in ["test_env_promote_source", "test_env_promote_target"] {
+            let req = test::TestRequest::post()
+                .uri("/admin/environments")
+                .set_json(Json(Environment::new(name)))
+                .to_request();
+            let resp: Environment = test::call_and_read_body_json(&app, req).await;
+            ids.push(resp.id.unwrap().to_string());
+        }
+        let req = test::TestRequest::put()
+            .uri(&format!("/admin/environments/{}/flags", ids[0]))
+            .insert_header((http::header::IF_MATCH, "*"))
+            .set_json(json!({"name": "flag_to_promote", "enabled": true}))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        // Preview leaves the target unchanged
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/environments/{}/promote/{}?preview=true", ids[0], ids[1]))
+            .to_request();
+        let resp: Promotion = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.changes.len(), 1);
+        assert_eq!(resp.changes[0].field, "flags.flag_to_promote");
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/environments/{}", ids[1]))
+            .to_request();
+        let resp: Environment = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.flags.is_empty());
+
+        // Unknown flags are rejected
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/environments/{}/promote/{}?flags=unknown_flag", ids[0], ids[1]))
+            .to_request();
         let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/environments/{}/promote/{}?flags=flag_to_promote", ids[0], ids[1]))
+            .to_request();
+        let resp: Promotion = test::call_and_read_body_json(&app, req).await;
+        assert!(!resp.preview);
+        assert_eq!(resp.environment.get_override("flag_to_promote").unwrap().enabled, Some(true));
+
+        for id in ids {
+            let req = test::TestRequest::delete()
+                .uri(&format!("/admin/environments/{}", &id))
+                .to_request();
+            test::call_service(&app, req).await;
+            let req = test::TestRequest::delete()
+                .uri(&format!("/admin/environments/{}/purge", &id))
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/feature_flags/{}", &flag_id))
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/feature_flags/{}/purge", &flag_id))
+            .to_request();
+        test::call_service(&app, req).await;
     }
 
-}
\ No newline at end of file
+    #[actix_web::test]
+    async fn test_diff() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&get_state().await))
+                .service(create_scope()),
+        )
+        .await;
+        let db = init_db().await.unwrap();
+        let repo = environment_repository_factory(&db).await;
+        let mut ids = vec![];
+        for (name, flag_name) in [("test_env_diff_left", "left_flag"), ("test_env_diff_right", "right_flag")] {
+            let mut env = Environment::new(name);
+            env.set_override(FlagOverride::new(flag_name));
+            ids.push(repo.create(&env).await.unwrap());
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/admin/environments/diff?left=test_env_diff_left&right=test_env_diff_right")
+            .to_request();
+        let resp: environment_handlers::EnvironmentDiff = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.only_left, vec!["left_flag"]);
+        assert_eq!(resp.only_right, vec!["right_flag"]);
+        assert!(resp.changed.is_empty());
+
+        let req = test::TestRequest::get()
+            .uri("/admin/environments/diff?left=test_env_diff_left&right=unknown_env")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        for id in ids {
+            repo.delete(&id).await.unwrap();
+        }
+    }
+}
@@ -1,109 +1,307 @@
-use std::sync::Mutex;
-use crate::adapters::repositories::feature_flags_repository::feature_flags_repository_factory;
-use crate::domain::models::{FeatureFlag, Rule};
-use crate::resources::CustomError;
-use crate::services::{feature_flag_handlers, ServiceError};
+use feature_flags_storage::repositories::environment_repository::environment_repository_factory;
+use feature_flags_storage::repositories::feature_flags_repository::feature_flags_repository_factory;
+use feature_flags_storage::repositories::revision_repository::feature_flag_revisions_repository_factory;
+use feature_flags_storage::domain::history::{Revision, RevisionAction};
+use feature_flags_core::models::{FeatureFlag, FlagMetadata, Rule};
+use feature_flags_storage::domain::sync::ChangedEntity;
+use crate::resources::{
+    actor, audit, auditor, batch_response, check_batch_size, ensure_project, entity_tag, if_match, page_request,
+    publish_change, record_revision, split_list, BatchMode, CustomError, ProjectScope, BATCH_PAYLOAD_LIMIT,
+};
+use crate::services::feature_flag_handlers::{FlagOperation, FlagUpdate, TagCount};
+use crate::services::{feature_flag_handlers, history_handlers};
 use crate::AppState;
 use actix_web::web::Json;
-use actix_web::{web, HttpResponse, Result, Scope};
-use chrono::Utc;
-use mongodb::bson::oid::ObjectId;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Resource, Result, Scope};
 use serde::{Deserialize, Serialize};
-use crate::adapters::repositories::{ErrorKind, RepositoryError};
+use feature_flags_storage::repositories::ErrorKind;
 
-async fn find(data: web::Data<Mutex<AppState>>) -> Result<Json<FeatureFlagList>> {
-    let db = &data.lock().unwrap().db;
-    let repo = feature_flags_repository_factory(db).await;
-    let res = feature_flag_handlers::find(&repo, None).await;
-    let flags = match res {
-        Ok(flags) => flags,
-        Err(_) => vec![],
+async fn find(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    query: web::Query<FeatureFlagListQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let page = page_request(
+        query.limit,
+        query.cursor.as_deref(),
+        query.sort.as_deref(),
+        query.order.as_deref(),
+    )?;
+    let filters = feature_flag_handlers::Filters {
+        project: Some(project.0),
+        name: query.name.clone(),
+        label: query.label.clone(),
+        enabled: query.enabled,
+        tags: query.tags.as_deref().map(split_list).unwrap_or_default(),
+        owner: query.owner.clone(),
+        archived: query.archived.unwrap_or(false),
     };
-    Ok(Json(FeatureFlagList { items: flags }))
+    let db = &data.db;
+    let repo = feature_flags_repository_factory(db).await;
+    match feature_flag_handlers::find_page(&repo, &filters, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(FeatureFlagList {
+            items: page.items,
+            total: page.total,
+            next_cursor: page.next_cursor,
+        })),
+        Err(_) => Err(CustomError::ApplicationError),
+    }
 }
 
-async fn get(data: web::Data<Mutex<AppState>>, id: web::Path<String>) -> Result<HttpResponse, CustomError> {
-    let db = &data.lock().unwrap().db;
+async fn get(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    id: web::Path<String>,
+) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
     let repo = feature_flags_repository_factory(db).await;
-    let flag_id = id.into_inner();
-    match feature_flag_handlers::get(&repo, &flag_id).await {
-        Ok(mut flag) => {
-            let _id = ObjectId::parse_str(flag_id).unwrap();
-            flag.id = Some(_id);
-            Ok(HttpResponse::Ok().json(Json(flag)))
+    match feature_flag_handlers::get(&repo, &project.0, &id.into_inner()).await {
+        Ok(flag) => {
+            Ok(HttpResponse::Ok().insert_header(entity_tag(flag.version)).json(Json(flag)))
         }
         Err(_) => Err(CustomError::NotFound),
     }
 }
 
 async fn create(
-    data: web::Data<Mutex<AppState>>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
     body: Json<FeatureFlagCreateSchema>,
 ) -> Result<HttpResponse, CustomError> {
-    let mut app_data = data.lock().unwrap();
-    let db = &app_data.db;
+    let db = &data.db;
+    ensure_project(db, &project).await?;
     let repo = feature_flags_repository_factory(db).await;
+    let history_repo = feature_flag_revisions_repository_factory(db).await;
     match feature_flag_handlers::create(
-        &repo, &body.name, &body.label, body.enabled, &body.rules,
+        &repo, &project.0, &body.name, &body.label, body.enabled, &body.rules, &body.metadata,
     ).await {
         Ok(id) => {
-            match feature_flag_handlers::get(&repo, &id).await {
+            match feature_flag_handlers::get(&repo, &project.0, &id).await {
                 Ok(f) => {
                     // Flag created, invalidate cache
-                    app_data.flags = vec![];
+                    data.snapshots.invalidate_flags(&project.0);
+                    publish_change(&data, &project.0, &[ChangedEntity::Flag(f.name.clone())]).await;
+                    record_revision(&history_repo, RevisionAction::Created, None, &f, &actor(&req)).await;
+                    audit(&req, db, "feature_flag.create", None, Some(&f)).await;
                     Ok(
-                        HttpResponse::Created().json(Json(f))
+                        HttpResponse::Created().insert_header(entity_tag(f.version)).json(Json(f))
                     )
                 },
-                Err(err) => Err(CustomError::ApplicationError)
+                Err(_) => Err(CustomError::ApplicationError)
             }
         }
-        Err(_) => Err(CustomError::Conflict),
+        Err(err) => match err.kind {
+            ErrorKind::InvalidArgument => Err(err.into()),
+            _ => Err(CustomError::Conflict),
+        },
     }
 }
 
 async fn update(
-    data: web::Data<Mutex<AppState>>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
     body: Json<FeatureFlagUpdateSchema>,
     id: web::Path<String>,
 ) -> Result<HttpResponse, CustomError> {
-    let mut app_data = data.lock().unwrap();
-    let db = &app_data.db;
+    let version = if_match(&req, true)?;
+    let db = &data.db;
     let repo = feature_flags_repository_factory(db).await;
+    let history_repo = feature_flag_revisions_repository_factory(db).await;
     let flag_id = id.into_inner();
-    match feature_flag_handlers::update(&repo, &flag_id, &body.label, body.enabled, body.rules.to_vec()).await {
-        Ok(id) => {
-            match feature_flag_handlers::get(&repo, &flag_id).await {
-                Ok(f) => {
-                    // Flag updated, invalidate cache
-                    app_data.flags = vec![];
-                    Ok(
-                        HttpResponse::Accepted().json(Json(f))
-                    )
-                },
-                Err(err) => Err(CustomError::ApplicationError)
-            }
+    match feature_flag_handlers::update(&repo, &project.0, &flag_id, body.into_inner(), version).await {
+        Ok(change) => {
+            // Flag updated, invalidate cache
+            data.snapshots.invalidate_flags(&project.0);
+            publish_change(&data, &project.0, &[ChangedEntity::Flag(change.after.name.clone())]).await;
+            record_revision(
+                &history_repo, RevisionAction::Updated, Some(&change.before), &change.after, &actor(&req),
+            ).await;
+            audit(&req, db, "feature_flag.update", Some(&change.before), Some(&change.after)).await;
+            let f = change.after;
+            Ok(
+                HttpResponse::Accepted().insert_header(entity_tag(f.version)).json(Json(f))
+            )
+        },
+        Err(err) => match err.kind {
+            ErrorKind::NotFound | ErrorKind::VersionConflict => Err(err.into()),
+            _ => Err(CustomError::Conflict),
         },
-        Err(_) => Err(CustomError::Conflict),
     }
 }
 
+/// Archives the flag, see `purge` to remove it permanently
 async fn delete(
-    data: web::Data<Mutex<AppState>>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
     id: web::Path<String>,
 ) -> Result<HttpResponse, CustomError> {
-    let mut app_data = data.lock().unwrap();
-    let db = &app_data.db;
+    let version = if_match(&req, false)?;
+    let db = &data.db;
     let repo = feature_flags_repository_factory(db).await;
-    let flag_id = id.into_inner();
-    match feature_flag_handlers::delete(&repo, &flag_id).await {
-        Ok(_) => {
-            // Flag deleted, invalidate cache
-            app_data.flags = vec![];
-            Ok(HttpResponse::NoContent().finish())
-        },
-        Err(_) => Err(CustomError::NotFound),
+    let history_repo = feature_flag_revisions_repository_factory(db).await;
+    let change = feature_flag_handlers::archive(&repo, &project.0, &id.into_inner(), version).await?;
+    // Flag archived, invalidate cache
+    data.snapshots.invalidate_flags(&project.0);
+    publish_change(&data, &project.0, &[ChangedEntity::Flag(change.after.name.clone())]).await;
+    record_revision(
+        &history_repo, RevisionAction::Archived, Some(&change.before), &change.after, &actor(&req),
+    ).await;
+    audit(&req, db, "feature_flag.archive", Some(&change.before), Some(&change.after)).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn restore(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    id: web::Path<String>,
+) -> Result<HttpResponse, CustomError> {
+    let version = if_match(&req, false)?;
+    let db = &data.db;
+    let repo = feature_flags_repository_factory(db).await;
+    let history_repo = feature_flag_revisions_repository_factory(db).await;
+    let change = feature_flag_handlers::restore(&repo, &project.0, &id.into_inner(), version).await?;
+    // Flag restored, invalidate cache
+    data.snapshots.invalidate_flags(&project.0);
+    publish_change(&data, &project.0, &[ChangedEntity::Flag(change.after.name.clone())]).await;
+    record_revision(
+        &history_repo, RevisionAction::Restored, Some(&change.before), &change.after, &actor(&req),
+    ).await;
+    audit(&req, db, "feature_flag.restore", Some(&change.before), Some(&change.after)).await;
+    let f = change.after;
+    Ok(HttpResponse::Accepted().insert_header(entity_tag(f.version)).json(Json(f)))
+}
+
+/// Permanently removes an archived flag
+async fn purge(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    id: web::Path<String>,
+) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
+    let repo = feature_flags_repository_factory(db).await;
+    let env_repo = environment_repository_factory(db).await;
+    let history_repo = feature_flag_revisions_repository_factory(db).await;
+    let flag = feature_flag_handlers::purge(&repo, &env_repo, &project.0, &id.into_inner()).await?;
+    // Flag and its overrides removed, invalidate cache
+    data.snapshots.invalidate_project(&project.0);
+    publish_change(&data, &project.0, &[ChangedEntity::Project]).await;
+    record_revision(&history_repo, RevisionAction::Deleted, None, &flag, &actor(&req)).await;
+    audit(&req, db, "feature_flag.purge", Some(&flag), None).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn history(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    id: web::Path<String>,
+) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
+    let repo = feature_flags_repository_factory(db).await;
+    let history_repo = feature_flag_revisions_repository_factory(db).await;
+    let flag_id = feature_flag_handlers::resolve_id(&repo, &project.0, &id.into_inner()).await?;
+    let mut revisions = history_handlers::find(&history_repo, &flag_id).await?;
+    revisions.retain(|revision| revision.snapshot.project == project.0);
+    Ok(HttpResponse::Ok().json(RevisionList { items: revisions }))
+}
+
+async fn rollback(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    path: web::Path<(String, i64)>,
+) -> Result<HttpResponse, CustomError> {
+    let version = if_match(&req, false)?;
+    let db = &data.db;
+    let repo = feature_flags_repository_factory(db).await;
+    let history_repo = feature_flag_revisions_repository_factory(db).await;
+    let (flag_id, revision) = path.into_inner();
+    let change = feature_flag_handlers::rollback(&repo, &project.0, &history_repo, &flag_id, revision, version).await?;
+    // Flag updated, invalidate cache
+    data.snapshots.invalidate_flags(&project.0);
+    publish_change(&data, &project.0, &[ChangedEntity::Flag(change.after.name.clone())]).await;
+    record_revision(
+        &history_repo, RevisionAction::RolledBack(revision), Some(&change.before), &change.after, &actor(&req),
+    ).await;
+    audit(&req, db, "feature_flag.rollback", Some(&change.before), Some(&change.after)).await;
+    let f = change.after;
+    Ok(HttpResponse::Accepted().insert_header(entity_tag(f.version)).json(Json(f)))
+}
+
+async fn batch(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    body: Json<FeatureFlagBatchSchema>,
+) -> Result<HttpResponse, CustomError> {
+    let FeatureFlagBatchSchema { mode, operations } = body.into_inner();
+    check_batch_size(operations.len())?;
+    let size = operations.len();
+    let actions: Vec<RevisionAction> = operations
+        .iter()
+        .map(|operation| match operation {
+            FlagOperation::Create { .. } => RevisionAction::Created,
+            FlagOperation::Update { .. } | FlagOperation::Toggle { .. } => RevisionAction::Updated,
+            FlagOperation::Delete { .. } => RevisionAction::Archived,
+        })
+        .collect();
+    let db = &data.db;
+    ensure_project(db, &project).await?;
+    let repo = feature_flags_repository_factory(db).await;
+    let history_repo = feature_flag_revisions_repository_factory(db).await;
+    // Audited by the batch, in its transaction when atomic
+    let auditor = auditor(&req, db).await;
+    let res = feature_flag_handlers::batch(
+        &repo, &data.client, &project.0, operations, mode == BatchMode::Atomic, &auditor,
+    ).await?;
+    if res.committed {
+        // Flags changed, invalidate cache
+        data.snapshots.invalidate_flags(&project.0);
+        let changes: Vec<ChangedEntity> = res.results.iter().flatten()
+            .map(|write| ChangedEntity::Flag(write.after.name.clone()))
+            .collect();
+        publish_change(&data, &project.0, &changes).await;
+        for (write, action) in res.results.iter().zip(actions) {
+            if let Ok(write) = write {
+                record_revision(
+                    &history_repo, action, write.before.as_ref(), &write.after, &actor(&req),
+                ).await;
+            }
+        }
     }
+    Ok(batch_response(
+        &res,
+        size,
+        None,
+        |write| write.after.id.map(|id| id.to_hex()),
+        |write| if write.before.is_none() { StatusCode::CREATED } else { StatusCode::OK },
+    ))
+}
+
+async fn tags(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
+    let repo = feature_flags_repository_factory(db).await;
+    let tags = feature_flag_handlers::tags(&repo, &project.0).await?;
+    Ok(HttpResponse::Ok().json(TagList { items: tags }))
+}
+
+pub fn create_tags_resource() -> Resource {
+    web::resource("/admin/tags").route(web::get().to(tags))
+}
+
+/// `:batch` is not a separate path segment, so the route can't be part of the scope
+pub fn create_batch_resource() -> Resource {
+    web::resource("/admin/feature_flags:batch")
+        .app_data(web::JsonConfig::default().limit(BATCH_PAYLOAD_LIMIT))
+        .route(web::post().to(batch))
 }
 
 pub fn create_scope() -> Scope {
@@ -113,47 +311,77 @@ pub fn create_scope() -> Scope {
         .route("", web::post().to(create))
         .route("/{id}", web::delete().to(delete))
         .route("/{id}", web::put().to(update))
+        .route("/{id}/restore", web::post().to(restore))
+        .route("/{id}/purge", web::delete().to(purge))
+        .route("/{id}/history", web::get().to(history))
+        .route("/{id}/rollback/{revision}", web::post().to(rollback))
 }
 
 #[derive(Serialize, Deserialize)]
 struct FeatureFlagList {
     items: Vec<FeatureFlag>,
+    total: u64,
+    next_cursor: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
-struct FeatureFlagUpdateSchema {
-    label: String,
-    enabled: bool,
-    rules: Vec<Rule>
+struct TagList {
+    items: Vec<TagCount>,
 }
 
+#[derive(Serialize, Deserialize)]
+struct RevisionList {
+    items: Vec<Revision<FeatureFlag>>,
+}
+
+#[derive(Deserialize)]
+struct FeatureFlagListQuery {
+    limit: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+    order: Option<String>,
+    name: Option<String>,
+    label: Option<String>,
+    enabled: Option<bool>,
+    /// Comma separated list of tags
+    tags: Option<String>,
+    owner: Option<String>,
+    archived: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct FeatureFlagBatchSchema {
+    #[serde(default)]
+    mode: BatchMode,
+    operations: Vec<FlagOperation>,
+}
+
+pub type FeatureFlagUpdateSchema = FlagUpdate;
+
 #[derive(Serialize, Deserialize)]
 pub struct FeatureFlagCreateSchema {
     pub(crate) name: String,
     pub(crate) label: String,
     pub(crate) enabled: bool,
-    pub(crate) rules: Vec<Rule>
+    pub(crate) rules: Vec<Rule>,
+    #[serde(flatten)]
+    pub(crate) metadata: FlagMetadata,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::models::{Operator, Rule};
-    use crate::{AppState, get_state};
+    use feature_flags_core::models::FlagMetadataUpdate;
+    use feature_flags_core::evaluation::{Operator, Rule};
+    use crate::get_state;
     use actix_web::http::StatusCode;
-    use actix_web::{
-        http::{self, header::ContentType},
-        test, App, HttpResponse,
-    };
-    use mongodb::bson::doc;
-    use std::process::id;
-    use chrono::Utc;
-    use mongodb::bson;
-    use crate::database::init_db;
+    use actix_web::{http, test, App};
+    use mongodb::bson::oid::ObjectId;
+    use feature_flags_storage::database::supports_transactions;
+    use crate::resources::BatchResponse;
 
     #[actix_web::test]
     async fn test_feature_flag_resource() {
-        let db = init_db().await.unwrap();
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::clone(&get_state().await))
@@ -170,6 +398,7 @@ mod tests {
                     operator: Operator::Is("tenant1".to_string()),
                 }
             ],
+            metadata: FlagMetadata::default(),
         };
 
         // Create flag
@@ -190,24 +419,397 @@ mod tests {
         let resp: FeatureFlag = test::call_and_read_body_json(&app, req).await;
         assert_eq!(resp.name, "sample_flag_integration_test");
 
+        // Get by name, ids are serialized as hex strings
+        let req = test::TestRequest::get()
+            .uri("/admin/feature_flags/sample_flag_integration_test")
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["_id"], serde_json::Value::String(id.clone()));
+
         // Test update
         let update_flag = FeatureFlagUpdateSchema {
             label: "Updated Label".to_string(),
             enabled: true,
-            rules: vec![]
+            rules: vec![],
+            metadata: FlagMetadataUpdate::default(),
         };
         let req = test::TestRequest::put()
             .uri(&format!("/admin/feature_flags/{}", &id))
-            .set_json(Json(update_flag))
+            .insert_header((http::header::IF_MATCH, "\"0\""))
+            .set_json(Json(&update_flag))
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        assert_eq!(resp.headers().get(http::header::ETAG).unwrap(), "\"1\"");
+
+        // Stale version is rejected
+        let req = test::TestRequest::put()
+            .uri(&format!("/admin/feature_flags/{}", &id))
+            .insert_header((http::header::IF_MATCH, "\"0\""))
+            .set_json(Json(&update_flag))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+
+        // If-Match is required
+        let req = test::TestRequest::put()
+            .uri(&format!("/admin/feature_flags/{}", &id))
+            .set_json(Json(&update_flag))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_REQUIRED);
+
+        // Purge is refused until the flag is archived
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/feature_flags/{}/purge", &id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
 
-        // Delete item
+        // Delete archives the item
         let req = test::TestRequest::delete()
             .uri(&format!("/admin/feature_flags/{}", &id))
             .to_request();
         let resp = test::call_service(&app, req).await;
-        assert_eq!(resp.status(), StatusCode::NO_CONTENT)
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/feature_flags?name=sample_flag_integration_test")
+            .to_request();
+        let resp: FeatureFlagList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.total, 0);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/feature_flags?name=sample_flag_integration_test&archived=true")
+            .to_request();
+        let resp: FeatureFlagList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.total, 1);
+
+        // Restore
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/feature_flags/{}/restore", &id))
+            .to_request();
+        let resp: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        assert!(!resp.archived);
+
+        // Archive and purge, by name
+        let req = test::TestRequest::delete()
+            .uri("/admin/feature_flags/sample_flag_integration_test")
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::delete()
+            .uri("/admin/feature_flags/sample_flag_integration_test/purge")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/feature_flags/{}", &id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_update_keeps_metadata() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&get_state().await))
+                .service(create_scope()),
+        )
+        .await;
+        let flag = FeatureFlagCreateSchema {
+            name: "metadata_update_flag".to_string(),
+            label: "Metadata flag".to_string(),
+            enabled: false,
+            rules: vec![],
+            metadata: FlagMetadata {
+                tags: vec!["checkout".to_string()],
+                owner: Some("payments".to_string()),
+                ..Default::default()
+            },
+        };
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags")
+            .set_json(Json(flag))
+            .to_request();
+        let resp: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        let id = resp.id.unwrap().to_string();
+
+        // An update without metadata keeps the stored metadata
+        let req = test::TestRequest::put()
+            .uri(&format!("/admin/feature_flags/{}", &id))
+            .insert_header((http::header::IF_MATCH, "\"0\""))
+            .set_json(serde_json::json!({
+                "label": "Updated metadata flag",
+                "enabled": true,
+                "rules": [],
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/feature_flags/{}", &id))
+            .to_request();
+        let resp: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.label, "Updated metadata flag");
+        assert_eq!(resp.tags, vec!["checkout".to_string()]);
+        assert_eq!(resp.owner.as_deref(), Some("payments"));
+
+        // Null clears the owner
+        let req = test::TestRequest::put()
+            .uri(&format!("/admin/feature_flags/{}", &id))
+            .insert_header((http::header::IF_MATCH, "\"1\""))
+            .set_json(serde_json::json!({
+                "label": "Updated metadata flag",
+                "enabled": true,
+                "rules": [],
+                "owner": null,
+            }))
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/feature_flags/{}", &id))
+            .to_request();
+        let resp: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.tags, vec!["checkout".to_string()]);
+        assert!(resp.owner.is_none());
+
+        for uri in ["", "/purge"] {
+            let req = test::TestRequest::delete()
+                .uri(&format!("/admin/feature_flags/{}{}", &id, uri))
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_find_paginated() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&get_state().await))
+                .service(create_tags_resource())
+                .service(create_scope()),
+        )
+        .await;
+        let mut ids = vec![];
+        for name in ["paginated_flag_a", "paginated_flag_b", "paginated_flag_c"] {
+            let flag = FeatureFlagCreateSchema {
+                name: name.to_string(),
+                label: "Paginated flag".to_string(),
+                enabled: name != "paginated_flag_c",
+                rules: vec![],
+                metadata: FlagMetadata {
+                    tags: vec!["pagination".to_string()],
+                    owner: Some(if name == "paginated_flag_a" { "growth" } else { "payments" }.to_string()),
+                    ..Default::default()
+                },
+            };
+            let req = test::TestRequest::post()
+                .uri("/admin/feature_flags")
+                .set_json(Json(flag))
+                .to_request();
+            let resp: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+            ids.push(resp.id.unwrap().to_string());
+        }
+
+        // First page
+        let req = test::TestRequest::get()
+            .uri("/admin/feature_flags?name=paginated_flag_&limit=2&sort=name&order=desc")
+            .to_request();
+        let resp: FeatureFlagList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.total, 3);
+        assert_eq!(resp.items.len(), 2);
+        assert_eq!(resp.items[0].name, "paginated_flag_c");
+        let cursor = resp.next_cursor.unwrap();
+
+        // Last page
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/feature_flags?name=paginated_flag_&limit=2&sort=name&order=desc&cursor={}", cursor))
+            .to_request();
+        let resp: FeatureFlagList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.items.len(), 1);
+        assert_eq!(resp.items[0].name, "paginated_flag_a");
+        assert!(resp.next_cursor.is_none());
+
+        // Filters
+        let req = test::TestRequest::get()
+            .uri("/admin/feature_flags?name=paginated_flag_&enabled=true&tags=pagination")
+            .to_request();
+        let resp: FeatureFlagList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.total, 2);
+        let req = test::TestRequest::get()
+            .uri("/admin/feature_flags?name=paginated_flag_&owner=payments")
+            .to_request();
+        let resp: FeatureFlagList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.total, 2);
+
+        // Tags
+        let req = test::TestRequest::get().uri("/admin/tags").to_request();
+        let resp: TagList = test::call_and_read_body_json(&app, req).await;
+        let pagination = resp.items.iter().find(|tag| tag.name == "pagination").unwrap();
+        assert!(pagination.count >= 3);
+
+        // Invalid cursor
+        let req = test::TestRequest::get()
+            .uri("/admin/feature_flags?cursor=invalid")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        for id in ids {
+            let req = test::TestRequest::delete()
+                .uri(&format!("/admin/feature_flags/{}", &id))
+                .to_request();
+            test::call_service(&app, req).await;
+            let req = test::TestRequest::delete()
+                .uri(&format!("/admin/feature_flags/{}/purge", &id))
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_history_and_rollback() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&get_state().await))
+                .service(create_scope()),
+        )
+        .await;
+        let flag = FeatureFlagCreateSchema {
+            name: "flag_with_history".to_string(),
+            label: "Original label".to_string(),
+            enabled: false,
+            rules: vec![],
+            metadata: FlagMetadata::default(),
+        };
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags")
+            .insert_header(("X-Actor", "john"))
+            .set_json(Json(flag))
+            .to_request();
+        let resp: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        let id = resp.id.unwrap().to_string();
+
+        let update_flag = FeatureFlagUpdateSchema {
+            label: "Broken label".to_string(),
+            enabled: true,
+            rules: vec![],
+            metadata: FlagMetadataUpdate::default(),
+        };
+        let req = test::TestRequest::put()
+            .uri(&format!("/admin/feature_flags/{}", &id))
+            .insert_header((http::header::IF_MATCH, "0"))
+            .insert_header(("X-Actor", "jane"))
+            .set_json(Json(update_flag))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/feature_flags/{}/history", &id))
+            .to_request();
+        let resp: RevisionList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.items.len(), 2);
+        assert_eq!(resp.items[0].revision, 1);
+        assert_eq!(resp.items[0].actor, "jane");
+        assert_eq!(resp.items[0].changes.len(), 2);
+
+        // Rollback to the created revision
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/feature_flags/{}/rollback/0", &id))
+            .to_request();
+        let resp: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.label, "Original label");
+        assert!(!resp.enabled);
+        assert_eq!(resp.version, 2);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/feature_flags/{}/history", &id))
+            .to_request();
+        let resp: RevisionList = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.items.len(), 3);
+        assert_eq!(resp.items[0].action, RevisionAction::RolledBack(0));
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/feature_flags/{}", &id))
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/feature_flags/{}/purge", &id))
+            .to_request();
+        test::call_service(&app, req).await;
+    }
+
+    #[actix_web::test]
+    async fn test_batch() {
+        let state = get_state().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&state))
+                .service(create_batch_resource())
+                .service(create_scope()),
+        )
+        .await;
+
+        // Best effort, the duplicated creation fails alone
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags:batch")
+            .set_json(serde_json::json!({
+                "mode": "best_effort",
+                "operations": [
+                    {"op": "create", "name": "batch_flag", "label": "Batch flag"},
+                    {"op": "create", "name": "batch_flag", "label": "Batch flag"},
+                ],
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp: BatchResponse = test::read_body_json(resp).await;
+        assert!(resp.committed);
+        assert_eq!(resp.results[0].status, 201);
+        assert_eq!(resp.results[1].status, 409);
+        let id = resp.results[0].id.clone().unwrap();
+
+        // Atomic, nothing is applied when an operation fails. Deployments without
+        // transactions reject atomic batches.
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags:batch")
+            .set_json(serde_json::json!({
+                "mode": "atomic",
+                "operations": [
+                    {"op": "toggle", "id": &id},
+                    {"op": "delete", "id": ObjectId::new().to_hex()},
+                    {"op": "create", "name": "batch_flag_skipped", "label": "Skipped"},
+                ],
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        if supports_transactions(&state.client).await.unwrap() {
+            assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            let resp: BatchResponse = test::read_body_json(resp).await;
+            assert!(!resp.committed);
+            assert_eq!(resp.results.iter().map(|item| item.status).collect::<Vec<_>>(), vec![424, 404, 424]);
+        } else {
+            assert_eq!(resp.status(), StatusCode::NOT_IMPLEMENTED);
+        }
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/feature_flags/{}", &id))
+            .to_request();
+        let resp: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        assert!(!resp.enabled);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags:batch")
+            .set_json(serde_json::json!({"operations": [{"op": "delete", "id": &id}]}))
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/feature_flags/{}/purge", &id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
     }
 }
@@ -1,11 +1,37 @@
-use actix_web::http::StatusCode;
-use actix_web::{HttpResponse, ResponseError};
-use serde::Serialize;
+use actix_web::dev::{Payload, ServiceRequest};
+use actix_web::http::header::{self, EntityTag, ETag, HeaderValue};
+use actix_web::http::{StatusCode, Uri};
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use futures::future::{ready, Ready};
+use mongodb::bson::oid::ObjectId;
+use mongodb::Database;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use feature_flags_storage::repositories::audit_repository::audit_repository_factory;
+use feature_flags_storage::repositories::ErrorKind;
+use feature_flags_storage::repositories::pagination::{PageRequest, Sort};
+use feature_flags_storage::repositories::project_repository::project_repository_factory;
+use feature_flags_storage::repositories::revision_counter_repository::revision_counter_repository_factory;
+use feature_flags_storage::domain::audit::Auditable;
+use feature_flags_core::models::DEFAULT_PROJECT;
+use feature_flags_storage::domain::sync::ChangedEntity;
+use feature_flags_storage::domain::history::{Revision, RevisionAction, Revisioned};
+use feature_flags_storage::repositories::revision_repository::RevisionRepository;
+use crate::services::audit_handlers::Auditor;
+use crate::services::{history_handlers, project_handlers, sync_handlers, Batch, ServiceError};
+use crate::AppState;
 
+pub mod audit_api;
+pub mod cache_api;
 pub mod feature_flags_api;
 pub mod environments_api;
 pub mod client_api;
+pub mod client_ws;
+pub mod config_api;
+pub mod projects_api;
+pub mod reports_api;
+pub mod sdk_api;
 
 #[derive(Error, Debug)]
 enum CustomError {
@@ -22,7 +48,17 @@ enum CustomError {
     #[error("No Content")]
     NoContent,
     #[error("Creation error")]
-    CreationError(String)
+    CreationError(String),
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("Resource was modified, fetch it again and retry")]
+    PreconditionFailed,
+    #[error("If-Match header is required")]
+    PreconditionRequired,
+    #[error("{0}")]
+    InvalidState(String),
+    #[error("{0}")]
+    NotImplemented(String),
 }
 
 impl CustomError {
@@ -34,7 +70,12 @@ impl CustomError {
             Self::ApplicationError => "ApplicationError".to_string(),
             Self::Conflict => "Conflict".to_string(),
             Self::NoContent => "NoContent".to_string(),
-            Self::CreationError(err) => format!("Creation error: {}", err.to_string())
+            Self::CreationError(err) => format!("Creation error: {}", err),
+            Self::BadRequest(_) => "BadRequest".to_string(),
+            Self::PreconditionFailed => "PreconditionFailed".to_string(),
+            Self::PreconditionRequired => "PreconditionRequired".to_string(),
+            Self::InvalidState(_) => "Conflict".to_string(),
+            Self::NotImplemented(_) => "NotImplemented".to_string(),
         }
     }
 }
@@ -49,6 +90,11 @@ impl ResponseError for CustomError {
             Self::Conflict => StatusCode::CONFLICT,
             Self::NoContent => StatusCode::NO_CONTENT,
             Self::CreationError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Self::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            Self::InvalidState(_) => StatusCode::CONFLICT,
+            Self::NotImplemented(_) => StatusCode::NOT_IMPLEMENTED,
         }
     }
 
@@ -63,9 +109,346 @@ impl ResponseError for CustomError {
     }
 }
 
+impl From<ServiceError> for CustomError {
+    fn from(err: ServiceError) -> Self {
+        match err.kind {
+            ErrorKind::NotFound => Self::NotFound,
+            ErrorKind::AlreadyExists => Self::Conflict,
+            ErrorKind::InvalidArgument => Self::BadRequest(err.to_string()),
+            ErrorKind::VersionConflict => Self::PreconditionFailed,
+            ErrorKind::InvalidState => Self::InvalidState(err.to_string()),
+            ErrorKind::Unsupported => Self::NotImplemented(err.to_string()),
+            ErrorKind::Internal => Self::ApplicationError,
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct ErrorResponse {
     code: u16,
     error: String,
     message: String,
 }
+
+const SORTABLE_FIELDS: [&str; 3] = ["name", "created_at", "updated_at"];
+
+/// Builds a page request from the `limit`, `cursor`, `sort` and `order` query parameters
+fn page_request(
+    limit: Option<i64>,
+    cursor: Option<&str>,
+    sort: Option<&str>,
+    order: Option<&str>,
+) -> Result<PageRequest, CustomError> {
+    let field = sort.unwrap_or("name");
+    if !SORTABLE_FIELDS.contains(&field) {
+        return Err(CustomError::BadRequest(format!(
+            "sort must be one of {}", SORTABLE_FIELDS.join(", ")
+        )));
+    }
+    let descending = match order.unwrap_or("asc") {
+        "asc" => false,
+        "desc" => true,
+        _ => return Err(CustomError::BadRequest("order must be asc or desc".to_string())),
+    };
+    PageRequest::new(limit, cursor, Sort::new(field, descending))
+        .map_err(|err| CustomError::BadRequest(err.to_string()))
+}
+
+/// Largest number of operations accepted in one batch request
+const MAX_BATCH_SIZE: usize = 500;
+
+/// Payload limit of batch requests, larger than the default one
+const BATCH_PAYLOAD_LIMIT: usize = 4 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BatchMode {
+    /// Every operation is applied or none is, flag batches need a replica set
+    Atomic,
+    /// Operations are applied independently of the others
+    #[default]
+    BestEffort,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BatchResponse {
+    committed: bool,
+    results: Vec<BatchItemResponse>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BatchItemResponse {
+    index: usize,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn check_batch_size(size: usize) -> Result<(), CustomError> {
+    if size == 0 || size > MAX_BATCH_SIZE {
+        return Err(CustomError::BadRequest(format!(
+            "operations must contain between 1 and {} items", MAX_BATCH_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// Reports the result of every operation of a batch. Operations of an aborted batch are
+/// reported with a 424 status and the whole response with a 422 status.
+fn batch_response<T>(
+    batch: &Batch<T>,
+    size: usize,
+    tag: Option<ETag>,
+    id: impl Fn(&T) -> Option<String>,
+    status: impl Fn(&T) -> StatusCode,
+) -> HttpResponse {
+    let not_applied = |index| BatchItemResponse {
+        index,
+        status: StatusCode::FAILED_DEPENDENCY.as_u16(),
+        id: None,
+        error: Some("Not applied, another operation of the batch failed".to_string()),
+    };
+    let results = (0..size)
+        .map(|index| match batch.results.get(index) {
+            Some(Ok(item)) if batch.committed => BatchItemResponse {
+                index,
+                status: status(item).as_u16(),
+                id: id(item),
+                error: None,
+            },
+            Some(Err(err)) => BatchItemResponse {
+                index,
+                status: CustomError::from(err.clone()).status_code().as_u16(),
+                id: None,
+                error: Some(err.to_string()),
+            },
+            _ => not_applied(index),
+        })
+        .collect();
+    let status = if batch.committed { StatusCode::OK } else { StatusCode::UNPROCESSABLE_ENTITY };
+    let mut resp = HttpResponse::build(status);
+    if let Some(tag) = tag {
+        resp.insert_header(tag);
+    }
+    resp.json(BatchResponse { committed: batch.committed, results })
+}
+
+/// Splits a comma separated query parameter, ignoring empty values
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Name of the user making the request, taken from the `X-Actor` header
+fn actor(req: &HttpRequest) -> String {
+    req.headers()
+        .get("X-Actor")
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or("anonymous")
+        .trim()
+        .to_string()
+}
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Makes sure the request carries an `X-Request-Id` header, generating one when missing
+pub fn ensure_request_id(req: &mut ServiceRequest) -> HeaderValue {
+    if let Some(value) = req.headers().get(REQUEST_ID_HEADER) {
+        return value.clone();
+    }
+    let value = HeaderValue::from_str(&ObjectId::new().to_hex()).unwrap();
+    req.headers_mut().insert(header::HeaderName::from_static(REQUEST_ID_HEADER), value.clone());
+    value
+}
+
+/// Project a request applies to, see `scope_project`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProjectScope(pub String);
+
+impl FromRequest for ProjectScope {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let project = req
+            .extensions()
+            .get::<ProjectScope>()
+            .cloned()
+            .unwrap_or_else(|| ProjectScope(DEFAULT_PROJECT.to_string()));
+        ready(Ok(project))
+    }
+}
+
+/// Routes `/projects/{project}/...` requests to the unscoped routes, the project is then
+/// available through the `ProjectScope` extractor. Unscoped requests use the default project.
+pub fn scope_project(req: &mut ServiceRequest) {
+    let (project, path) = match req.path().strip_prefix("/projects/").and_then(|rest| rest.split_once('/')) {
+        Some((project, path)) if !project.is_empty() => (project.to_string(), format!("/{}", path)),
+        _ => return,
+    };
+    let path_and_query = match req.query_string() {
+        "" => path,
+        query => format!("{}?{}", path, query),
+    };
+    let uri = match Uri::builder().path_and_query(path_and_query).build() {
+        Ok(uri) => uri,
+        Err(_) => return,
+    };
+    req.match_info_mut().get_mut().update(&uri);
+    req.head_mut().uri = uri;
+    req.extensions_mut().insert(ProjectScope(project));
+}
+
+/// Id of the request, taken from the `X-Request-Id` header
+fn request_id(req: &HttpRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| ObjectId::new().to_hex())
+}
+
+/// Records a revision of a write that is already stored. Failures are logged instead of
+/// returned, the client would otherwise retry a write that succeeded.
+async fn record_revision<T>(
+    repo: &RevisionRepository<Revision<T>>,
+    action: RevisionAction,
+    before: Option<&T>,
+    after: &T,
+    actor: &str,
+) where
+    T: Revisioned + DeserializeOwned + Clone + Unpin + Send + Sync,
+{
+    if let Err(err) = history_handlers::record(repo, action, before, after, actor).await {
+        let id = after.entity_id().map(|id| id.to_hex()).unwrap_or_default();
+        eprintln!("Failed to record the revision of {}: {}", id, err);
+    }
+}
+
+/// Records the writes of the request in the audit log
+async fn auditor(req: &HttpRequest, db: &Database) -> Auditor {
+    Auditor::new(audit_repository_factory(db).await, &actor(req), &request_id(req))
+}
+
+/// Records an admin write in the audit log. Like `record_revision`, failures are logged
+/// since the write is already stored.
+async fn audit<T: Auditable>(
+    req: &HttpRequest,
+    db: &Database,
+    action: &str,
+    before: Option<&T>,
+    after: Option<&T>,
+) {
+    // Without a session failures are logged, not returned
+    let _ = auditor(req, db).await.record(action, before, after, None).await;
+}
+
+/// Tells the other replicas and the SDKs what changed in the project, once this process
+/// invalidated its entries. Failures are logged since the write is already stored, the
+/// other replicas then pick it up within the max staleness.
+async fn publish_change(data: &AppState, project: &str, changes: &[ChangedEntity]) {
+    let repo = revision_counter_repository_factory(&data.db).await;
+    if let Err(err) = sync_handlers::bump(&repo, &data.snapshots, project, changes).await {
+        eprintln!("Failed to publish the changes of project {}: {}", project, err);
+    }
+}
+
+/// Writes are only accepted in existing projects, the default one always exists
+async fn ensure_project(db: &Database, project: &ProjectScope) -> Result<(), CustomError> {
+    if project.0 == DEFAULT_PROJECT {
+        return Ok(());
+    }
+    let repo = project_repository_factory(db).await;
+    project_handlers::get_by_name(&repo, &project.0).await?;
+    Ok(())
+}
+
+/// `ETag` header holding an entity version
+fn entity_tag(version: i64) -> ETag {
+    ETag(EntityTag::new_strong(version.to_string()))
+}
+
+/// Reads the expected entity version from the `If-Match` header, `*` matches any version
+fn if_match(req: &HttpRequest, required: bool) -> Result<Option<i64>, CustomError> {
+    let value = match req.headers().get(header::IF_MATCH) {
+        None if required => return Err(CustomError::PreconditionRequired),
+        None => return Ok(None),
+        Some(value) => value,
+    };
+    let invalid = || CustomError::BadRequest("Invalid If-Match header".to_string());
+    let value = value.to_str().map_err(|_| invalid())?.trim();
+    if value == "*" {
+        return Ok(None);
+    }
+    value
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse::<i64>()
+        .map(Some)
+        .map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[actix_web::test]
+    async fn test_scope_project() {
+        let mut req = TestRequest::with_uri("/projects/checkout/admin/feature_flags?name=new_ui").to_srv_request();
+        scope_project(&mut req);
+        assert_eq!(req.path(), "/admin/feature_flags");
+        assert_eq!(req.query_string(), "name=new_ui");
+        let (req, mut payload) = req.into_parts();
+        let project = ProjectScope::from_request(&req, &mut payload).await.unwrap();
+        assert_eq!(project, ProjectScope("checkout".to_string()));
+
+        let mut req = TestRequest::with_uri("/flags/production").to_srv_request();
+        scope_project(&mut req);
+        assert_eq!(req.path(), "/flags/production");
+        let (req, mut payload) = req.into_parts();
+        let project = ProjectScope::from_request(&req, &mut payload).await.unwrap();
+        assert_eq!(project.0, DEFAULT_PROJECT);
+    }
+
+    #[test]
+    fn test_if_match() {
+        let req = TestRequest::default().to_http_request();
+        assert!(matches!(if_match(&req, true), Err(CustomError::PreconditionRequired)));
+        assert_eq!(if_match(&req, false).unwrap(), None);
+
+        let req = TestRequest::default().insert_header((header::IF_MATCH, "\"3\"")).to_http_request();
+        assert_eq!(if_match(&req, true).unwrap(), Some(3));
+
+        let req = TestRequest::default().insert_header((header::IF_MATCH, "*")).to_http_request();
+        assert_eq!(if_match(&req, true).unwrap(), None);
+
+        let req = TestRequest::default().insert_header((header::IF_MATCH, "abc")).to_http_request();
+        assert!(matches!(if_match(&req, true), Err(CustomError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_batch_response() {
+        let batch = Batch {
+            committed: false,
+            results: vec![
+                Ok("flag_1".to_string()),
+                Err(ServiceError::new(ErrorKind::AlreadyExists, "Duplicated".to_string())),
+            ],
+        };
+        let resp = batch_response(&batch, 3, None, |id| Some(id.clone()), |_| StatusCode::CREATED);
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let batch = Batch { committed: true, results: vec![Ok("flag_1".to_string())] };
+        let resp = batch_response(&batch, 1, None, |id| Some(id.clone()), |_| StatusCode::CREATED);
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(check_batch_size(0).is_err());
+        assert!(check_batch_size(MAX_BATCH_SIZE + 1).is_err());
+    }
+}
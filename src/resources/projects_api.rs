@@ -0,0 +1,172 @@
+use actix_web::web::Json;
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use serde::{Deserialize, Serialize};
+use feature_flags_storage::repositories::environment_repository::environment_repository_factory;
+use feature_flags_storage::repositories::feature_flags_repository::feature_flags_repository_factory;
+use feature_flags_storage::repositories::project_repository::project_repository_factory;
+use feature_flags_core::models::Project;
+use feature_flags_storage::domain::sync::ChangedEntity;
+use crate::resources::{audit, publish_change, CustomError};
+use crate::services::project_handlers;
+use crate::AppState;
+
+async fn find(data: web::Data<AppState>) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
+    let repo = project_repository_factory(db).await;
+    let projects = project_handlers::find(&repo).await?;
+    Ok(HttpResponse::Ok().json(ProjectList { items: projects }))
+}
+
+async fn create(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: Json<ProjectCreateSchema>,
+) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
+    let repo = project_repository_factory(db).await;
+    let id = project_handlers::create(&repo, &body.name, &body.label).await?;
+    let project = project_handlers::get(&repo, &id).await?;
+    audit(&req, db, "project.create", None, Some(&project)).await;
+    Ok(HttpResponse::Created().json(Json(project)))
+}
+
+async fn get(
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
+    let repo = project_repository_factory(db).await;
+    let project = project_handlers::get(&repo, &id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(Json(project)))
+}
+
+/// Deletes an empty project, its flags and environments must be purged first
+async fn delete(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
+    let repo = project_repository_factory(db).await;
+    let flag_repo = feature_flags_repository_factory(db).await;
+    let env_repo = environment_repository_factory(db).await;
+    let project = project_handlers::delete(&repo, &flag_repo, &env_repo, &id.into_inner()).await?;
+    data.snapshots.invalidate_project(&project.name);
+    publish_change(&data, &project.name, &[ChangedEntity::Project]).await;
+    audit(&req, db, "project.delete", Some(&project), None).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn create_scope() -> Scope {
+    web::scope("/admin/projects")
+        .route("", web::get().to(find))
+        .route("", web::post().to(create))
+        .route("/{id}", web::get().to(get))
+        .route("/{id}", web::delete().to(delete))
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProjectList {
+    items: Vec<Project>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProjectCreateSchema {
+    name: String,
+    #[serde(default)]
+    label: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::dev::Service;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use feature_flags_core::models::{FeatureFlag, FlagMetadata};
+    use crate::get_state;
+    use crate::resources::{feature_flags_api, scope_project};
+    use crate::resources::feature_flags_api::FeatureFlagCreateSchema;
+
+    #[actix_web::test]
+    async fn test_project_resource() {
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(|mut req, srv| {
+                    scope_project(&mut req);
+                    srv.call(req)
+                })
+                .app_data(web::Data::clone(&get_state().await))
+                .service(create_scope())
+                .service(feature_flags_api::create_scope()),
+        )
+        .await;
+
+        // Writes to unknown projects are rejected
+        let flag = FeatureFlagCreateSchema {
+            name: "project_flag".to_string(),
+            label: "Project Flag".to_string(),
+            enabled: true,
+            rules: vec![],
+            metadata: FlagMetadata::default(),
+        };
+        let req = test::TestRequest::post()
+            .uri("/projects/test_project/admin/feature_flags")
+            .set_json(&flag)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/projects")
+            .set_json(ProjectCreateSchema { name: "test_project".to_string(), label: "Test".to_string() })
+            .to_request();
+        let project: Project = test::call_and_read_body_json(&app, req).await;
+        let project_id = project.id.unwrap().to_hex();
+
+        // The same flag name can be used in another project
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags")
+            .set_json(&flag)
+            .to_request();
+        let default_flag: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri("/projects/test_project/admin/feature_flags")
+            .set_json(&flag)
+            .to_request();
+        let project_flag: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(project_flag.project, "test_project");
+
+        // Flags of other projects are not found
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/feature_flags/{}", project_flag.id.unwrap().to_hex()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // Projects having flags can't be deleted
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/projects/{}", project_id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        for (prefix, flag) in [("", default_flag), ("/projects/test_project", project_flag)] {
+            let id = flag.id.unwrap().to_hex();
+            let req = test::TestRequest::delete()
+                .uri(&format!("{}/admin/feature_flags/{}", prefix, id))
+                .to_request();
+            test::call_service(&app, req).await;
+            let req = test::TestRequest::delete()
+                .uri(&format!("{}/admin/feature_flags/{}/purge", prefix, id))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        }
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/projects/{}", project_id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+}
@@ -0,0 +1,37 @@
+use actix_web::{web, HttpResponse, Scope};
+use serde::{Deserialize, Serialize};
+use feature_flags_storage::repositories::environment_repository::environment_repository_factory;
+use feature_flags_storage::repositories::feature_flags_repository::feature_flags_repository_factory;
+use feature_flags_storage::repositories::usage_repository::usage_repository_factory;
+use crate::resources::{client_api, CustomError, ProjectScope};
+use crate::services::report_handlers;
+use crate::AppState;
+
+/// Days without changes or evaluations after which a flag is stale, by default
+const DEFAULT_STALE_DAYS: i64 = 30;
+
+async fn stale(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    query: web::Query<StaleQuery>,
+) -> Result<HttpResponse, CustomError> {
+    // Include the evaluations still counted in memory
+    client_api::flush_evaluations(&data).await?;
+    let db = &data.db;
+    let repo = feature_flags_repository_factory(db).await;
+    let env_repo = environment_repository_factory(db).await;
+    let usage_repo = usage_repository_factory(db).await;
+    let days = query.days.unwrap_or(DEFAULT_STALE_DAYS);
+    let report = report_handlers::stale(&repo, &env_repo, &usage_repo, &project.0, days).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+pub fn create_scope() -> Scope {
+    web::scope("/admin/reports")
+        .route("/stale", web::get().to(stale))
+}
+
+#[derive(Serialize, Deserialize)]
+struct StaleQuery {
+    days: Option<i64>,
+}
@@ -0,0 +1,138 @@
+use actix_web::{web, HttpResponse, Scope};
+use serde::Deserialize;
+use feature_flags_storage::repositories::environment_repository::environment_repository_factory;
+use feature_flags_storage::repositories::feature_flags_repository::feature_flags_repository_factory;
+use feature_flags_storage::repositories::revision_counter_repository::revision_counter_repository_factory;
+use crate::resources::{CustomError, ProjectScope};
+use crate::services::ruleset_handlers;
+use crate::AppState;
+
+#[derive(Deserialize)]
+struct RulesetQuery {
+    /// Revision of the ruleset the SDK already has
+    since: Option<i64>,
+}
+
+/// Flag definitions for evaluating the environment locally, a delta when `since` is
+/// recent enough
+async fn get_ruleset(
+    data: web::Data<AppState>,
+    project: ProjectScope,
+    environment_name: web::Path<String>,
+    query: web::Query<RulesetQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let db = &data.db;
+    let repo = feature_flags_repository_factory(db).await;
+    let env_repo = environment_repository_factory(db).await;
+    let counter_repo = revision_counter_repository_factory(db).await;
+    let ruleset = ruleset_handlers::get(
+        &repo, &env_repo, &counter_repo, &project.0, &environment_name.into_inner(), query.since,
+    ).await?;
+    Ok(HttpResponse::Ok().json(ruleset))
+}
+
+pub fn create_scope() -> Scope {
+    web::scope("/sdk")
+        .route("/{environment_name}/ruleset", web::get().to(get_ruleset))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http, test, App};
+    use actix_web::web::Json;
+    use serde_json::{Map, Value};
+    use feature_flags_core::evaluation::{Operator, Rule};
+    use feature_flags_core::models::{Environment, FeatureFlag, FlagMetadata, FlagMetadataUpdate, FlagOverride};
+    use feature_flags_core::ruleset::Ruleset;
+    use crate::get_state;
+    use crate::resources::{environments_api, feature_flags_api};
+    use crate::resources::feature_flags_api::{FeatureFlagCreateSchema, FeatureFlagUpdateSchema};
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_ruleset() {
+        let state = get_state().await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::clone(&state))
+                .service(feature_flags_api::create_scope())
+                .service(environments_api::create_scope())
+                .service(create_scope()),
+        ).await;
+        let req = test::TestRequest::post()
+            .uri("/admin/feature_flags")
+            .set_json(Json(FeatureFlagCreateSchema {
+                name: "ruleset_flag".to_string(),
+                label: "Ruleset Flag".to_string(),
+                enabled: true,
+                rules: vec![Rule { parameter: "tenant".to_string(), operator: Operator::Is("tenant1".to_string()) }],
+                metadata: FlagMetadata::default(),
+            }))
+            .to_request();
+        let flag: FeatureFlag = test::call_and_read_body_json(&app, req).await;
+        let flag_id = flag.id.unwrap().to_string();
+        let version = flag.version;
+        let mut env = Environment::new("test_env_ruleset");
+        env.set_override(FlagOverride { variant: Some("treatment".to_string()), ..FlagOverride::new("ruleset_flag") });
+        let req = test::TestRequest::post()
+            .uri("/admin/environments")
+            .set_json(Json(env))
+            .to_request();
+        let env: Environment = test::call_and_read_body_json(&app, req).await;
+        let env_id = env.id.unwrap().to_string();
+
+        // Full ruleset, evaluated locally like `/flags` does
+        let req = test::TestRequest::get().uri("/sdk/test_env_ruleset/ruleset").to_request();
+        let ruleset: Ruleset = test::call_and_read_body_json(&app, req).await;
+        assert!(ruleset.full);
+        let flag = ruleset.flags.iter().find(|flag| flag.name == "ruleset_flag").unwrap();
+        let context = Map::from_iter([("tenant".to_string(), Value::from("tenant1"))]);
+        let evaluation = ruleset.environment.as_ref().unwrap().evaluate(flag, &context);
+        assert_eq!((evaluation.value, evaluation.variant), (true, Some("treatment".to_string())));
+
+        // Only the updated flag is sent since the revision of the full ruleset
+        let req = test::TestRequest::put()
+            .uri(&format!("/admin/feature_flags/{}", &flag_id))
+            .insert_header((http::header::IF_MATCH, version.to_string()))
+            .set_json(Json(FeatureFlagUpdateSchema {
+                label: "Ruleset Flag".to_string(),
+                enabled: false,
+                rules: vec![],
+                metadata: FlagMetadataUpdate::default(),
+            }))
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get()
+            .uri(&format!("/sdk/test_env_ruleset/ruleset?since={}", ruleset.revision))
+            .to_request();
+        let delta: Ruleset = test::call_and_read_body_json(&app, req).await;
+        assert!(!delta.full);
+        assert!(delta.revision > ruleset.revision);
+        assert!(delta.environment.is_none());
+        let flag = delta.flags.iter().find(|flag| flag.name == "ruleset_flag").unwrap();
+        assert!(!flag.enabled);
+
+        // Archived flags are reported as removed
+        let req = test::TestRequest::delete()
+            .uri(&format!("/admin/feature_flags/{}", &flag_id))
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get()
+            .uri(&format!("/sdk/test_env_ruleset/ruleset?since={}", delta.revision))
+            .to_request();
+        let delta: Ruleset = test::call_and_read_body_json(&app, req).await;
+        assert!(delta.removed.contains(&"ruleset_flag".to_string()));
+
+        let req = test::TestRequest::get().uri("/sdk/unknown_env/ruleset").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), http::StatusCode::NOT_FOUND);
+
+        for uri in [
+            format!("/admin/environments/{}", &env_id),
+            format!("/admin/environments/{}/purge", &env_id),
+            format!("/admin/feature_flags/{}/purge", &flag_id),
+        ] {
+            let req = test::TestRequest::delete().uri(&uri).to_request();
+            test::call_service(&app, req).await;
+        }
+    }
+}
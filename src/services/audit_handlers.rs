@@ -0,0 +1,185 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Document};
+use mongodb::{ClientSession, Cursor};
+use feature_flags_storage::repositories::audit_repository::AuditRepository;
+use feature_flags_storage::repositories::pagination::{Page, PageRequest};
+use feature_flags_storage::repositories::{BaseRepository, ErrorKind};
+use feature_flags_storage::domain::audit::{AuditEntry, Auditable};
+use crate::services::ServiceError;
+use feature_flags_core::utils::date_format;
+
+pub async fn record<T: Auditable>(
+    repo: &AuditRepository<AuditEntry>,
+    actor: &str,
+    action: &str,
+    request_id: &str,
+    before: Option<&T>,
+    after: Option<&T>,
+    session: Option<&mut ClientSession>,
+) -> Result<(), ServiceError> {
+    let entry = match AuditEntry::new(actor, action, request_id, before, after) {
+        Some(entry) => entry,
+        None => return Err(ServiceError::new(
+            ErrorKind::InvalidArgument,
+            "Audit entries require a target".to_string(),
+        )),
+    };
+    let res = match session {
+        Some(session) => repo.create_with_session(&entry, session).await,
+        None => repo.create(&entry).await,
+    };
+    match res {
+        Ok(_) => Ok(()),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+/// Records the writes of one request in the audit log
+pub struct Auditor {
+    repo: AuditRepository<AuditEntry>,
+    actor: String,
+    request_id: String,
+}
+
+impl Auditor {
+    pub fn new(repo: AuditRepository<AuditEntry>, actor: &str, request_id: &str) -> Self {
+        Auditor { repo, actor: actor.to_string(), request_id: request_id.to_string() }
+    }
+
+    /// Records a write made in the session transaction, failures are returned so the
+    /// write is aborted with its entry. Without a session the write is already stored,
+    /// failures are logged instead.
+    pub async fn record<T: Auditable>(
+        &self,
+        action: &str,
+        before: Option<&T>,
+        after: Option<&T>,
+        session: Option<&mut ClientSession>,
+    ) -> Result<(), ServiceError> {
+        let in_transaction = session.is_some();
+        match record(&self.repo, &self.actor, action, &self.request_id, before, after, session).await {
+            Err(err) if !in_transaction => {
+                eprintln!("Failed to audit {} of request {}: {}", action, self.request_id, err);
+                Ok(())
+            }
+            res => res,
+        }
+    }
+}
+
+pub async fn find_page(
+    repo: &AuditRepository<AuditEntry>,
+    filters: &Filters,
+    page: &PageRequest,
+) -> Result<Page<AuditEntry>, ServiceError> {
+    match repo.find_page(filters.to_document(), page).await {
+        Ok(res) => Ok(res),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+pub async fn export(
+    repo: &AuditRepository<AuditEntry>,
+    filters: &Filters,
+) -> Result<Cursor<AuditEntry>, ServiceError> {
+    match repo.stream(filters.to_document()).await {
+        Ok(cursor) => Ok(cursor),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Filters {
+    pub project: Option<String>,
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target_kind: Option<String>,
+    pub target_id: Option<ObjectId>,
+    pub target_name: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl Filters {
+    pub fn to_document(&self) -> Document {
+        let mut filter = doc! {};
+        if let Some(project) = &self.project {
+            filter.insert("project", project);
+        }
+        if let Some(actor) = &self.actor {
+            filter.insert("actor", actor);
+        }
+        if let Some(action) = &self.action {
+            filter.insert("action", action);
+        }
+        if let Some(kind) = &self.target_kind {
+            filter.insert("target.kind", kind);
+        }
+        if let Some(id) = &self.target_id {
+            filter.insert("target.id", id);
+        }
+        if let Some(name) = &self.target_name {
+            filter.insert("target.name", name);
+        }
+        // Dates are stored formatted, the format sorts chronologically
+        let mut created_at = doc! {};
+        if let Some(from) = &self.from {
+            created_at.insert("$gte", date_format::format(from));
+        }
+        if let Some(to) = &self.to {
+            created_at.insert("$lte", date_format::format(to));
+        }
+        if !created_at.is_empty() {
+            filter.insert("created_at", created_at);
+        }
+        filter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feature_flags_storage::repositories::audit_repository::audit_repository_factory;
+    use feature_flags_storage::repositories::pagination::Sort;
+    use feature_flags_storage::database::init_db;
+    use feature_flags_core::models::FeatureFlag;
+
+    #[test]
+    fn test_filters_to_document() {
+        let from = Utc::now();
+        let filters = Filters {
+            project: Some("checkout".to_string()),
+            actor: Some("john".to_string()),
+            target_kind: Some("feature_flag".to_string()),
+            from: Some(from),
+            ..Default::default()
+        };
+        assert_eq!(filters.to_document(), doc! {
+            "project": "checkout",
+            "actor": "john",
+            "target.kind": "feature_flag",
+            "created_at": {"$gte": date_format::format(&from)},
+        });
+    }
+
+    #[actix_web::test]
+    async fn test_record() {
+        let db = init_db().await.unwrap();
+        let repo = audit_repository_factory(&db).await;
+        let mut flag = FeatureFlag::new("audited_flag", "Audited flag", true, vec![]);
+        flag.id = Some(ObjectId::new());
+        record(&repo, "audit_test_actor", "feature_flag.create", "req-1", None, Some(&flag), None).await.unwrap();
+
+        let filters = Filters {
+            actor: Some("audit_test_actor".to_string()),
+            target_id: flag.id,
+            ..Default::default()
+        };
+        let page = PageRequest::new(None, None, Sort::new("created_at", true)).unwrap();
+        let res = find_page(&repo, &filters, &page).await.unwrap();
+        assert_eq!(res.total, 1);
+        assert_eq!(res.items[0].action, "feature_flag.create");
+        repo.collection.delete_many(doc! {"actor": "audit_test_actor"}, None).await.unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use mongodb::bson::doc;
+use feature_flags_storage::repositories::environment_repository::EnvironmentRepository;
+use feature_flags_storage::repositories::feature_flags_repository::FeatureFlagRepository;
+use feature_flags_storage::repositories::{BaseRepository, ErrorKind};
+use feature_flags_core::models::{Environment, FeatureFlag};
+use crate::services::{environment_handlers, ServiceError};
+use crate::snapshot::Snapshots;
+
+/// All flags of the project that are not archived, disabled ones included, loaded on misses
+pub async fn flags(
+    snapshots: &Snapshots,
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+) -> Result<Arc<Vec<FeatureFlag>>, ServiceError> {
+    match snapshots.flags(project) {
+        Some(flags) => Ok(flags),
+        None => load_flags(snapshots, repo, project).await,
+    }
+}
+
+/// Active environment with the given name, loaded on misses
+pub async fn environment(
+    snapshots: &Snapshots,
+    repo: &EnvironmentRepository<Environment>,
+    project: &str,
+    name: &str,
+) -> Result<Arc<Environment>, ServiceError> {
+    match snapshots.env(project, name) {
+        Some(env) => Ok(env),
+        None => load_environment(snapshots, repo, project, name).await,
+    }
+}
+
+/// Reloads the entries older than the TTL, environments archived meanwhile are dropped.
+/// Entries failing to load are kept and retried on the next refresh.
+pub async fn refresh(
+    snapshots: &Snapshots,
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    env_repo: &EnvironmentRepository<Environment>,
+) -> Result<(), ServiceError> {
+    let (projects, envs) = snapshots.expired();
+    let mut res = Ok(());
+    for project in projects {
+        match load_flags(snapshots, repo, &project).await {
+            Ok(_) => snapshots.record_refresh(),
+            Err(err) => res = Err(err),
+        }
+    }
+    for (project, name) in envs {
+        match load_environment(snapshots, env_repo, &project, &name).await {
+            Ok(_) => snapshots.record_refresh(),
+            Err(err) if err.kind == ErrorKind::NotFound => snapshots.invalidate_env(&project, &name),
+            Err(err) => res = Err(err),
+        }
+    }
+    res
+}
+
+async fn load_flags(
+    snapshots: &Snapshots,
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+) -> Result<Arc<Vec<FeatureFlag>>, ServiceError> {
+    let generation = snapshots.generation();
+    let flags = Arc::new(repo.find(doc! {"project": project, "archived": {"$ne": true}}).await?);
+    snapshots.publish_flags(generation, project, Arc::clone(&flags));
+    Ok(flags)
+}
+
+async fn load_environment(
+    snapshots: &Snapshots,
+    repo: &EnvironmentRepository<Environment>,
+    project: &str,
+    name: &str,
+) -> Result<Arc<Environment>, ServiceError> {
+    let generation = snapshots.generation();
+    let env = Arc::new(environment_handlers::get_by_name(repo, project, name).await?);
+    snapshots.publish_env(generation, Arc::clone(&env));
+    Ok(env)
+}
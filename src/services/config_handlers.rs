@@ -0,0 +1,364 @@
+use std::collections::HashSet;
+use chrono::Utc;
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::{Client, ClientSession};
+use serde::{Deserialize, Serialize};
+use feature_flags_storage::database::supports_transactions;
+use feature_flags_storage::repositories::environment_repository::EnvironmentRepository;
+use feature_flags_storage::repositories::feature_flags_repository::FeatureFlagRepository;
+use feature_flags_storage::repositories::{BaseRepository, ErrorKind};
+use crate::domain::config::{Configuration, CONFIG_VERSION};
+use feature_flags_storage::domain::audit::Auditable;
+use feature_flags_storage::domain::history::Revisioned;
+use feature_flags_core::models::{Environment, FeatureFlag};
+use crate::services::audit_handlers::Auditor;
+use crate::services::{environment_handlers, feature_flag_handlers, Change, ServiceError};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStrategy {
+    /// Creates and updates the entities of the document, the others are kept
+    #[default]
+    Merge,
+    /// Also archives the entities missing from the document
+    Replace,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct ImportOptions {
+    #[serde(default)]
+    pub strategy: ImportStrategy,
+    /// Reports the writes without making them
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Write made by an import
+#[derive(Clone, Debug)]
+pub enum Planned<T> {
+    Create(T),
+    Update(Change<T>),
+    Archive(Change<T>),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImportPlan {
+    pub feature_flags: Vec<Planned<FeatureFlag>>,
+    pub environments: Vec<Planned<Environment>>,
+}
+
+impl ImportPlan {
+    pub fn is_empty(&self) -> bool {
+        self.feature_flags.is_empty() && self.environments.is_empty()
+    }
+}
+
+/// Every flag and environment of the project, archived ones included
+pub async fn export(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    env_repo: &EnvironmentRepository<Environment>,
+    project: &str,
+) -> Result<Configuration, ServiceError> {
+    let (flags, envs) = find(repo, env_repo, project).await?;
+    Ok(Configuration::new(project, &flags, &envs))
+}
+
+/// Loads a configuration document and returns the writes it needs, nothing is written on dry runs.
+/// Writes and their audit entries run in a transaction when the deployment supports them, so a
+/// failed import leaves the configuration unchanged. Otherwise the writes made before the failure
+/// are kept.
+pub async fn import(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    env_repo: &EnvironmentRepository<Environment>,
+    client: &Client,
+    project: &str,
+    config: &Configuration,
+    options: ImportOptions,
+    auditor: &Auditor,
+) -> Result<ImportPlan, ServiceError> {
+    let (flags, envs) = find(repo, env_repo, project).await?;
+    let mut plan = plan(project, &flags, &envs, config, options.strategy)?;
+    if options.dry_run || plan.is_empty() {
+        return Ok(plan);
+    }
+    if !supports_transactions(client).await? {
+        write(repo, env_repo, &mut plan, auditor, None).await?;
+        return Ok(plan);
+    }
+    let mut session = client.start_session(None).await?;
+    session.start_transaction(None).await?;
+    if let Err(err) = write(repo, env_repo, &mut plan, auditor, Some(&mut session)).await {
+        session.abort_transaction().await?;
+        return Err(err);
+    }
+    session.commit_transaction().await?;
+    Ok(plan)
+}
+
+/// Flags and environments of the project, archived ones included
+async fn find(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    env_repo: &EnvironmentRepository<Environment>,
+    project: &str,
+) -> Result<(Vec<FeatureFlag>, Vec<Environment>), ServiceError> {
+    let flags = repo.find(doc! {"project": project}).await?;
+    let envs = env_repo.find(doc! {"project": project}).await?;
+    Ok((flags, envs))
+}
+
+/// Writes needed for the stored entities of the project to match the configuration document
+pub fn plan(
+    project: &str,
+    flags: &[FeatureFlag],
+    envs: &[Environment],
+    config: &Configuration,
+    strategy: ImportStrategy,
+) -> Result<ImportPlan, ServiceError> {
+    validate(project, flags, envs, config, strategy)?;
+    let mut plan = ImportPlan::default();
+    for flag_config in config.feature_flags.iter() {
+        match flags.iter().find(|flag| flag.name == flag_config.name) {
+            None => {
+                let mut flag = flag_config.to_flag();
+                flag.project = project.to_string();
+                plan.feature_flags.push(Planned::Create(flag));
+            }
+            Some(flag) => {
+                let mut after = flag.clone();
+                flag_config.apply(&mut after);
+                if after.tracked_fields() != flag.tracked_fields() {
+                    plan.feature_flags.push(Planned::Update(Change { before: flag.clone(), after }));
+                }
+            }
+        }
+    }
+    for env_config in config.environments.iter() {
+        match envs.iter().find(|env| env.name == env_config.name) {
+            None => {
+                let mut env = env_config.to_environment();
+                env.project = project.to_string();
+                plan.environments.push(Planned::Create(env));
+            }
+            Some(env) => {
+                let mut after = env.clone();
+                env_config.apply(&mut after);
+                if after.tracked_fields() != env.tracked_fields() {
+                    plan.environments.push(Planned::Update(Change { before: env.clone(), after }));
+                }
+            }
+        }
+    }
+    if strategy == ImportStrategy::Replace {
+        let missing_flags = flags.iter().filter(|flag| !config.feature_flags.iter().any(|f| f.name == flag.name));
+        for flag in missing_flags.filter(|flag| !flag.archived) {
+            let mut after = flag.clone();
+            after.set_archived(true);
+            plan.feature_flags.push(Planned::Archive(Change { before: flag.clone(), after }));
+        }
+        let missing_envs = envs.iter().filter(|env| !config.environments.iter().any(|e| e.name == env.name));
+        for env in missing_envs.filter(|env| !env.archived) {
+            let mut after = env.clone();
+            after.archived = true;
+            plan.environments.push(Planned::Archive(Change { before: env.clone(), after }));
+        }
+    }
+    Ok(plan)
+}
+
+fn validate(
+    project: &str,
+    flags: &[FeatureFlag],
+    envs: &[Environment],
+    config: &Configuration,
+    strategy: ImportStrategy,
+) -> Result<(), ServiceError> {
+    let invalid = |message: String| Err(ServiceError::new(ErrorKind::InvalidArgument, message));
+    if config.version != CONFIG_VERSION {
+        return invalid(format!(
+            "Unsupported configuration version {}, expected {}", config.version, CONFIG_VERSION
+        ));
+    }
+    // Documents only hold one project, they are not loaded in another one by mistake
+    if let Some(exported) = config.project.as_deref().filter(|exported| *exported != project) {
+        return invalid(format!(
+            "Configuration of project {} can not be imported in project {}", exported, project
+        ));
+    }
+    let mut flag_names = HashSet::new();
+    for flag in config.feature_flags.iter() {
+        if !flag_names.insert(flag.name.as_str()) {
+            return invalid(format!("Feature flag {} is defined more than once", flag.name));
+        }
+        // Stored flags keep their names, only the created ones are checked
+        if !flags.iter().any(|stored| stored.name == flag.name) {
+            feature_flag_handlers::validate_name(&flag.name)?;
+        }
+    }
+    let mut env_names = HashSet::new();
+    for env in config.environments.iter() {
+        if !env_names.insert(env.name.as_str()) {
+            return invalid(format!("Environment {} is defined more than once", env.name));
+        }
+        if !envs.iter().any(|stored| stored.name == env.name) {
+            environment_handlers::validate_name(&env.name)?;
+        }
+    }
+    // Flags missing from the document are kept when merging
+    if strategy == ImportStrategy::Merge {
+        flag_names.extend(flags.iter().map(|flag| flag.name.as_str()));
+    }
+    for env in config.environments.iter() {
+        if let Some(flag) = env.flags.iter().find(|flag| !flag_names.contains(flag.name.as_str())) {
+            return invalid(format!("Environment {} overrides unknown flag {}", env.name, flag.name));
+        }
+    }
+    Ok(())
+}
+
+async fn write(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    env_repo: &EnvironmentRepository<Environment>,
+    plan: &mut ImportPlan,
+    auditor: &Auditor,
+    mut session: Option<&mut ClientSession>,
+) -> Result<(), ServiceError> {
+    for planned in plan.feature_flags.iter_mut() {
+        match planned {
+            Planned::Create(flag) => {
+                let id = match session.as_deref_mut() {
+                    Some(session) => repo.create_with_session(flag, session).await?,
+                    None => repo.create(flag).await?,
+                };
+                flag.id = ObjectId::parse_str(id).ok();
+            }
+            Planned::Update(change) | Planned::Archive(change) => {
+                change.after.updated_at = Utc::now();
+                let id = hex_id(change.before.id);
+                match session.as_deref_mut() {
+                    Some(session) => repo.update_with_session(&id, &change.after, session).await?,
+                    None => repo.update(&id, &change.after).await?,
+                }
+                change.after.version += 1;
+            }
+        }
+        audit(auditor, planned, session.as_deref_mut()).await?;
+    }
+    for planned in plan.environments.iter_mut() {
+        match planned {
+            Planned::Create(env) => {
+                let id = match session.as_deref_mut() {
+                    Some(session) => env_repo.create_with_session(env, session).await?,
+                    None => env_repo.create(env).await?,
+                };
+                env.id = ObjectId::parse_str(id).ok();
+            }
+            Planned::Update(change) | Planned::Archive(change) => {
+                change.after.updated_at = Utc::now();
+                let id = hex_id(change.before.id);
+                match session.as_deref_mut() {
+                    Some(session) => env_repo.update_with_session(&id, &change.after, session).await?,
+                    None => env_repo.update(&id, &change.after).await?,
+                }
+                change.after.version += 1;
+            }
+        }
+        audit(auditor, planned, session.as_deref_mut()).await?;
+    }
+    Ok(())
+}
+
+/// Records an imported write, in the import transaction when there is one
+async fn audit<T: Auditable>(
+    auditor: &Auditor,
+    planned: &Planned<T>,
+    session: Option<&mut ClientSession>,
+) -> Result<(), ServiceError> {
+    let (before, after) = match planned {
+        Planned::Create(entity) => (None, entity),
+        Planned::Update(change) | Planned::Archive(change) => (Some(&change.before), &change.after),
+    };
+    let action = format!("{}.import", after.audit_target().kind);
+    auditor.record(&action, before, Some(after), session).await
+}
+
+fn hex_id(id: Option<ObjectId>) -> String {
+    id.map(|id| id.to_hex()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::config::{EnvironmentConfiguration, FlagConfiguration};
+    use feature_flags_core::models::{FlagOverride, DEFAULT_PROJECT};
+
+    fn configuration(flags: &[&FeatureFlag], envs: &[EnvironmentConfiguration]) -> Configuration {
+        Configuration {
+            version: CONFIG_VERSION,
+            project: None,
+            exported_at: Utc::now(),
+            feature_flags: flags.iter().map(|flag| FlagConfiguration::from(*flag)).collect(),
+            environments: envs.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let unchanged = FeatureFlag::new("unchanged_flag", "Unchanged", true, vec![]);
+        let mut updated = FeatureFlag::new("updated_flag", "Updated", false, vec![]);
+        let missing = FeatureFlag::new("missing_flag", "Missing", true, vec![]);
+        let created = FeatureFlag::new("created_flag", "Created", true, vec![]);
+        let flags = vec![unchanged.clone(), updated.clone(), missing.clone()];
+        let envs = vec![Environment::new("production")];
+
+        updated.enabled = true;
+        let mut staging = EnvironmentConfiguration::from(&Environment::new("staging"));
+        staging.flags = vec![FlagOverride::new("missing_flag")];
+        let config = configuration(&[&unchanged, &updated, &created], &[staging.clone()]);
+
+        // Flags and environments missing from the document are kept when merging
+        let plan = plan(DEFAULT_PROJECT, &flags, &envs, &config, ImportStrategy::Merge).unwrap();
+        assert_eq!(plan.feature_flags.len(), 2);
+        assert!(matches!(&plan.feature_flags[0], Planned::Update(change) if change.after.enabled));
+        assert!(matches!(&plan.feature_flags[1], Planned::Create(flag) if flag.name == "created_flag"));
+        assert!(matches!(&plan.environments[..], [Planned::Create(env)] if env.flags.len() == 1));
+
+        // Replacing archives them, so they can't be overridden
+        let err = super::plan(DEFAULT_PROJECT, &flags, &envs, &config, ImportStrategy::Replace).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidArgument);
+        staging.flags = vec![];
+        let config = configuration(&[&unchanged, &updated, &created], &[staging]);
+        let plan = super::plan(DEFAULT_PROJECT, &flags, &envs, &config, ImportStrategy::Replace).unwrap();
+        assert!(matches!(&plan.feature_flags[2], Planned::Archive(change) if change.after.name == "missing_flag" && change.after.archived));
+        assert!(matches!(&plan.environments[1], Planned::Archive(change) if change.after.name == "production" && change.after.archived));
+
+        // Entities already archived are left as they are
+        let mut archived = missing.clone();
+        archived.set_archived(true);
+        let flags = vec![unchanged.clone(), flags[1].clone(), archived];
+        let plan = super::plan(DEFAULT_PROJECT, &flags, &[], &config, ImportStrategy::Replace).unwrap();
+        assert!(!plan.feature_flags.iter().any(|planned| matches!(planned, Planned::Archive(_))));
+    }
+
+    #[test]
+    fn test_plan_rejects_invalid_documents() {
+        let flag = FeatureFlag::new("sample_flag", "Sample Flag", true, vec![]);
+        let mut config = configuration(&[&flag, &flag], &[]);
+        assert!(plan(DEFAULT_PROJECT, &[], &[], &config, ImportStrategy::Merge).is_err());
+        config.feature_flags.pop();
+        config.version = CONFIG_VERSION + 1;
+        assert!(plan(DEFAULT_PROJECT, &[], &[], &config, ImportStrategy::Merge).is_err());
+        config.version = CONFIG_VERSION;
+        assert!(plan(DEFAULT_PROJECT, &[], &[], &config, ImportStrategy::Merge).is_ok());
+        config.project = Some("other".to_string());
+        assert!(plan(DEFAULT_PROJECT, &[], &[], &config, ImportStrategy::Merge).is_err());
+        config.project = Some(DEFAULT_PROJECT.to_string());
+        assert!(plan(DEFAULT_PROJECT, &[], &[], &config, ImportStrategy::Merge).is_ok());
+        // Reserved names can't be created
+        let reserved = FeatureFlag::new("batch", "Batch", true, vec![]);
+        let config = configuration(&[&reserved], &[]);
+        assert!(plan(DEFAULT_PROJECT, &[], &[], &config, ImportStrategy::Merge).is_err());
+        let config = configuration(&[&flag], &[EnvironmentConfiguration::from(&Environment::new("diff"))]);
+        assert!(plan(DEFAULT_PROJECT, &[], &[], &config, ImportStrategy::Merge).is_err());
+    }
+}
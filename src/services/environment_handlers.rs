@@ -1,11 +1,16 @@
-use futures::StreamExt;
-use crate::adapters::repositories::environment_repository::{EnvironmentRepository};
-use crate::adapters::repositories::BaseRepository;
-use crate::domain::models::{Environment, FeatureFlag};
-use crate::services::ServiceError;
-use mongodb::bson::{doc, to_document};
-use mongodb::bson::oid::ObjectId;
-use serde::{Serialize};
+use chrono::Utc;
+use feature_flags_storage::repositories::environment_repository::{EnvironmentRepository};
+use feature_flags_storage::repositories::feature_flags_repository::FeatureFlagRepository;
+use feature_flags_storage::repositories::pagination::{Page, PageRequest};
+use feature_flags_storage::repositories::revision_repository::RevisionRepository;
+use feature_flags_storage::repositories::{BaseRepository, ErrorKind};
+use feature_flags_storage::domain::history::{diff, FieldChange, Revision};
+use feature_flags_core::models::{Environment, FeatureFlag, FlagOverride};
+use crate::services::{self, feature_flag_handlers, history_handlers, Batch, Change, ServiceError};
+use crate::utils::escape_regex;
+use mongodb::bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 pub async fn find(
     repo: &EnvironmentRepository<Environment>,
@@ -13,51 +18,83 @@ pub async fn find(
 ) -> Result<Vec<Environment>, ServiceError> {
     let _filters = match filters.into() {
         None => doc! {},
-        Some(f) => to_document(&f).unwrap(),
+        Some(f) => f.to_document(),
     };
     let res = repo.find(_filters).await;
     match res {
         Ok(res) => Ok(res),
-        Err(e) => Err(ServiceError {
-            message: e.to_string(),
-        }),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+pub async fn find_page(
+    repo: &EnvironmentRepository<Environment>,
+    filters: &Filters,
+    page: &PageRequest,
+) -> Result<Page<Environment>, ServiceError> {
+    match repo.find_page(filters.to_document(), page).await {
+        Ok(res) => Ok(res),
+        Err(e) => Err(ServiceError::from(e)),
     }
 }
 
 pub async fn create(
     repo: &EnvironmentRepository<Environment>,
+    project: &str,
     name: &str,
 ) -> Result<String, ServiceError> {
-    let env = Environment::new(name);
+    validate_name(name)?;
+    let mut env = Environment::new(name);
+    env.project = project.to_string();
     match repo.create(&env).await {
         Ok(id) => Ok(id),
-        Err(err) => Err(ServiceError {
-            message: err.to_string(),
-        }),
+        Err(err) => Err(ServiceError::from(err)),
     }
 }
 
+/// Names of the routes under `/admin/environments` that take the place of an environment
+const RESERVED_NAMES: [&str; 1] = ["diff"];
+
+/// Environment names can not look like ids, `diff` is reserved for the comparison route
+pub fn validate_name(name: &str) -> Result<(), ServiceError> {
+    services::validate_name("Environment", name, &RESERVED_NAMES)
+}
+
+/// Id of the project environment with the given id or name, archived environments included
+pub async fn resolve_id(
+    repo: &EnvironmentRepository<Environment>,
+    project: &str,
+    key: &str,
+) -> Result<String, ServiceError> {
+    services::resolve_id(&repo.collection, Some(project), key, None).await
+}
+
+/// Environment with the given id or name, environments of other projects are not found
 pub async fn get(
     repo: &EnvironmentRepository<Environment>,
-    id: &str,
+    project: &str,
+    key: &str,
 ) -> Result<Environment, ServiceError> {
-    match repo.get(id).await {
-        Ok(item) => Ok(item),
-        Err(e) => Err(ServiceError {
-            message: e.to_string(),
-        }),
+    let id = resolve_id(repo, project, key).await?;
+    match repo.get(&id).await {
+        Ok(item) if item.project == project => Ok(item),
+        Ok(_) => Err(ServiceError::new(ErrorKind::NotFound, "Entity not found".to_string())),
+        Err(e) => Err(ServiceError::from(e)),
     }
 }
 
 pub async fn get_by_name(
     repo: &EnvironmentRepository<Environment>,
+    project: &str,
     name: &str,
 ) -> Result<Environment, ServiceError> {
     match repo
         .collection
         .find_one(
             doc! {
-                "name": name
+                "project": project,
+                "name": name,
+                "archived": {"$ne": true}
             },
             None,
         )
@@ -65,13 +102,24 @@ pub async fn get_by_name(
     {
         Ok(res) => match res {
             Some(env) => Ok(env),
-            None => Err(ServiceError {
-                message: format!("Environment not found with name {}", name),
-            }),
+            None => Err(ServiceError::new(
+                ErrorKind::NotFound,
+                format!("Environment not found with name {}", name),
+            )),
         },
-        Err(err) => Err(ServiceError {
-            message: err.to_string(),
-        }),
+        Err(err) => Err(ServiceError::from(err)),
+    }
+}
+
+/// Environments, archived ones included, overriding the given flag
+pub async fn find_referencing(
+    repo: &EnvironmentRepository<Environment>,
+    project: &str,
+    flag_name: &str,
+) -> Result<Vec<Environment>, ServiceError> {
+    match repo.find(doc! {"project": project, "flags.name": flag_name}).await {
+        Ok(res) => Ok(res),
+        Err(e) => Err(ServiceError::from(e)),
     }
 }
 
@@ -82,9 +130,7 @@ pub async fn update(
 ) -> Result<(), ServiceError> {
     match repo.update(id, env).await {
         Ok(_) => Ok(()),
-        Err(e) => Err(ServiceError {
-            message: e.to_string(),
-        }),
+        Err(e) => Err(ServiceError::from(e)),
     }
 }
 
@@ -94,100 +140,473 @@ pub async fn delete(
 ) -> Result<(), ServiceError> {
     match repo.delete(id).await {
         Ok(_) => Ok(()),
-        Err(e) => Err(ServiceError {
-            message: e.to_string(),
-        }),
+        Err(e) => Err(ServiceError::from(e)),
     }
 }
 
-pub async fn set_flag(
+/// Hides the environment from evaluation and default listings, it can be restored later
+pub async fn archive(
+    repo: &EnvironmentRepository<Environment>,
+    project: &str,
+    id: &str,
+    version: Option<i64>,
+) -> Result<Change<Environment>, ServiceError> {
+    modify(repo, project, id, version, |env| env.archived = true).await
+}
+
+pub async fn restore(
+    repo: &EnvironmentRepository<Environment>,
+    project: &str,
+    id: &str,
+    version: Option<i64>,
+) -> Result<Change<Environment>, ServiceError> {
+    modify(repo, project, id, version, |env| env.archived = false).await
+}
+
+/// Permanently removes an archived environment
+pub async fn purge(
     repo: &EnvironmentRepository<Environment>,
+    project: &str,
     id: &str,
-    flag: &FeatureFlag
 ) -> Result<Environment, ServiceError> {
-    match get(&repo, id).await {
-        Ok(mut env) => {
-            env.add_flag(flag);
-            env.id = Option::from(ObjectId::parse_str(&id).unwrap());
-            match repo.update(id, &env).await {
-                Ok(_) => Ok(env),
-                Err(e) => Err(ServiceError {
-                    message: e.to_string(),
-                })
-            }
-        }
-        Err(e) => Err(e)
+    let id = resolve_id(repo, project, id).await?;
+    let env = get(repo, project, &id).await?;
+    if !env.archived {
+        return Err(ServiceError::new(
+            ErrorKind::InvalidState,
+            format!("Environment {} must be archived before being purged", env.name),
+        ));
     }
+    delete(repo, &id).await?;
+    Ok(env)
+}
+
+/// Overrides a global flag in the environment, the flag must exist and not be archived
+pub async fn set_flag(
+    repo: &EnvironmentRepository<Environment>,
+    project: &str,
+    flag_repo: &FeatureFlagRepository<FeatureFlag>,
+    id: &str,
+    flag_override: FlagOverride,
+    version: Option<i64>,
+) -> Result<Change<Environment>, ServiceError> {
+    feature_flag_handlers::get_by_name(flag_repo, project, &flag_override.name).await?;
+    modify(repo, project, id, version, |env| env.set_override(flag_override)).await
 }
 
 pub async fn remove_flag(
     repo: &EnvironmentRepository<Environment>,
+    project: &str,
     id: &str,
-    flag_name: &str
-) -> Result<Environment, ServiceError> {
-    match get(&repo, id).await {
-        Ok(mut env) => {
-            env.remove_flag_by_name(&flag_name);
-            env.id = Option::from(ObjectId::parse_str(&id).unwrap());
-            match repo.update(id, &env).await {
-                Ok(_) => Ok(env),
-                Err(e) => Err(ServiceError {
-                    message: e.to_string(),
-                })
+    flag_name: &str,
+    version: Option<i64>,
+) -> Result<Change<Environment>, ServiceError> {
+    modify(repo, project, id, version, |env| env.remove_override(flag_name)).await
+}
+
+/// Restores the environment flags as they were at the given revision
+pub async fn rollback(
+    repo: &EnvironmentRepository<Environment>,
+    project: &str,
+    history_repo: &RevisionRepository<Revision<Environment>>,
+    id: &str,
+    revision: i64,
+    version: Option<i64>,
+) -> Result<Change<Environment>, ServiceError> {
+    let id = resolve_id(repo, project, id).await?;
+    let revision = history_handlers::get(history_repo, &id, revision).await?;
+    modify(repo, project, &id, version, |env| env.set_overrides(revision.snapshot.flags)).await
+}
+
+pub struct PromoteOptions<'a> {
+    /// Names of the flags to promote, all of them when missing
+    pub flags: Option<&'a [String]>,
+    /// Returns the target as it would be, without writing it
+    pub preview: bool,
+}
+
+/// Makes the flags of the target environment behave as in the source one
+pub async fn promote(
+    repo: &EnvironmentRepository<Environment>,
+    project: &str,
+    flag_repo: &FeatureFlagRepository<FeatureFlag>,
+    source_id: &str,
+    target_id: &str,
+    options: PromoteOptions<'_>,
+    version: Option<i64>,
+) -> Result<Change<Environment>, ServiceError> {
+    let PromoteOptions { flags, preview } = options;
+    let source_id = resolve_id(repo, project, source_id).await?;
+    let target_id = resolve_id(repo, project, target_id).await?;
+    if source_id == target_id {
+        return Err(ServiceError::new(
+            ErrorKind::InvalidArgument,
+            "Source and target environments must be different".to_string(),
+        ));
+    }
+    let source = get(repo, project, &source_id).await?;
+    for name in flags.unwrap_or_default() {
+        feature_flag_handlers::get_by_name(flag_repo, project, name).await?;
+    }
+    if preview {
+        let before = get(repo, project, &target_id).await?;
+        let mut after = before.clone();
+        promote_overrides(&source, &mut after, flags);
+        return Ok(Change { before, after });
+    }
+    modify(repo, project, &target_id, version, |env| promote_overrides(&source, env, flags)).await
+}
+
+/// Differences between the flags of two environments
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentDiff {
+    pub left: String,
+    pub right: String,
+    /// Overrides of flags that are not active global flags, only in the left environment
+    pub only_left: Vec<String>,
+    /// Overrides of flags that are not active global flags, only in the right environment
+    pub only_right: Vec<String>,
+    /// Flags whose settings differ once the overrides are applied
+    pub changed: Vec<FlagDiff>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FlagDiff {
+    pub name: String,
+    /// `before` holds the left value and `after` the right one, unset fields are null
+    pub changes: Vec<FieldChange>,
+}
+
+/// Compares the flags of two active environments, looked up by name
+pub async fn compare(
+    repo: &EnvironmentRepository<Environment>,
+    flag_repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+    left: &str,
+    right: &str,
+) -> Result<EnvironmentDiff, ServiceError> {
+    let left = get_by_name(repo, project, left).await?;
+    let right = get_by_name(repo, project, right).await?;
+    let filters = feature_flag_handlers::Filters {
+        project: Some(project.to_string()),
+        ..Default::default()
+    };
+    let flags = feature_flag_handlers::find(flag_repo, filters).await?;
+    Ok(diff_overrides(&left, &right, &flags))
+}
+
+/// Compares the global flags with the overrides of each environment applied
+pub fn diff_overrides(left: &Environment, right: &Environment, flags: &[FeatureFlag]) -> EnvironmentDiff {
+    let settings = |env: &Environment, flag: &FeatureFlag| {
+        let flag = match env.get_override(&flag.name) {
+            Some(flag_override) => flag_override.apply(flag),
+            None => flag.clone(),
+        };
+        json!({"enabled": flag.enabled, "rules": flag.rules, "variant": flag.variant})
+    };
+    let mut res = EnvironmentDiff {
+        left: left.name.clone(),
+        right: right.name.clone(),
+        ..Default::default()
+    };
+    for flag in flags {
+        let changes = diff(&settings(left, flag), &settings(right, flag));
+        if !changes.is_empty() {
+            res.changed.push(FlagDiff { name: flag.name.clone(), changes });
+        }
+    }
+    let is_orphan = |flag_override: &&FlagOverride| !flags.iter().any(|flag| flag.name == flag_override.name);
+    for flag_override in left.flags.iter().filter(is_orphan) {
+        if right.get_override(&flag_override.name).is_none() {
+            res.only_left.push(flag_override.name.clone());
+        }
+    }
+    for flag_override in right.flags.iter().filter(is_orphan) {
+        if left.get_override(&flag_override.name).is_none() {
+            res.only_right.push(flag_override.name.clone());
+        }
+    }
+    res.only_left.sort();
+    res.only_right.sort();
+    res.changed.sort_by(|a, b| a.name.cmp(&b.name));
+    res
+}
+
+/// Copies the overrides of the selected flags, the target overrides of the flags the
+/// source inherits from the global flag are removed
+fn promote_overrides(source: &Environment, target: &mut Environment, flags: Option<&[String]>) {
+    let names = match flags {
+        None => return target.set_overrides(source.flags.clone()),
+        Some(names) => names,
+    };
+    for name in names {
+        match source.get_override(name) {
+            Some(flag_override) => target.set_override(flag_override.clone()),
+            None => target.remove_override(name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum OverrideOperation {
+    /// Adds an override, failing if the flag is already overridden
+    Create(FlagOverride),
+    /// Replaces an existing override
+    Update(FlagOverride),
+    Delete { name: String },
+    /// Sets the overridden `enabled`, or flips the effective one when not given
+    Toggle { name: String, enabled: Option<bool> },
+}
+
+/// Applies the operations to the environment overrides. The environment is written once,
+/// so atomic batches need no transaction, they are not written if any operation fails.
+pub async fn batch(
+    repo: &EnvironmentRepository<Environment>,
+    flag_repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+    id: &str,
+    operations: Vec<OverrideOperation>,
+    atomic: bool,
+    version: Option<i64>,
+) -> Result<(Batch<()>, Option<Change<Environment>>), ServiceError> {
+    let filters = feature_flag_handlers::Filters {
+        project: Some(project.to_string()),
+        ..Default::default()
+    };
+    let flags = feature_flag_handlers::find(flag_repo, filters).await?;
+    let id = &resolve_id(repo, project, id).await?;
+    let before = get(repo, project, id).await?;
+    let mut env = before.clone();
+    let mut results = vec![];
+    for operation in operations {
+        let res = apply(&mut env, &flags, operation);
+        let failed = res.is_err();
+        results.push(res);
+        if failed && atomic {
+            return Ok((Batch { committed: false, results }, None));
+        }
+    }
+    if !results.iter().any(|res| res.is_ok()) {
+        return Ok((Batch { committed: true, results }, None));
+    }
+    env.updated_at = Utc::now();
+    env.version = version.unwrap_or(before.version);
+    repo.update(id, &env).await?;
+    env.version += 1;
+    Ok((Batch { committed: true, results }, Some(Change { before, after: env })))
+}
+
+fn apply(
+    env: &mut Environment,
+    flags: &[FeatureFlag],
+    operation: OverrideOperation,
+) -> Result<(), ServiceError> {
+    let get_flag = |name: &str| {
+        flags.iter().find(|flag| flag.name == name).ok_or_else(|| ServiceError::new(
+            ErrorKind::NotFound,
+            format!("Feature flag not found with name {}", name),
+        ))
+    };
+    let not_overridden = |name: &str| ServiceError::new(
+        ErrorKind::NotFound,
+        format!("Feature flag {} is not overridden", name),
+    );
+    match operation {
+        OverrideOperation::Create(flag_override) => {
+            get_flag(&flag_override.name)?;
+            if env.get_override(&flag_override.name).is_some() {
+                return Err(ServiceError::new(
+                    ErrorKind::AlreadyExists,
+                    format!("Feature flag {} is already overridden", flag_override.name),
+                ));
+            }
+            env.set_override(flag_override);
+        }
+        OverrideOperation::Update(flag_override) => {
+            get_flag(&flag_override.name)?;
+            if env.get_override(&flag_override.name).is_none() {
+                return Err(not_overridden(&flag_override.name));
+            }
+            env.set_override(flag_override);
+        }
+        OverrideOperation::Delete { name } => {
+            // Overrides of archived flags can still be removed
+            if env.get_override(&name).is_none() {
+                return Err(not_overridden(&name));
             }
+            env.remove_override(&name);
+        }
+        OverrideOperation::Toggle { name, enabled } => {
+            let flag = get_flag(&name)?;
+            let current = env.get_override(&name).cloned().unwrap_or_else(|| FlagOverride::new(&name));
+            let enabled = enabled.unwrap_or(!current.apply(flag).enabled);
+            env.set_override(FlagOverride { enabled: Some(enabled), ..current });
         }
-        Err(e) => Err(e)
     }
+    Ok(())
 }
 
-#[derive(Serialize, Debug)]
+/// Applies the changes to the stored environment, failing if its version is not the expected one
+async fn modify(
+    repo: &EnvironmentRepository<Environment>,
+    project: &str,
+    id: &str,
+    version: Option<i64>,
+    changes: impl FnOnce(&mut Environment),
+) -> Result<Change<Environment>, ServiceError> {
+    let id = &resolve_id(repo, project, id).await?;
+    let before = get(repo, project, id).await?;
+    let mut env = before.clone();
+    changes(&mut env);
+    env.updated_at = Utc::now();
+    if let Some(version) = version {
+        env.version = version;
+    }
+    match repo.update(id, &env).await {
+        Ok(_) => {
+            env.version += 1;
+            Ok(Change { before, after: env })
+        }
+        Err(e) => Err(ServiceError::from(e))
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct Filters {
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Matches environments of the given project, all projects when missing
+    pub project: Option<String>,
+    /// Matches environments whose name starts with the given prefix
     pub name: Option<String>,
+    /// Lists archived environments instead of active ones
+    pub archived: bool,
+}
+
+impl Filters {
+    pub fn to_document(&self) -> Document {
+        let mut filter = doc! {};
+        if let Some(project) = &self.project {
+            filter.insert("project", project);
+        }
+        if let Some(name) = &self.name {
+            filter.insert("name", doc! {"$regex": format!("^{}", escape_regex(name))});
+        }
+        if self.archived {
+            filter.insert("archived", true);
+        } else {
+            filter.insert("archived", doc! {"$ne": true});
+        }
+        filter
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::adapters::repositories::environment_repository::environment_repository_factory;
-    use crate::adapters::repositories::feature_flags_repository::feature_flags_repository_factory;
+    use feature_flags_storage::repositories::environment_repository::environment_repository_factory;
+    use feature_flags_storage::repositories::feature_flags_repository::feature_flags_repository_factory;
     use super::*;
-    use crate::database::init_db;
-    use crate::domain::models::FeatureFlag;
+    use serde_json::Value;
+    use feature_flags_storage::database::init_db;
+    use feature_flags_core::models::{FeatureFlag, FlagMetadata, DEFAULT_PROJECT};
     use crate::services::feature_flag_handlers;
 
+    #[test]
+    fn test_apply_override_operations() {
+        let mut env = Environment::new("development");
+        let flags = vec![FeatureFlag::new("sample_flag", "Sample Flag", false, vec![])];
+
+        let res = apply(&mut env, &flags, OverrideOperation::Toggle { name: "sample_flag".to_string(), enabled: None });
+        assert!(res.is_ok());
+        assert_eq!(env.get_override("sample_flag").unwrap().enabled, Some(true));
+
+        let res = apply(&mut env, &flags, OverrideOperation::Create(FlagOverride::new("sample_flag")));
+        assert_eq!(res.unwrap_err().kind, ErrorKind::AlreadyExists);
+
+        let res = apply(&mut env, &flags, OverrideOperation::Update(FlagOverride::new("unknown_flag")));
+        assert_eq!(res.unwrap_err().kind, ErrorKind::NotFound);
+
+        let res = apply(&mut env, &flags, OverrideOperation::Delete { name: "sample_flag".to_string() });
+        assert!(res.is_ok());
+        assert!(env.flags.is_empty());
+    }
+
+    #[test]
+    fn test_diff_overrides() {
+        let flags = vec![
+            FeatureFlag::new("shared_flag", "Shared Flag", true, vec![]),
+            FeatureFlag::new("same_flag", "Same Flag", true, vec![]),
+            FeatureFlag::new("staging_flag", "Staging Flag", false, vec![]),
+        ];
+        let mut staging = Environment::new("staging");
+        staging.set_override(FlagOverride { enabled: Some(true), ..FlagOverride::new("shared_flag") });
+        // Explicitly set to the global value, the same as inheriting it
+        staging.set_override(FlagOverride { enabled: Some(true), ..FlagOverride::new("same_flag") });
+        staging.set_override(FlagOverride { enabled: Some(true), ..FlagOverride::new("staging_flag") });
+        staging.set_override(FlagOverride::new("archived_flag"));
+        let mut production = Environment::new("production");
+        production.set_override(FlagOverride {
+            enabled: Some(false),
+            variant: Some("control".to_string()),
+            ..FlagOverride::new("shared_flag")
+        });
+
+        let res = diff_overrides(&staging, &production, &flags);
+        assert_eq!(res.only_left, vec!["archived_flag"]);
+        assert!(res.only_right.is_empty());
+        let names: Vec<&str> = res.changed.iter().map(|flag| flag.name.as_str()).collect();
+        assert_eq!(names, vec!["shared_flag", "staging_flag"]);
+        let fields: Vec<&str> = res.changed[0].changes.iter().map(|c| c.field.as_str()).collect();
+        assert_eq!(fields, vec!["enabled", "variant"]);
+        assert_eq!(res.changed[0].changes[1].before, Value::Null);
+    }
+
+    #[test]
+    fn test_promote_overrides() {
+        let mut source = Environment::new("staging");
+        source.set_override(FlagOverride { enabled: Some(true), ..FlagOverride::new("flag_a") });
+        let mut target = Environment::new("production");
+        target.set_override(FlagOverride { enabled: Some(false), ..FlagOverride::new("flag_b") });
+        target.set_override(FlagOverride { enabled: Some(false), ..FlagOverride::new("flag_c") });
+
+        // The source inherits flag_b, so does the target afterwards
+        let mut promoted = target.clone();
+        promote_overrides(&source, &mut promoted, Some(&["flag_a".to_string(), "flag_b".to_string()]));
+        assert_eq!(promoted.get_override("flag_a").unwrap().enabled, Some(true));
+        assert!(promoted.get_override("flag_b").is_none());
+        assert!(promoted.get_override("flag_c").is_some());
+
+        let mut promoted = target.clone();
+        promote_overrides(&source, &mut promoted, None);
+        assert_eq!(promoted.flags, source.flags);
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("diff_staging").is_ok());
+        assert_eq!(validate_name("diff").unwrap_err().kind, ErrorKind::InvalidArgument);
+        assert_eq!(validate_name("64b7f0c2a1e3d4f5a6b7c8d9").unwrap_err().kind, ErrorKind::InvalidArgument);
+    }
+
     #[actix_web::test]
     async fn test_create() {
         let db = init_db().await.unwrap();
         let repo = environment_repository_factory(&db).await;
-        let res = create(&repo, "development").await;
-        assert!(res.is_ok());
-        match res {
-            Ok(id) => {
-                delete(&repo, &id).await.unwrap();
-            }
-            Err(_) => {}
-        }
+        let id = create(&repo, DEFAULT_PROJECT, "development").await.unwrap();
+        delete(&repo, &id).await.unwrap();
     }
 
     #[actix_web::test]
     async fn test_update() {
         let db = init_db().await.unwrap();
         let repo = environment_repository_factory(&db).await;
-        let res = create(&repo, "services_test").await;
-        assert!(res.is_ok());
-        match res {
-            Ok(id) => {
-                let mut env = Environment::new("services_test");
-                let flag = FeatureFlag::new("sample_flag", "Sample Flag", true, vec![]);
-                env.add_flag(&flag);
-                update(&repo, &id, &env).await.unwrap();
-                let item = get(&repo, &id).await.unwrap();
-                assert_eq!(item.name, "services_test");
-                assert_eq!(item.flags.len(), 1);
-                delete(&repo, &id).await.unwrap();
-            }
-            Err(_) => {}
-        }
+        let id = create(&repo, DEFAULT_PROJECT, "services_test").await.unwrap();
+        let mut env = Environment::new("services_test");
+        env.set_override(FlagOverride::new("sample_flag"));
+        update(&repo, &id, &env).await.unwrap();
+        let item = get(&repo, DEFAULT_PROJECT, &id).await.unwrap();
+        assert_eq!(item.name, "services_test");
+        assert_eq!(item.flags.len(), 1);
+        delete(&repo, &id).await.unwrap();
     }
 
     #[actix_web::test]
@@ -195,36 +614,45 @@ mod tests {
         let db = init_db().await.unwrap();
         let repo = environment_repository_factory(&db).await;
 
-        let inserted_id = create(&repo, "services_test_env").await.unwrap();
+        let inserted_id = create(&repo, DEFAULT_PROJECT, "services_test_env").await.unwrap();
 
         let flag_repo = feature_flags_repository_factory(&db).await;
-        let inserted_flag_id = feature_flag_handlers::create(
+        let flag_id = feature_flag_handlers::create(
             &flag_repo,
+            DEFAULT_PROJECT,
             "flag_to_be_managed",
             "Flag to be managed",
             false,
-            &vec![]
+            &[],
+            &FlagMetadata::default(),
         ).await.unwrap();
 
-        let flag = FeatureFlag::new(
-            "flag_to_be_managed",
-            "Flag to be managed",
-            true, vec![]
-        );
+        let flag_override = FlagOverride {
+            enabled: Some(true),
+            ..FlagOverride::new("flag_to_be_managed")
+        };
 
-        let res = set_flag(&repo, &inserted_id, &flag).await;
+        let res = set_flag(&repo, DEFAULT_PROJECT, &flag_repo, &inserted_id, flag_override.clone(), None).await;
         assert!(res.is_ok());
+        assert_eq!(res.unwrap().after.version, 1);
+
+        let res = set_flag(&repo, DEFAULT_PROJECT, &flag_repo, &inserted_id, flag_override, Some(0)).await;
+        assert_eq!(res.unwrap_err().kind, ErrorKind::VersionConflict);
+
+        // Only existing flags can be overridden
+        let res = set_flag(&repo, DEFAULT_PROJECT, &flag_repo, &inserted_id, FlagOverride::new("unknown_flag"), None).await;
+        assert_eq!(res.unwrap_err().kind, ErrorKind::NotFound);
 
-        let res = get(&repo, &inserted_id).await.unwrap();
+        let res = get(&repo, DEFAULT_PROJECT, &inserted_id).await.unwrap();
         assert_eq!(res.flags.len(), 1);
 
-        let res = remove_flag(&repo, &inserted_id, "flag_to_be_managed").await;
+        let res = remove_flag(&repo, DEFAULT_PROJECT, &inserted_id, "flag_to_be_managed", None).await;
         assert!(res.is_ok());
 
-        let res = get(&repo, &inserted_id).await.unwrap();
+        let res = get(&repo, DEFAULT_PROJECT, &inserted_id).await.unwrap();
         assert_eq!(res.flags.len(), 0);
 
         delete(&repo, &inserted_id).await.unwrap();
-        flag_repo.collection.delete_one(doc! {"name": "flag_to_be_managed"}, None).await.unwrap();
+        feature_flag_handlers::delete(&flag_repo, &flag_id).await.unwrap();
     }
 }
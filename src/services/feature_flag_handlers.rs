@@ -1,35 +1,39 @@
 use chrono::Utc;
-use mongodb::bson;
-use crate::adapters::repositories::feature_flags_repository::{FeatureFlagRepository};
-use crate::adapters::repositories::BaseRepository;
-use crate::domain::models::{FeatureFlag, Rule};
-use crate::services::ServiceError;
-use mongodb::bson::{doc, to_document};
-use serde::Serialize;
+use futures::TryStreamExt;
+use feature_flags_storage::database::supports_transactions;
+use feature_flags_storage::repositories::environment_repository::EnvironmentRepository;
+use feature_flags_storage::repositories::feature_flags_repository::{FeatureFlagRepository};
+use feature_flags_storage::repositories::pagination::{Page, PageRequest};
+use feature_flags_storage::repositories::revision_repository::RevisionRepository;
+use feature_flags_storage::repositories::{BaseRepository, ErrorKind};
+use feature_flags_storage::domain::history::Revision;
+use feature_flags_core::models::{Environment, FeatureFlag, FlagMetadata, FlagMetadataUpdate, Lifecycle, Rule};
+use crate::services::audit_handlers::Auditor;
+use crate::services::{self, environment_handlers, history_handlers, Batch, Change, ServiceError, Write};
+use crate::utils::escape_regex;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Document};
+use mongodb::{Client, ClientSession};
+use serde::{Deserialize, Serialize};
 
 pub async fn create(
     repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
     name: &str,
     label: &str,
     enabled: bool,
-    rules: &Vec<Rule>,
+    rules: &[Rule],
+    metadata: &FlagMetadata,
 ) -> Result<String, ServiceError> {
-    let inserted_id = repo.create(
-        &FeatureFlag {
-            id: None,
-            name: name.to_string(),
-            label: label.to_string(),
-            enabled,
-            rules: rules.to_vec(),
-            created_at: Utc::now(),
-            updated_at: Utc::now(),
-        }
-    ).await;
+    validate_name(name)?;
+    check_lifecycle(metadata.lifecycle)?;
+    let mut feature_flag = FeatureFlag::new(name, label, enabled, rules.to_vec());
+    feature_flag.project = project.to_string();
+    feature_flag.set_metadata(metadata.clone());
+    let inserted_id = repo.create(&feature_flag).await;
     match inserted_id {
         Ok(id) => Ok(id),
-        Err(e) => Err(ServiceError {
-            message: e.to_string(),
-        }),
+        Err(e) => Err(ServiceError::from(e)),
     }
 }
 
@@ -39,54 +43,397 @@ pub async fn find(
 ) -> Result<Vec<FeatureFlag>, ServiceError> {
     let _filters = match filters.into() {
         None => doc! {},
-        Some(f) => to_document(&f).unwrap(),
+        Some(f) => f.to_document(),
     };
     let res = repo.find(_filters).await;
     match res {
         Ok(res) => Ok(res),
-        Err(e) => Err(ServiceError {
-            message: e.to_string(),
-        }),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+pub async fn find_page(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    filters: &Filters,
+    page: &PageRequest,
+) -> Result<Page<FeatureFlag>, ServiceError> {
+    match repo.find_page(filters.to_document(), page).await {
+        Ok(res) => Ok(res),
+        Err(e) => Err(ServiceError::from(e)),
     }
 }
 
+/// Tags of the active flags of the project, with the number of flags having each of them
+pub async fn tags(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+) -> Result<Vec<TagCount>, ServiceError> {
+    let pipeline = [
+        doc! {"$match": {"project": project, "archived": {"$ne": true}}},
+        doc! {"$unwind": "$tags"},
+        doc! {"$group": {"_id": "$tags", "count": {"$sum": 1}}},
+        doc! {"$sort": {"_id": 1}},
+    ];
+    let mut cursor = repo.collection.aggregate(pipeline, None).await?;
+    let mut res = vec![];
+    while let Some(item) = cursor.try_next().await? {
+        if let (Ok(name), Ok(count)) = (item.get_str("_id"), item.get_i32("count")) {
+            res.push(TagCount { name: name.to_string(), count: count as u64 });
+        }
+    }
+    Ok(res)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagCount {
+    pub name: String,
+    pub count: u64,
+}
+
+/// Id of the project flag with the given id or name, archived flags included
+pub async fn resolve_id(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+    key: &str,
+) -> Result<String, ServiceError> {
+    services::resolve_id(&repo.collection, Some(project), key, None).await
+}
+
+/// Flag with the given id or name, flags of other projects are not found
 pub async fn get(
     repo: &FeatureFlagRepository<FeatureFlag>,
-    id: &str,
+    project: &str,
+    key: &str,
 ) -> Result<FeatureFlag, ServiceError> {
-    let res = repo.get(id).await;
+    let id = resolve_id(repo, project, key).await?;
+    let res = repo.get(&id).await;
     match res {
-        Ok(flag) => Ok(flag),
-        Err(e) => Err(ServiceError {
-            message: e.to_string(),
-        }),
+        Ok(flag) if flag.project == project => Ok(flag),
+        Ok(_) => Err(ServiceError::new(ErrorKind::NotFound, "Entity not found".to_string())),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+/// Active flag with the given name, archived flags are not returned
+pub async fn get_by_name(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+    name: &str,
+) -> Result<FeatureFlag, ServiceError> {
+    match repo
+        .collection
+        .find_one(doc! {"project": project, "name": name, "archived": {"$ne": true}}, None)
+        .await
+    {
+        Ok(Some(feature_flag)) => Ok(feature_flag),
+        Ok(None) => Err(ServiceError::new(
+            ErrorKind::NotFound,
+            format!("Feature flag not found with name {}", name),
+        )),
+        Err(err) => Err(ServiceError::from(err)),
+    }
+}
+
+/// Settings replaced by an update, the metadata fields that are not given are kept
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FlagUpdate {
+    pub label: String,
+    pub enabled: bool,
+    pub rules: Vec<Rule>,
+    #[serde(flatten)]
+    pub metadata: FlagMetadataUpdate,
+}
+
+impl FlagUpdate {
+    fn apply(self, feature_flag: &mut FeatureFlag) {
+        feature_flag.label = self.label;
+        feature_flag.enabled = self.enabled;
+        feature_flag.rules = self.rules;
+        feature_flag.set_metadata(self.metadata.apply(feature_flag.metadata()));
     }
 }
 
 pub async fn update(
     repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
     id: &str,
-    label: &str,
-    enabled: bool,
-    rules: Vec<Rule>
-) -> Result<(), ServiceError> {
-    match repo.get(id).await {
-        Ok(mut feature_flag) => {
-            feature_flag.label = label.to_string();
-            feature_flag.enabled = enabled;
-            feature_flag.rules = rules;
-
-            match repo.update(id, &feature_flag).await {
-                Ok(_) => Ok(()),
-                Err(e) => Err(ServiceError {
-                    message: e.to_string(),
-                }),
+    update: FlagUpdate,
+    version: Option<i64>,
+) -> Result<Change<FeatureFlag>, ServiceError> {
+    check_lifecycle(update.metadata.lifecycle.unwrap_or_default())?;
+    modify(repo, project, id, version, |feature_flag| update.apply(feature_flag)).await
+}
+
+/// Restores the configurable fields of a flag as they were at the given revision
+pub async fn rollback(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+    history_repo: &RevisionRepository<Revision<FeatureFlag>>,
+    id: &str,
+    revision: i64,
+    version: Option<i64>,
+) -> Result<Change<FeatureFlag>, ServiceError> {
+    let id = resolve_id(repo, project, id).await?;
+    let revision = history_handlers::get(history_repo, &id, revision).await?;
+    let snapshot = revision.snapshot;
+    modify(repo, project, &id, version, |feature_flag| {
+        feature_flag.set_metadata(snapshot.metadata());
+        feature_flag.label = snapshot.label;
+        feature_flag.enabled = snapshot.enabled;
+        feature_flag.rules = snapshot.rules;
+    }).await
+}
+
+/// Applies the changes to the stored flag, failing if its version is not the expected one
+async fn modify(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+    id: &str,
+    version: Option<i64>,
+    changes: impl FnOnce(&mut FeatureFlag),
+) -> Result<Change<FeatureFlag>, ServiceError> {
+    modify_in(repo, project, id, version, changes, None).await
+}
+
+/// Same as `modify`, as part of the session transaction when one is given
+async fn modify_in(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+    key: &str,
+    version: Option<i64>,
+    changes: impl FnOnce(&mut FeatureFlag),
+    mut session: Option<&mut ClientSession>,
+) -> Result<Change<FeatureFlag>, ServiceError> {
+    let id = &services::resolve_id(&repo.collection, Some(project), key, session.as_deref_mut()).await?;
+    let before = match session.as_deref_mut() {
+        Some(session) => repo.get_with_session(id, session).await?,
+        None => repo.get(id).await?,
+    };
+    if before.project != project {
+        return Err(ServiceError::new(ErrorKind::NotFound, "Entity not found".to_string()));
+    }
+    let mut feature_flag = before.clone();
+    changes(&mut feature_flag);
+    feature_flag.updated_at = Utc::now();
+    if let Some(version) = version {
+        feature_flag.version = version;
+    }
+    let res = match session {
+        Some(session) => repo.update_with_session(id, &feature_flag, session).await,
+        None => repo.update(id, &feature_flag).await,
+    };
+    match res {
+        Ok(_) => {
+            feature_flag.version += 1;
+            Ok(Change { before, after: feature_flag })
+        }
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+/// Hides the flag from evaluation and default listings, it can be restored later
+pub async fn archive(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+    id: &str,
+    version: Option<i64>,
+) -> Result<Change<FeatureFlag>, ServiceError> {
+    modify(repo, project, id, version, |feature_flag| feature_flag.set_archived(true)).await
+}
+
+pub async fn restore(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+    id: &str,
+    version: Option<i64>,
+) -> Result<Change<FeatureFlag>, ServiceError> {
+    modify(repo, project, id, version, |feature_flag| feature_flag.set_archived(false)).await
+}
+
+/// Permanently removes an archived flag, refused while an environment still references it
+pub async fn purge(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    env_repo: &EnvironmentRepository<Environment>,
+    project: &str,
+    id: &str,
+) -> Result<FeatureFlag, ServiceError> {
+    let id = resolve_id(repo, project, id).await?;
+    let feature_flag = get(repo, project, &id).await?;
+    if !feature_flag.archived {
+        return Err(ServiceError::new(
+            ErrorKind::InvalidState,
+            format!("Flag {} must be archived before being purged", feature_flag.name),
+        ));
+    }
+    let envs = environment_handlers::find_referencing(env_repo, project, &feature_flag.name).await?;
+    if !envs.is_empty() {
+        let names: Vec<String> = envs.into_iter().map(|env| env.name).collect();
+        return Err(ServiceError::new(
+            ErrorKind::InvalidState,
+            format!("Flag {} is still used by environments {}", feature_flag.name, names.join(", ")),
+        ));
+    }
+    delete(repo, &id).await?;
+    Ok(feature_flag)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FlagOperation {
+    Create {
+        name: String,
+        label: String,
+        #[serde(default)]
+        enabled: bool,
+        #[serde(default)]
+        rules: Vec<Rule>,
+        #[serde(flatten)]
+        metadata: FlagMetadata,
+    },
+    Update {
+        id: String,
+        label: String,
+        enabled: bool,
+        #[serde(default)]
+        rules: Vec<Rule>,
+        #[serde(flatten)]
+        metadata: FlagMetadataUpdate,
+        version: Option<i64>,
+    },
+    /// Archives the flag
+    Delete {
+        id: String,
+        version: Option<i64>,
+    },
+    /// Sets `enabled`, or flips it when not given
+    Toggle {
+        id: String,
+        enabled: Option<bool>,
+        version: Option<i64>,
+    },
+}
+
+impl FlagOperation {
+    /// Action recorded in the audit log
+    pub fn audit_action(&self) -> &'static str {
+        match self {
+            FlagOperation::Create { .. } => "feature_flag.create",
+            FlagOperation::Update { .. } => "feature_flag.update",
+            FlagOperation::Delete { .. } => "feature_flag.archive",
+            FlagOperation::Toggle { .. } => "feature_flag.toggle",
+        }
+    }
+}
+
+/// Applies the operations in order and audits them. Atomic batches run in a transaction,
+/// audit entries included, that is aborted on the first failure, otherwise every operation
+/// is applied on its own. Atomic batches are rejected by deployments without transactions.
+pub async fn batch(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    client: &Client,
+    project: &str,
+    operations: Vec<FlagOperation>,
+    atomic: bool,
+    auditor: &Auditor,
+) -> Result<Batch<Write<FeatureFlag>>, ServiceError> {
+    let mut results = vec![];
+    if !atomic {
+        for operation in operations {
+            let action = operation.audit_action();
+            let res = apply(repo, project, operation, None).await;
+            if let Ok(write) = &res {
+                auditor.record(action, write.before.as_ref(), Some(&write.after), None).await?;
             }
+            results.push(res);
+        }
+        return Ok(Batch { committed: true, results });
+    }
+    if !supports_transactions(client).await? {
+        return Err(ServiceError::new(
+            ErrorKind::Unsupported,
+            "Atomic batches need a replica set, retry with mode=best_effort".to_string(),
+        ));
+    }
+    let mut session = client.start_session(None).await?;
+    session.start_transaction(None).await?;
+    for operation in operations {
+        let action = operation.audit_action();
+        let res = match apply(repo, project, operation, Some(&mut session)).await {
+            Ok(write) => auditor
+                .record(action, write.before.as_ref(), Some(&write.after), Some(&mut session))
+                .await
+                .map(|_| write),
+            Err(err) => Err(err),
+        };
+        let failed = res.is_err();
+        results.push(res);
+        if failed {
+            session.abort_transaction().await?;
+            return Ok(Batch { committed: false, results });
+        }
+    }
+    session.commit_transaction().await?;
+    Ok(Batch { committed: true, results })
+}
+
+async fn apply(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    project: &str,
+    operation: FlagOperation,
+    mut session: Option<&mut ClientSession>,
+) -> Result<Write<FeatureFlag>, ServiceError> {
+    match operation {
+        FlagOperation::Create { name, label, enabled, rules, metadata } => {
+            validate_name(&name)?;
+            check_lifecycle(metadata.lifecycle)?;
+            let mut feature_flag = FeatureFlag::new(&name, &label, enabled, rules);
+            feature_flag.project = project.to_string();
+            feature_flag.set_metadata(metadata);
+            let id = match session.as_deref_mut() {
+                Some(session) => repo.create_with_session(&feature_flag, session).await?,
+                None => repo.create(&feature_flag).await?,
+            };
+            feature_flag.id = ObjectId::parse_str(id).ok();
+            Ok(Write { before: None, after: feature_flag })
+        }
+        FlagOperation::Update { id, label, enabled, rules, metadata, version } => {
+            check_lifecycle(metadata.lifecycle.unwrap_or_default())?;
+            let update = FlagUpdate { label, enabled, rules, metadata };
+            modify_in(repo, project, &id, version, |feature_flag| update.apply(feature_flag), session)
+                .await
+                .map(Write::from)
         }
-        Err(e) => Err(ServiceError {
-            message: e.to_string(),
-        }),
+        FlagOperation::Delete { id, version } => {
+            modify_in(repo, project, &id, version, |feature_flag| feature_flag.set_archived(true), session)
+                .await
+                .map(Write::from)
+        }
+        FlagOperation::Toggle { id, enabled, version } => {
+            modify_in(repo, project, &id, version, |feature_flag| {
+                feature_flag.enabled = enabled.unwrap_or(!feature_flag.enabled);
+            }, session).await.map(Write::from)
+        }
+    }
+}
+
+/// Names of the client routes under `/flags/{environment_name}`, flags named like them
+/// could not be evaluated one at a time
+const RESERVED_NAMES: [&str; 2] = ["batch", "stream"];
+
+/// Flag names can not look like ids, and the names of the client routes are reserved
+pub fn validate_name(name: &str) -> Result<(), ServiceError> {
+    services::validate_name("Feature flag", name, &RESERVED_NAMES)
+}
+
+/// The archived state is set by archiving the flag, not by updating its lifecycle
+fn check_lifecycle(lifecycle: Lifecycle) -> Result<(), ServiceError> {
+    if lifecycle == Lifecycle::Archived {
+        return Err(ServiceError::new(
+            ErrorKind::InvalidArgument,
+            "Flags are moved to the archived state by archiving them".to_string(),
+        ));
     }
+    Ok(())
 }
 
 pub async fn delete(
@@ -95,29 +442,65 @@ pub async fn delete(
 ) -> Result<(), ServiceError> {
     match repo.delete(id).await {
         Ok(_) => Ok(()),
-        Err(e) => Err(ServiceError {
-            message: e.to_string(),
-        }),
+        Err(e) => Err(ServiceError::from(e)),
     }
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Debug, Default)]
 pub struct Filters {
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Matches flags of the given project, all projects when missing
+    pub project: Option<String>,
+    /// Matches flags whose name starts with the given prefix
     pub name: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Matches flags whose label contains the given text, ignoring case
     pub label: Option<String>,
+    pub enabled: Option<bool>,
+    /// Matches flags having all the given tags
+    pub tags: Vec<String>,
+    pub owner: Option<String>,
+    /// Lists archived flags instead of active ones
+    pub archived: bool,
+}
+
+impl Filters {
+    pub fn to_document(&self) -> Document {
+        let mut filter = doc! {};
+        if let Some(project) = &self.project {
+            filter.insert("project", project);
+        }
+        if let Some(name) = &self.name {
+            filter.insert("name", doc! {"$regex": format!("^{}", escape_regex(name))});
+        }
+        if let Some(label) = &self.label {
+            filter.insert("label", doc! {"$regex": escape_regex(label), "$options": "i"});
+        }
+        if let Some(enabled) = self.enabled {
+            filter.insert("enabled", enabled);
+        }
+        if !self.tags.is_empty() {
+            filter.insert("tags", doc! {"$all": &self.tags});
+        }
+        if let Some(owner) = &self.owner {
+            filter.insert("owner", owner);
+        }
+        if self.archived {
+            filter.insert("archived", true);
+        } else {
+            // Flags created before archiving have no archived field
+            filter.insert("archived", doc! {"$ne": true});
+        }
+        filter
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashSet;
-    use actix_web::web::Json;
-    use mongodb::bson::Bson::DateTime;
-    use crate::adapters::repositories::feature_flags_repository::feature_flags_repository_factory;
+    use feature_flags_storage::repositories::feature_flags_repository::feature_flags_repository_factory;
     use super::*;
-    use crate::database::init_db;
-    use crate::domain::models::Operator;
+    use feature_flags_storage::database::init_db;
+    use feature_flags_storage::repositories::ErrorKind;
+    use feature_flags_core::evaluation::Operator;
+    use feature_flags_core::models::DEFAULT_PROJECT;
 
     #[actix_web::test]
     async fn test_create() {
@@ -125,26 +508,23 @@ mod tests {
         let repo = feature_flags_repository_factory(&db).await;
         let res = create(
             &repo,
+            DEFAULT_PROJECT,
             "feature_flag_handlers_test",
             "Feature Flag handlers test",
             false,
-            &vec![
+            &[
                 Rule {
                     parameter: "tenant".to_string(),
                     operator: Operator::Is("tenant1".to_string()),
                 }
-            ]
+            ],
+            &FlagMetadata::default(),
         ).await;
-        assert!(res.is_ok());
-        match res {
-            Ok(id) => {
-                let res = repo.get(&id).await.unwrap();
-                assert_eq!(res.name, "feature_flag_handlers_test");
-                assert_eq!(res.rules.len(), 1);
-                delete(&repo, &id).await.unwrap();
-            }
-            Err(_) => {}
-        }
+        let id = res.unwrap();
+        let res = repo.get(&id).await.unwrap();
+        assert_eq!(res.name, "feature_flag_handlers_test");
+        assert_eq!(res.rules.len(), 1);
+        delete(&repo, &id).await.unwrap();
     }
 
     #[actix_web::test]
@@ -153,20 +533,82 @@ mod tests {
         let repo = feature_flags_repository_factory(&db).await;
         let res = create(
             &repo,
+            DEFAULT_PROJECT,
             "feature_flag_handlers_test_update",
             "test",
             false,
-            &vec![]
+            &[],
+            &FlagMetadata::default(),
         ).await;
-        assert!(res.is_ok());
-        match res {
-            Ok(id) => {
-                update(&repo, &id, "new_label", true, vec![]).await.unwrap();
-                let res = get(&repo, &id).await.unwrap();
-                assert_eq!(res.label, "new_label");
-                delete(&repo, &id).await.unwrap();
+        let id = res.unwrap();
+        let changes = |label: &str| FlagUpdate {
+            label: label.to_string(),
+            enabled: true,
+            rules: vec![],
+            metadata: FlagMetadataUpdate::default(),
+        };
+        update(&repo, DEFAULT_PROJECT, &id, changes("new_label"), Some(0)).await.unwrap();
+        let res = get(&repo, DEFAULT_PROJECT, &id).await.unwrap();
+        assert_eq!(res.label, "new_label");
+        assert_eq!(res.version, 1);
+
+        // Stale version is rejected
+        let res = update(&repo, DEFAULT_PROJECT, &id, changes("stale_label"), Some(0)).await;
+        assert_eq!(res.unwrap_err().kind, ErrorKind::VersionConflict);
+        delete(&repo, &id).await.unwrap();
+    }
+
+    #[test]
+    fn test_flag_operation_metadata() {
+        let operation: FlagOperation = serde_json::from_value(serde_json::json!({
+            "op": "create",
+            "name": "sample_flag",
+            "label": "Sample Flag",
+            "description": "Shows the new checkout",
+            "owner": "payments",
+            "links": ["https://tracker/FF-1"],
+        })).unwrap();
+        match operation {
+            FlagOperation::Create { metadata, .. } => {
+                assert_eq!(metadata.description, "Shows the new checkout");
+                assert_eq!(metadata.owner.as_deref(), Some("payments"));
+                assert_eq!(metadata.links.len(), 1);
+                assert!(metadata.tags.is_empty());
             }
-            Err(_) => {}
+            _ => panic!("Unexpected operation"),
         }
     }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("batch_rollout").is_ok());
+        assert_eq!(validate_name("batch").unwrap_err().kind, ErrorKind::InvalidArgument);
+        assert_eq!(validate_name("stream").unwrap_err().kind, ErrorKind::InvalidArgument);
+        assert_eq!(validate_name("64b7f0c2a1e3d4f5a6b7c8d9").unwrap_err().kind, ErrorKind::InvalidArgument);
+    }
+
+    #[test]
+    fn test_filters_to_document() {
+        let filters = Filters {
+            project: Some("checkout".to_string()),
+            name: Some("new_".to_string()),
+            label: Some("ui".to_string()),
+            enabled: Some(true),
+            tags: vec!["frontend".to_string()],
+            owner: Some("growth".to_string()),
+            archived: false,
+        };
+        assert_eq!(filters.to_document(), doc! {
+            "project": "checkout",
+            "name": {"$regex": "^new_"},
+            "label": {"$regex": "ui", "$options": "i"},
+            "enabled": true,
+            "tags": {"$all": ["frontend"]},
+            "owner": "growth",
+            "archived": {"$ne": true},
+        });
+        assert_eq!(Filters::default().to_document(), doc! {"archived": {"$ne": true}});
+        let archived = Filters { archived: true, ..Default::default() };
+        assert_eq!(archived.to_document(), doc! {"archived": true});
+    }
 }
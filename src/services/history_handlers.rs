@@ -0,0 +1,62 @@
+use mongodb::bson::oid::ObjectId;
+use serde::de::DeserializeOwned;
+use feature_flags_storage::repositories::revision_repository::RevisionRepository;
+use feature_flags_storage::repositories::{BaseRepository, ErrorKind};
+use feature_flags_storage::domain::history::{Revision, RevisionAction, Revisioned};
+use crate::services::ServiceError;
+
+pub async fn record<T>(
+    repo: &RevisionRepository<Revision<T>>,
+    action: RevisionAction,
+    before: Option<&T>,
+    after: &T,
+    actor: &str,
+) -> Result<(), ServiceError>
+where
+    T: Revisioned + DeserializeOwned + Clone + Unpin + Send + Sync,
+{
+    let revision = match Revision::new(action, before, after.clone(), actor) {
+        Some(revision) => revision,
+        None => return Err(ServiceError::new(
+            ErrorKind::InvalidArgument,
+            "Cannot record a revision for an entity without id".to_string(),
+        )),
+    };
+    match repo.create(&revision).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+pub async fn find<T>(
+    repo: &RevisionRepository<Revision<T>>,
+    entity_id: &str,
+) -> Result<Vec<Revision<T>>, ServiceError>
+where
+    T: Revisioned + DeserializeOwned + Unpin + Send + Sync,
+{
+    let entity_id = parse_id(entity_id)?;
+    match repo.find_by_entity(&entity_id).await {
+        Ok(revisions) => Ok(revisions),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+pub async fn get<T>(
+    repo: &RevisionRepository<Revision<T>>,
+    entity_id: &str,
+    revision: i64,
+) -> Result<Revision<T>, ServiceError>
+where
+    T: Revisioned + DeserializeOwned + Unpin + Send + Sync,
+{
+    let entity_id = parse_id(entity_id)?;
+    match repo.find_revision(&entity_id, revision).await {
+        Ok(revision) => Ok(revision),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+fn parse_id(id: &str) -> Result<ObjectId, ServiceError> {
+    ObjectId::parse_str(id).map_err(|err| ServiceError::new(ErrorKind::NotFound, err.to_string()))
+}
@@ -1,11 +1,32 @@
+pub mod audit_handlers;
+pub mod cache_handlers;
+pub mod config_handlers;
 pub mod environment_handlers;
 pub mod feature_flag_handlers;
+pub mod history_handlers;
+pub mod project_handlers;
+pub mod report_handlers;
+pub mod ruleset_handlers;
+pub mod sync_handlers;
+pub mod usage_handlers;
 
 use std::fmt::{Display, Formatter};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOneOptions;
+use mongodb::{ClientSession, Collection};
+use feature_flags_storage::repositories::{ErrorKind, RepositoryError};
 
 #[derive(Clone, Debug)]
 pub struct ServiceError {
     message: String,
+    pub kind: ErrorKind,
+}
+
+impl ServiceError {
+    pub fn new(kind: ErrorKind, message: String) -> Self {
+        Self { message, kind }
+    }
 }
 
 impl Display for ServiceError {
@@ -13,3 +34,92 @@ impl Display for ServiceError {
         write!(f, "{}", self.message)
     }
 }
+
+impl From<RepositoryError> for ServiceError {
+    fn from(err: RepositoryError) -> Self {
+        Self::new(err.kind, err.message)
+    }
+}
+
+impl From<mongodb::error::Error> for ServiceError {
+    fn from(err: mongodb::error::Error) -> Self {
+        Self::new(ErrorKind::Internal, err.to_string())
+    }
+}
+
+/// States of an entity before and after an update
+#[derive(Clone, Debug)]
+pub struct Change<T> {
+    pub before: T,
+    pub after: T,
+}
+
+/// States of an entity before and after a write, `before` is empty for creations
+#[derive(Clone, Debug)]
+pub struct Write<T> {
+    pub before: Option<T>,
+    pub after: T,
+}
+
+impl<T> From<Change<T>> for Write<T> {
+    fn from(change: Change<T>) -> Self {
+        Write { before: Some(change.before), after: change.after }
+    }
+}
+
+/// Outcome of a batch of operations, one result per operation in the same order.
+/// Atomic batches stop at the first failure and are not committed.
+#[derive(Debug)]
+pub struct Batch<T> {
+    pub committed: bool,
+    pub results: Vec<Result<T, ServiceError>>,
+}
+
+/// Names are accepted in place of ids in the routes, names that look like ids or that are
+/// taken by other routes could not be looked up
+pub(crate) fn validate_name(entity: &str, name: &str, reserved: &[&str]) -> Result<(), ServiceError> {
+    if ObjectId::parse_str(name).is_ok() {
+        return Err(ServiceError::new(
+            ErrorKind::InvalidArgument,
+            format!("{} name {} can not be an id", entity, name),
+        ));
+    }
+    if reserved.contains(&name) {
+        return Err(ServiceError::new(
+            ErrorKind::InvalidArgument,
+            format!("{} name {} is reserved", entity, name),
+        ));
+    }
+    Ok(())
+}
+
+/// Id of the entity with the given id or name, archived entities included. Entities are
+/// looked up within the project when one is given. Keys that are valid ids are never
+/// looked up as names, `validate_name` keeps names from looking like ids.
+pub(crate) async fn resolve_id<T>(
+    collection: &Collection<T>,
+    project: Option<&str>,
+    key: &str,
+    session: Option<&mut ClientSession>,
+) -> Result<String, ServiceError> {
+    if ObjectId::parse_str(key).is_ok() {
+        return Ok(key.to_string());
+    }
+    let mut filter = doc! {"name": key};
+    if let Some(project) = project {
+        filter.insert("project", project);
+    }
+    let options = FindOneOptions::builder().projection(doc! {"_id": 1}).build();
+    let collection = collection.clone_with_type::<Document>();
+    let res = match session {
+        Some(session) => collection.find_one_with_session(filter, options, session).await?,
+        None => collection.find_one(filter, options).await?,
+    };
+    match res.and_then(|entity| entity.get_object_id("_id").ok()) {
+        Some(id) => Ok(id.to_hex()),
+        None => Err(ServiceError::new(
+            ErrorKind::NotFound,
+            format!("Entity not found with id or name {}", key),
+        )),
+    }
+}
@@ -0,0 +1,105 @@
+use feature_flags_storage::repositories::environment_repository::EnvironmentRepository;
+use feature_flags_storage::repositories::feature_flags_repository::FeatureFlagRepository;
+use feature_flags_storage::repositories::project_repository::ProjectRepository;
+use feature_flags_storage::repositories::{BaseRepository, ErrorKind};
+use feature_flags_core::models::{Environment, FeatureFlag, Project, DEFAULT_PROJECT};
+use crate::services::{self, ServiceError};
+use mongodb::bson::doc;
+
+pub async fn find(repo: &ProjectRepository<Project>) -> Result<Vec<Project>, ServiceError> {
+    match repo.find(doc! {}).await {
+        Ok(res) => Ok(res),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+pub async fn create(
+    repo: &ProjectRepository<Project>,
+    name: &str,
+    label: &str,
+) -> Result<String, ServiceError> {
+    validate_name(name)?;
+    match repo.create(&Project::new(name, label)).await {
+        Ok(id) => Ok(id),
+        Err(err) => Err(ServiceError::from(err)),
+    }
+}
+
+/// Project with the given id or name
+pub async fn get(repo: &ProjectRepository<Project>, key: &str) -> Result<Project, ServiceError> {
+    let id = services::resolve_id(&repo.collection, None, key, None).await?;
+    match repo.get(&id).await {
+        Ok(item) => Ok(item),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+pub async fn get_by_name(
+    repo: &ProjectRepository<Project>,
+    name: &str,
+) -> Result<Project, ServiceError> {
+    match repo.collection.find_one(doc! {"name": name}, None).await {
+        Ok(Some(item)) => Ok(item),
+        Ok(None) => Err(ServiceError::new(
+            ErrorKind::NotFound,
+            format!("Project {} not found", name),
+        )),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+/// Deletes an empty project, archived flags and environments still belong to it
+pub async fn delete(
+    repo: &ProjectRepository<Project>,
+    flag_repo: &FeatureFlagRepository<FeatureFlag>,
+    env_repo: &EnvironmentRepository<Environment>,
+    key: &str,
+) -> Result<Project, ServiceError> {
+    let id = services::resolve_id(&repo.collection, None, key, None).await?;
+    let project = get(repo, &id).await?;
+    if project.name == DEFAULT_PROJECT {
+        return Err(ServiceError::new(
+            ErrorKind::InvalidState,
+            "The default project cannot be deleted".to_string(),
+        ));
+    }
+    let filter = doc! {"project": &project.name};
+    let flags = flag_repo.collection.count_documents(filter.clone(), None).await?;
+    let envs = env_repo.collection.count_documents(filter, None).await?;
+    if flags + envs > 0 {
+        return Err(ServiceError::new(
+            ErrorKind::InvalidState,
+            format!("Project {} still has flags or environments", project.name),
+        ));
+    }
+    match repo.delete(&id).await {
+        Ok(_) => Ok(project),
+        Err(e) => Err(ServiceError::from(e)),
+    }
+}
+
+/// Project names are part of the routes, only letters, digits, `_` and `-` are allowed
+fn validate_name(name: &str) -> Result<(), ServiceError> {
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(ServiceError::new(
+            ErrorKind::InvalidArgument,
+            format!("Invalid project name {}", name),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("checkout-team_2").is_ok());
+        assert_eq!(validate_name("").unwrap_err().kind, ErrorKind::InvalidArgument);
+        assert_eq!(validate_name("a/b").unwrap_err().kind, ErrorKind::InvalidArgument);
+        assert_eq!(validate_name("with space").unwrap_err().kind, ErrorKind::InvalidArgument);
+    }
+}
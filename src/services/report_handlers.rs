@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use feature_flags_storage::repositories::environment_repository::EnvironmentRepository;
+use feature_flags_storage::repositories::feature_flags_repository::FeatureFlagRepository;
+use feature_flags_storage::repositories::usage_repository::UsageRepository;
+use feature_flags_storage::repositories::ErrorKind;
+use feature_flags_core::models::{Environment, FeatureFlag, Lifecycle};
+use feature_flags_storage::domain::usage::FlagUsage;
+use crate::services::{environment_handlers, feature_flag_handlers, usage_handlers, ServiceError};
+use feature_flags_core::utils;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StaleReport {
+    /// Period without changes or evaluations after which a flag is stale
+    pub days: i64,
+    /// Temporary flags past their expiry date
+    pub expired: Vec<StaleFlag>,
+    /// Flags serving the same value in every environment, unchanged during the period
+    pub unchanged: Vec<StaleFlag>,
+    /// Flags not evaluated during the period
+    pub unused: Vec<StaleFlag>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StaleFlag {
+    pub name: String,
+    pub owner: Option<String>,
+    pub lifecycle: Lifecycle,
+    #[serde(with = "utils::date_format")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(with = "utils::optional_date_format", default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(with = "utils::optional_date_format", default)]
+    pub last_evaluated_at: Option<DateTime<Utc>>,
+    /// Value served everywhere, for unchanged flags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<bool>,
+}
+
+/// Temporary flags of the project that are candidates for removal, permanent flags are left out
+pub async fn stale(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    env_repo: &EnvironmentRepository<Environment>,
+    usage_repo: &UsageRepository<FlagUsage>,
+    project: &str,
+    days: i64,
+) -> Result<StaleReport, ServiceError> {
+    if days < 1 {
+        return Err(ServiceError::new(
+            ErrorKind::InvalidArgument,
+            "days must be at least 1".to_string(),
+        ));
+    }
+    let flags = feature_flag_handlers::find(repo, feature_flag_handlers::Filters {
+        project: Some(project.to_string()),
+        ..Default::default()
+    }).await?;
+    let envs = environment_handlers::find(env_repo, environment_handlers::Filters {
+        project: Some(project.to_string()),
+        ..Default::default()
+    }).await?;
+    let usage = usage_handlers::last_evaluations(usage_repo, project).await?;
+    Ok(stale_flags(&flags, &envs, &usage, Utc::now(), days))
+}
+
+pub fn stale_flags(
+    flags: &[FeatureFlag],
+    envs: &[Environment],
+    usage: &HashMap<String, DateTime<Utc>>,
+    now: DateTime<Utc>,
+    days: i64,
+) -> StaleReport {
+    let since = now - Duration::days(days);
+    let mut report = StaleReport { days, expired: vec![], unchanged: vec![], unused: vec![] };
+    for flag in flags.iter().filter(|flag| !flag.archived && flag.lifecycle.is_temporary()) {
+        let last_evaluated_at = usage.get(&flag.name).copied();
+        let stale_flag = |value| StaleFlag {
+            name: flag.name.clone(),
+            owner: flag.owner.clone(),
+            lifecycle: flag.lifecycle,
+            updated_at: flag.updated_at,
+            expires_at: flag.expires_at,
+            last_evaluated_at,
+            value,
+        };
+        if flag.expires_at.is_some_and(|expires_at| expires_at < now) {
+            report.expired.push(stale_flag(None));
+        }
+        let overriding: Vec<&Environment> = envs
+            .iter()
+            .filter(|env| env.get_override(&flag.name).is_some())
+            .collect();
+        let changed = flag.updated_at >= since || overriding.iter().any(|env| env.updated_at >= since);
+        if let (false, Some(value)) = (changed, constant_value(flag, &overriding)) {
+            report.unchanged.push(stale_flag(Some(value)));
+        }
+        if flag.created_at < since && last_evaluated_at.is_none_or(|evaluated_at| evaluated_at < since) {
+            report.unused.push(stale_flag(None));
+        }
+    }
+    report
+}
+
+/// Value served to every context in every environment, if there is one
+fn constant_value(flag: &FeatureFlag, envs: &[&Environment]) -> Option<bool> {
+    let value = |flag: &FeatureFlag| match (flag.enabled, flag.rules.is_empty()) {
+        (false, _) => Some(false),
+        (true, true) => Some(true),
+        (true, false) => None,
+    };
+    let global = value(flag)?;
+    for env in envs {
+        let flag_override = env.get_override(&flag.name)?;
+        if value(&flag_override.apply(flag))? != global {
+            return None;
+        }
+    }
+    Some(global)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feature_flags_core::evaluation::{Operator, Rule};
+    use feature_flags_core::models::FlagOverride;
+
+    #[test]
+    fn test_stale_flags() {
+        let now = Utc::now();
+        let old = now - Duration::days(60);
+        let flag = |name: &str, enabled: bool| {
+            let mut flag = FeatureFlag::new(name, name, enabled, vec![]);
+            flag.created_at = old;
+            flag.updated_at = old;
+            flag
+        };
+        let mut expired = flag("expired", true);
+        expired.expires_at = Some(now - Duration::days(1));
+        expired.updated_at = now;
+        let rolled_out = flag("rolled_out", true);
+        let mut permanent = flag("permanent", true);
+        permanent.lifecycle = Lifecycle::Permanent;
+        let mut partial = flag("partial", true);
+        partial.rules = vec![Rule { parameter: "tenant".to_string(), operator: Operator::Is("tenant_1".to_string()) }];
+        let overridden = flag("overridden", true);
+        let mut env = Environment::new("production");
+        env.updated_at = old;
+        env.set_override(FlagOverride { enabled: Some(false), ..FlagOverride::new("overridden") });
+
+        let flags = vec![expired, rolled_out, permanent, partial, overridden];
+        let usage = HashMap::from([
+            ("rolled_out".to_string(), now),
+            ("partial".to_string(), now - Duration::days(40)),
+        ]);
+        let report = stale_flags(&flags, &[env], &usage, now, 30);
+        let names = |flags: &[StaleFlag]| flags.iter().map(|flag| flag.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&report.expired), vec!["expired"]);
+        assert_eq!(names(&report.unchanged), vec!["rolled_out"]);
+        assert_eq!(report.unchanged[0].value, Some(true));
+        assert_eq!(names(&report.unused), vec!["expired", "partial", "overridden"]);
+    }
+}
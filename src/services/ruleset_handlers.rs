@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+use mongodb::bson::doc;
+use feature_flags_storage::repositories::environment_repository::EnvironmentRepository;
+use feature_flags_storage::repositories::feature_flags_repository::FeatureFlagRepository;
+use feature_flags_storage::repositories::revision_counter_repository::RevisionCounterRepository;
+use feature_flags_storage::repositories::BaseRepository;
+use feature_flags_core::models::{Environment, FeatureFlag};
+use feature_flags_core::ruleset::Ruleset;
+use feature_flags_storage::domain::sync::{ChangedEntity, RevisionCounter};
+use crate::services::{environment_handlers, ServiceError};
+
+/// Definitions of the environment, only the changes since the given revision when they
+/// are still known. Read from the database, the revision is read first so the definitions
+/// are never older than it.
+pub async fn get(
+    repo: &FeatureFlagRepository<FeatureFlag>,
+    env_repo: &EnvironmentRepository<Environment>,
+    counter_repo: &RevisionCounterRepository<RevisionCounter>,
+    project: &str,
+    environment_name: &str,
+    since: Option<i64>,
+) -> Result<Ruleset, ServiceError> {
+    let counter = counter_repo.get(project).await?.unwrap_or_else(|| RevisionCounter {
+        project: project.to_string(),
+        revision: 0,
+        changes: vec![],
+    });
+    let changes = since
+        .and_then(|since| counter.changes_since(since))
+        .filter(|changes| !changes.contains(&&ChangedEntity::Project));
+    let env = environment_handlers::get_by_name(env_repo, project, environment_name).await?;
+    let changes = match changes {
+        Some(changes) => changes,
+        None => {
+            let flags = repo.find(doc! {"project": project, "archived": {"$ne": true}}).await?;
+            return Ok(Ruleset {
+                revision: counter.revision,
+                full: true,
+                environment: Some(env),
+                flags,
+                removed: vec![],
+            });
+        }
+    };
+
+    let mut names = HashSet::new();
+    let mut env_changed = false;
+    for change in changes {
+        match change {
+            ChangedEntity::Flag(name) => {
+                names.insert(name.as_str());
+            }
+            ChangedEntity::Environment(name) => env_changed |= name == environment_name,
+            ChangedEntity::Project => {}
+        }
+    }
+    let flags = match names.is_empty() {
+        true => vec![],
+        false => {
+            let names = Vec::from_iter(names.iter().copied());
+            repo.find(doc! {"project": project, "name": {"$in": names}, "archived": {"$ne": true}}).await?
+        }
+    };
+    let mut removed: Vec<String> = names
+        .into_iter()
+        .filter(|name| !flags.iter().any(|flag| flag.name == *name))
+        .map(String::from)
+        .collect();
+    removed.sort();
+    Ok(Ruleset {
+        revision: counter.revision,
+        full: false,
+        environment: env_changed.then_some(env),
+        flags,
+        removed,
+    })
+}
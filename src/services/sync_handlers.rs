@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use futures::TryStreamExt;
+use feature_flags_storage::repositories::revision_counter_repository::RevisionCounterRepository;
+use feature_flags_storage::domain::sync::{ChangedEntity, RevisionCounter};
+use crate::services::ServiceError;
+use crate::snapshot::Snapshots;
+
+/// Tells the other replicas the project changed, called after invalidating the entries
+/// the write changed
+pub async fn bump(
+    repo: &RevisionCounterRepository<RevisionCounter>,
+    snapshots: &Snapshots,
+    project: &str,
+    changes: &[ChangedEntity],
+) -> Result<(), ServiceError> {
+    let revision = repo.bump(project, changes).await?;
+    snapshots.observe(project, revision);
+    Ok(())
+}
+
+/// Drops the entries of the projects changed by other replicas
+pub async fn sync(
+    repo: &RevisionCounterRepository<RevisionCounter>,
+    snapshots: &Snapshots,
+) -> Result<(), ServiceError> {
+    let counters = repo.revisions().await?;
+    let revisions: HashMap<String, i64> = counters
+        .into_iter()
+        .map(|counter| (counter.project, counter.revision))
+        .collect();
+    snapshots.sync(&revisions);
+    Ok(())
+}
+
+/// Syncs on every change of the revisions. Fails right away when change streams are not
+/// supported, they require a replica set.
+pub async fn watch(
+    repo: &RevisionCounterRepository<RevisionCounter>,
+    snapshots: &Snapshots,
+) -> Result<(), ServiceError> {
+    let mut changes = repo.collection.watch(None, None).await?;
+    while changes.try_next().await?.is_some() {
+        sync(repo, snapshots).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use mongodb::bson::doc;
+    use feature_flags_storage::repositories::revision_counter_repository::revision_counter_repository_factory;
+    use feature_flags_storage::database::init_db;
+
+    #[actix_web::test]
+    async fn test_sync_replicas() {
+        let db = init_db().await.unwrap();
+        let repo = revision_counter_repository_factory(&db).await;
+        let project = "sync_test_project";
+        let (replica_1, replica_2) = (Snapshots::default(), Snapshots::default());
+        for replica in [&replica_1, &replica_2] {
+            replica.publish_flags(replica.generation(), project, Arc::new(vec![]));
+            sync(&repo, replica).await.unwrap();
+        }
+
+        // Write on the first replica, the second one drops its entries on the next sync
+        replica_1.invalidate_flags(project);
+        bump(&repo, &replica_1, project, &[ChangedEntity::Project]).await.unwrap();
+        replica_1.publish_flags(replica_1.generation(), project, Arc::new(vec![]));
+        assert!(replica_2.flags(project).is_some());
+        for replica in [&replica_1, &replica_2] {
+            sync(&repo, replica).await.unwrap();
+        }
+        assert!(replica_1.flags(project).is_some());
+        assert!(replica_2.flags(project).is_none());
+        repo.collection.delete_one(doc! {"_id": project}, None).await.unwrap();
+    }
+}
@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::bson::doc;
+use feature_flags_storage::repositories::usage_repository::UsageRepository;
+use feature_flags_storage::domain::usage::FlagUsage;
+use crate::services::ServiceError;
+
+/// Stores evaluation counts, by project and flag name, as evaluated now
+pub async fn record(
+    repo: &UsageRepository<FlagUsage>,
+    counts: &HashMap<(String, String), i64>,
+) -> Result<(), ServiceError> {
+    let now = Utc::now();
+    for ((project, name), count) in counts {
+        repo.increment(project, name, *count, &now).await?;
+    }
+    Ok(())
+}
+
+/// Last evaluation of the project flags, by flag name
+pub async fn last_evaluations(
+    repo: &UsageRepository<FlagUsage>,
+    project: &str,
+) -> Result<HashMap<String, DateTime<Utc>>, ServiceError> {
+    let usages: Vec<FlagUsage> = repo.collection.find(doc! {"project": project}, None).await?.try_collect().await?;
+    Ok(usages.into_iter().map(|usage| (usage.name, usage.last_evaluated_at)).collect())
+}
@@ -0,0 +1,474 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use feature_flags_core::models::{Environment, FeatureFlag};
+
+/// Age after which cached entries are reloaded, unless `CACHE_TTL_SECONDS` is set
+pub const DEFAULT_CACHE_TTL_SECONDS: u64 = 30;
+
+/// Longest time writes of other replicas may go unnoticed, unless `CACHE_MAX_STALENESS_SECONDS` is set
+pub const DEFAULT_MAX_STALENESS_SECONDS: u64 = 10;
+
+/// Flags and environments used for evaluation, never modified once published
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    /// Active flags, by project
+    pub flags: HashMap<String, Cached<Vec<FeatureFlag>>>,
+    /// Active environments, by project and name. They only hold overrides, evaluated
+    /// against the current flags, so flag writes leave them valid.
+    pub envs: HashMap<(String, String), Cached<Environment>>,
+    /// Revisions of the projects the entries are up to date with, see `Snapshots::sync`
+    pub revisions: HashMap<String, i64>,
+    /// Last time the revisions were read
+    pub synced_at: Option<Instant>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cached<T> {
+    pub value: Arc<T>,
+    pub loaded_at: Instant,
+}
+
+impl<T> Cached<T> {
+    fn new(value: Arc<T>) -> Self {
+        Cached { value, loaded_at: Instant::now() }
+    }
+}
+
+/// Current evaluation snapshot. Reads never block, loads and invalidations publish a new
+/// snapshot replacing the current one. Writes made by this process invalidate the entries
+/// they change, writes of other replicas are noticed through the project revisions and
+/// other entries are reloaded once older than the TTL.
+pub struct Snapshots {
+    current: ArcSwap<Snapshot>,
+    /// Bumped by every invalidation, loads started before it are not published
+    generation: AtomicU64,
+    ttl: Duration,
+    /// Entries are not served when the revisions were not read for longer
+    max_staleness: Option<Duration>,
+    started_at: Instant,
+    counters: Counters,
+    /// Incremented whenever a snapshot is published, see `subscribe`
+    changes: watch::Sender<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stale_hits: AtomicU64,
+    refreshes: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+/// Cache statistics since the process started
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheMetrics {
+    pub hits: u64,
+    pub misses: u64,
+    /// Hits served by entries older than the TTL, their refresh is pending or failing
+    pub stale_hits: u64,
+    pub refreshes: u64,
+    pub invalidations: u64,
+    pub entries: usize,
+    pub ttl_seconds: u64,
+    /// Age of the oldest entry
+    pub max_age_seconds: u64,
+    pub max_staleness_seconds: Option<u64>,
+    /// Time since the revisions were last read
+    pub sync_age_seconds: u64,
+}
+
+impl Default for Snapshots {
+    fn default() -> Self {
+        Snapshots::new(Duration::from_secs(DEFAULT_CACHE_TTL_SECONDS), None)
+    }
+}
+
+impl Snapshots {
+    pub fn new(ttl: Duration, max_staleness: Option<Duration>) -> Self {
+        Snapshots {
+            current: ArcSwap::default(),
+            generation: AtomicU64::new(0),
+            ttl,
+            max_staleness,
+            started_at: Instant::now(),
+            counters: Counters::default(),
+            changes: watch::channel(0).0,
+        }
+    }
+
+    /// Notified after entries are loaded or invalidated, the entries may be unchanged
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn load(&self) -> Arc<Snapshot> {
+        self.current.load_full()
+    }
+
+    /// Cached flags of the project, recorded as a hit or a miss
+    pub fn flags(&self, project: &str) -> Option<Arc<Vec<FeatureFlag>>> {
+        let snapshot = self.load();
+        self.record(&snapshot, snapshot.flags.get(project))
+    }
+
+    /// Cached environment, recorded as a hit or a miss
+    pub fn env(&self, project: &str, name: &str) -> Option<Arc<Environment>> {
+        let snapshot = self.load();
+        self.record(&snapshot, snapshot.envs.get(&(project.to_string(), name.to_string())))
+    }
+
+    fn record<T>(&self, snapshot: &Snapshot, entry: Option<&Cached<T>>) -> Option<Arc<T>> {
+        // Writes of other replicas may have been missed, entries are loaded again
+        let unsynced = self.max_staleness.is_some_and(|bound| self.sync_age(snapshot) > bound);
+        match entry {
+            Some(entry) if !unsynced => {
+                self.counters.hits.fetch_add(1, Ordering::Relaxed);
+                if entry.loaded_at.elapsed() > self.ttl {
+                    self.counters.stale_hits.fetch_add(1, Ordering::Relaxed);
+                }
+                Some(Arc::clone(&entry.value))
+            }
+            _ => {
+                self.counters.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Generation to pass to `publish_flags` and `publish_env`, taken before loading
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn publish_flags(&self, generation: u64, project: &str, flags: Arc<Vec<FeatureFlag>>) {
+        self.publish(generation, |snapshot| {
+            snapshot.flags.insert(project.to_string(), Cached::new(Arc::clone(&flags)));
+        });
+    }
+
+    pub fn publish_env(&self, generation: u64, env: Arc<Environment>) {
+        self.publish(generation, |snapshot| {
+            snapshot.envs.insert((env.project.clone(), env.name.clone()), Cached::new(Arc::clone(&env)));
+        });
+    }
+
+    pub fn record_refresh(&self) {
+        self.counters.refreshes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Projects and environments whose entries are older than the TTL
+    pub fn expired(&self) -> (Vec<String>, Vec<(String, String)>) {
+        let snapshot = self.load();
+        let flags = snapshot.flags.iter()
+            .filter(|(_, entry)| entry.loaded_at.elapsed() >= self.ttl)
+            .map(|(project, _)| project.clone())
+            .collect();
+        let envs = snapshot.envs.iter()
+            .filter(|(_, entry)| entry.loaded_at.elapsed() >= self.ttl)
+            .map(|(key, _)| key.clone())
+            .collect();
+        (flags, envs)
+    }
+
+    pub fn invalidate_flags(&self, project: &str) {
+        self.invalidate(|snapshot| {
+            snapshot.flags.remove(project);
+        });
+    }
+
+    pub fn invalidate_env(&self, project: &str, name: &str) {
+        self.invalidate(|snapshot| {
+            snapshot.envs.remove(&(project.to_string(), name.to_string()));
+        });
+    }
+
+    /// Drops the flags and environments of the project
+    pub fn invalidate_project(&self, project: &str) {
+        self.invalidate(|snapshot| {
+            snapshot.flags.remove(project);
+            snapshot.envs.retain(|(env_project, _), _| env_project != project);
+        });
+    }
+
+    /// Drops the entries of the projects whose revision moved since the last sync, the
+    /// revisions are read after the writes they count
+    pub fn sync(&self, revisions: &HashMap<String, i64>) {
+        let moved = |snapshot: &Snapshot, project: &String, revision: &i64| {
+            snapshot.revisions.get(project).copied().unwrap_or(0) < *revision
+        };
+        let current = self.load();
+        let changed = revisions.iter().any(|(project, revision)| moved(&current, project, revision));
+        if changed {
+            self.generation.fetch_add(1, Ordering::SeqCst);
+            self.counters.invalidations.fetch_add(1, Ordering::Relaxed);
+        }
+        self.current.rcu(|current| {
+            let mut snapshot = Snapshot::clone(current);
+            for (project, revision) in revisions {
+                if moved(&snapshot, project, revision) {
+                    snapshot.flags.remove(project);
+                    snapshot.envs.retain(|(env_project, _), _| env_project != project);
+                    snapshot.revisions.insert(project.clone(), *revision);
+                }
+            }
+            snapshot.synced_at = Some(Instant::now());
+            snapshot
+        });
+        if changed {
+            self.notify();
+        }
+    }
+
+    /// Records the revision of a write made by this process, once its entries are invalidated.
+    /// Revisions skipped meanwhile belong to other replicas, they are left to `sync`.
+    pub fn observe(&self, project: &str, revision: i64) {
+        self.current.rcu(|current| {
+            let known = current.revisions.get(project).copied().unwrap_or(0);
+            if known != revision - 1 {
+                return Arc::clone(current);
+            }
+            let mut snapshot = Snapshot::clone(current);
+            snapshot.revisions.insert(project.to_string(), revision);
+            Arc::new(snapshot)
+        });
+    }
+
+    fn sync_age(&self, snapshot: &Snapshot) -> Duration {
+        snapshot.synced_at.unwrap_or(self.started_at).elapsed()
+    }
+
+    pub fn metrics(&self) -> CacheMetrics {
+        let snapshot = self.load();
+        let ages = snapshot.flags.values().map(|entry| entry.loaded_at)
+            .chain(snapshot.envs.values().map(|entry| entry.loaded_at))
+            .map(|loaded_at| loaded_at.elapsed().as_secs());
+        CacheMetrics {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            stale_hits: self.counters.stale_hits.load(Ordering::Relaxed),
+            refreshes: self.counters.refreshes.load(Ordering::Relaxed),
+            invalidations: self.counters.invalidations.load(Ordering::Relaxed),
+            entries: snapshot.flags.len() + snapshot.envs.len(),
+            ttl_seconds: self.ttl.as_secs(),
+            max_age_seconds: ages.max().unwrap_or(0),
+            max_staleness_seconds: self.max_staleness.map(|bound| bound.as_secs()),
+            sync_age_seconds: self.sync_age(&snapshot).as_secs(),
+        }
+    }
+
+    fn publish(&self, generation: u64, changes: impl Fn(&mut Snapshot)) {
+        self.current.rcu(|current| {
+            // Invalidated while loading, the loaded data may be stale
+            if self.generation() != generation {
+                return Arc::clone(current);
+            }
+            let mut snapshot = Snapshot::clone(current);
+            changes(&mut snapshot);
+            Arc::new(snapshot)
+        });
+        self.notify();
+    }
+
+    fn invalidate(&self, changes: impl Fn(&mut Snapshot)) {
+        // Bumped first so loads racing with the invalidation are discarded
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.counters.invalidations.fetch_add(1, Ordering::Relaxed);
+        self.current.rcu(|current| {
+            let mut snapshot = Snapshot::clone(current);
+            changes(&mut snapshot);
+            snapshot
+        });
+        self.notify();
+    }
+
+    fn notify(&self) {
+        self.changes.send_modify(|count| *count += 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_and_invalidate() {
+        let snapshots = Snapshots::default();
+        let flags = Arc::new(vec![FeatureFlag::new("sample_flag", "Sample Flag", true, vec![])]);
+        let generation = snapshots.generation();
+        snapshots.publish_flags(generation, "default", Arc::clone(&flags));
+        snapshots.publish_env(generation, Arc::new(Environment::new("production")));
+        let snapshot = snapshots.load();
+        assert_eq!(snapshots.flags("default").unwrap().len(), 1);
+        assert!(snapshots.env("default", "production").is_some());
+
+        // Readers keep the snapshot they loaded
+        snapshots.invalidate_flags("default");
+        assert!(snapshots.flags("default").is_none());
+        assert_eq!(snapshot.flags["default"].value.len(), 1);
+
+        // Loads started before an invalidation are discarded
+        let generation = snapshots.generation();
+        snapshots.invalidate_env("default", "production");
+        snapshots.publish_flags(generation, "default", flags);
+        let snapshot = snapshots.load();
+        assert!(snapshot.flags.is_empty());
+        assert!(snapshot.envs.is_empty());
+
+        let metrics = snapshots.metrics();
+        assert_eq!((metrics.hits, metrics.misses, metrics.invalidations), (2, 1, 2));
+    }
+
+    #[test]
+    fn test_expired_entries() {
+        let snapshots = Snapshots::new(Duration::ZERO, None);
+        let generation = snapshots.generation();
+        snapshots.publish_flags(generation, "default", Arc::new(vec![]));
+        snapshots.publish_env(generation, Arc::new(Environment::new("production")));
+        std::thread::sleep(Duration::from_millis(1));
+        let (flags, envs) = snapshots.expired();
+        assert_eq!(flags, vec!["default"]);
+        assert_eq!(envs, vec![("default".to_string(), "production".to_string())]);
+
+        // Expired entries are still served until they are refreshed
+        assert!(snapshots.flags("default").is_some());
+        assert_eq!(snapshots.metrics().stale_hits, 1);
+        assert!(Snapshots::default().expired().0.is_empty());
+    }
+
+    #[test]
+    fn test_sync_revisions() {
+        let snapshots = Snapshots::default();
+        let publish = |project: &str| {
+            snapshots.publish_flags(snapshots.generation(), project, Arc::new(vec![]));
+        };
+        publish("default");
+        publish("checkout");
+
+        // Other replicas wrote in the checkout project
+        snapshots.sync(&HashMap::from([("default".to_string(), 0), ("checkout".to_string(), 2)]));
+        assert!(snapshots.flags("default").is_some());
+        assert!(snapshots.flags("checkout").is_none());
+
+        // Own writes are not reloaded twice, unless other replicas wrote meanwhile
+        publish("checkout");
+        snapshots.observe("checkout", 3);
+        snapshots.observe("default", 2);
+        snapshots.sync(&HashMap::from([("default".to_string(), 2), ("checkout".to_string(), 3)]));
+        assert!(snapshots.flags("checkout").is_some());
+        assert!(snapshots.flags("default").is_none());
+        assert_eq!(snapshots.load().revisions["default"], 2);
+    }
+
+    #[test]
+    fn test_max_staleness() {
+        let snapshots = Snapshots::new(Duration::from_secs(DEFAULT_CACHE_TTL_SECONDS), Some(Duration::ZERO));
+        snapshots.publish_flags(snapshots.generation(), "default", Arc::new(vec![]));
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(snapshots.flags("default").is_none());
+        assert_eq!(snapshots.metrics().max_staleness_seconds, Some(0));
+    }
+
+    /// Load test of the evaluation path with concurrent readers while a writer keeps publishing
+    /// new snapshots, compared with the previous `Mutex` guarded cache where the lock is held for
+    /// the whole evaluation. Run with
+    /// `cargo test --release bench_concurrent_evaluation -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_concurrent_evaluation() {
+        use std::sync::atomic::AtomicBool;
+        use std::sync::Mutex;
+        use std::time::{Duration, Instant};
+        use serde_json::{Map, Value};
+        use feature_flags_core::evaluation::{Operator, Rule};
+        use feature_flags_core::models::FlagOverride;
+
+        const DURATION: Duration = Duration::from_millis(500);
+        let flags: Vec<FeatureFlag> = (0..200)
+            .map(|i| {
+                let rules = vec![Rule {
+                    parameter: "tenant".to_string(),
+                    operator: Operator::IsOneOf((0..10).map(|t| format!("tenant_{}", t)).collect()),
+                }];
+                FeatureFlag::new(&format!("flag_{}", i), "", true, rules)
+            })
+            .collect();
+        let mut env = Environment::new("production");
+        for i in 0..20 {
+            env.set_override(FlagOverride { enabled: Some(false), ..FlagOverride::new(&format!("flag_{}", i)) });
+        }
+        let context: Map<String, Value> = Map::from_iter([("tenant".to_string(), Value::from("tenant_3"))]);
+        let key = ("default".to_string(), "production".to_string());
+
+        let snapshots = Snapshots::default();
+        let publish = || {
+            let generation = snapshots.generation();
+            snapshots.publish_flags(generation, "default", Arc::new(flags.clone()));
+            snapshots.publish_env(generation, Arc::new(env.clone()));
+        };
+        let mutex = Mutex::new((flags.clone(), env.clone()));
+
+        // Runs the readers for a while with a writer publishing every millisecond, returns requests per second
+        let run = |threads: usize, read: &(dyn Fn() -> usize + Sync), write: &(dyn Fn() + Sync)| {
+            let stop = AtomicBool::new(false);
+            let total = std::thread::scope(|scope| {
+                let readers: Vec<_> = (0..threads)
+                    .map(|_| scope.spawn(|| {
+                        let mut count = 0_u64;
+                        while !stop.load(Ordering::Relaxed) {
+                            assert!(read() > 0);
+                            count += 1;
+                        }
+                        count
+                    }))
+                    .collect();
+                scope.spawn(|| {
+                    while !stop.load(Ordering::Relaxed) {
+                        write();
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                });
+                let start = Instant::now();
+                std::thread::sleep(DURATION);
+                stop.store(true, Ordering::Relaxed);
+                let total: u64 = readers.into_iter().map(|reader| reader.join().unwrap()).sum();
+                total as f64 / start.elapsed().as_secs_f64()
+            });
+            total as u64
+        };
+
+        publish();
+        println!("{:>8} {:>16} {:>16}", "threads", "snapshot req/s", "mutex req/s");
+        for threads in [1, 2, 4, 8] {
+            let snapshot_rate = run(
+                threads,
+                &|| {
+                    let snapshot = snapshots.load();
+                    snapshot.envs[&key].value.get_flags_from_context(&snapshot.flags["default"].value, &context).len()
+                },
+                &publish,
+            );
+            let mutex_rate = run(
+                threads,
+                &|| {
+                    let cache = mutex.lock().unwrap();
+                    cache.1.get_flags_from_context(&cache.0, &context).len()
+                },
+                &|| {
+                    let mut cache = mutex.lock().unwrap();
+                    *cache = (flags.clone(), env.clone());
+                },
+            );
+            println!("{:>8} {:>16} {:>16}", threads, snapshot_rate, mutex_rate);
+        }
+    }
+}
@@ -1,52 +1,86 @@
-pub mod date_format {
-    use chrono::{DateTime, Utc, TimeZone};
-    use serde::{self, Deserialize, Serializer, Deserializer};
-
-    const FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
-    pub fn serialize<S>(
-        date: &DateTime<Utc>,
-        serializer: S,
-    ) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let s = format!("{}", date.format(FORMAT));
-        serializer.serialize_str(&s)
+use std::hash::{Hash, Hasher};
+use serde_json::Value;
+
+/// Escapes the characters with a special meaning in a MongoDB `$regex` pattern
+pub fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
     }
+    escaped
+}
 
-    pub fn deserialize<'de, D>(
-        deserializer: D,
-    ) -> Result<DateTime<Utc>, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let s = String::deserialize(deserializer)?;
-        Utc.datetime_from_str(&s, FORMAT).map_err(serde::de::Error::custom)
+/// FNV-1a hasher, unlike `DefaultHasher` its output does not change between Rust releases
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
     }
 }
 
-pub mod mongo_id_format {
-    use mongodb::bson::oid::ObjectId;
-    use serde::{Deserialize, Deserializer, Serializer};
-
-    pub fn serialize<S>(
-        id: &Option<ObjectId>,
-        serializer: S,
-    ) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let s = format!("{}", id.unwrap().to_hex().to_string());
-        serializer.serialize_str(&s)
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
     }
 
-    pub fn deserialize<'de, D>(
-        deserializer: D,
-    ) -> Result<Option<ObjectId>, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let s = String::deserialize(deserializer)?;
-        Ok(Option::from(ObjectId::parse_str(&s).unwrap()))
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
     }
-}
\ No newline at end of file
+}
+
+/// Hashes a JSON value regardless of the order of the object keys
+pub fn hash_json<H: Hasher>(value: &Value, state: &mut H) {
+    match value {
+        Value::Null => 0_u8.hash(state),
+        Value::Bool(value) => (1_u8, value).hash(state),
+        Value::Number(value) => (2_u8, value.to_string()).hash(state),
+        Value::String(value) => (3_u8, value).hash(state),
+        Value::Array(items) => {
+            (4_u8, items.len()).hash(state);
+            for item in items {
+                hash_json(item, state);
+            }
+        }
+        Value::Object(map) => {
+            (5_u8, map.len()).hash(state);
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            for (key, value) in entries {
+                key.hash(state);
+                hash_json(value, state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_regex() {
+        assert_eq!(escape_regex("new_ui"), "new_ui");
+        assert_eq!(escape_regex("a.b*(c)"), "a\\.b\\*\\(c\\)");
+    }
+
+    #[test]
+    fn test_hash_json() {
+        let hash = |value: &Value| {
+            let mut hasher = StableHasher::default();
+            hash_json(value, &mut hasher);
+            hasher.finish()
+        };
+        let value = serde_json::json!({"tenant": "tenant_1", "user": {"id": 1, "roles": ["admin"]}});
+        let reordered = serde_json::json!({"user": {"roles": ["admin"], "id": 1}, "tenant": "tenant_1"});
+        assert_eq!(hash(&value), hash(&reordered));
+        assert_ne!(hash(&value), hash(&serde_json::json!({"tenant": "tenant_2"})));
+        assert_ne!(hash(&serde_json::json!(["a", "b"])), hash(&serde_json::json!(["ab"])));
+    }
+}